@@ -1,44 +1,56 @@
-use schnorrkel::{olaf::multisig::{aggregate, SigningPackage}, Signature};
-use tokio::{fs::{read_to_string, File}, io::AsyncWriteExt};
-use crate::{cli::CliError, files::FilePaths};
+use olaf_cli::{
+    extrinsic::SigningRequest,
+    messages::{Collection, MessageBoard},
+    protocol::{aggregate, AggregateInput},
+    session::{resolve_file_paths, SessionPurpose, NO_SESSION},
+    storage::{read_json, write_json, Artifact, Backend},
+};
+use crate::cli::CliError;
 
 /// Aggregates at least t partial signatures into one threshold signature
-pub async fn aggregate_threshold_signature(files: String) -> Result<(), CliError> {
-    let file_paths = FilePaths::new(files);
+pub async fn aggregate_threshold_signature(
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
 
-    let signing_packages_string = read_to_string(file_paths.signing_packages())
-        .await
-        .map_err(|e| CliError(format!("Failed to read signing packages file: {}", e)))?;
-    
-    let signing_packages_bytes: Vec<Vec<u8>> = serde_json::from_str(&signing_packages_string)
-        .map_err(|e| CliError(format!("Failed to deserialize signing packages: {}", e)))?;
-    
-    let signing_packages: Vec<SigningPackage> = signing_packages_bytes
-        .iter()
-        .map(|sp| SigningPackage::from_bytes(sp)
-            .map_err(|e| CliError(format!("Failed to parse SigningPackage: {:?}", e))))
-        .collect::<Result<_, _>>()?;
-    
-    let group_signature: Signature = aggregate(&signing_packages)
-        .map_err(|e| CliError(format!("Failed to aggregate threshold signature: {:?}", e)))?;
-    
-    let signature_json = serde_json::to_string(&group_signature.to_bytes().to_vec())
-        .map_err(|e| CliError(format!("Failed to serialize threshold signature: {}", e)))?;
-    
-    let mut signature_file = File::create(file_paths.threshold_signature())
-        .await
-        .map_err(|e| CliError(format!("Failed to create threshold signature file: {}", e)))?;
+    let storage = backend.open(&file_paths)?;
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+
+    let signing_packages_bytes = message_board.fetch(Collection::SigningPackages).await?;
     
-    signature_file
-        .write_all(signature_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write threshold signature to file: {}", e)))?;
+    let signing_request = if storage.get(Artifact::SigningRequest)?.is_some() {
+        Some(read_json::<SigningRequest>(&*storage, Artifact::SigningRequest)?)
+    } else {
+        None
+    };
+
+    // Without a recipients file, misbehaving signers are named by their position among the recipients
+    let recipients: Vec<String> = if storage.get(Artifact::Recipients)?.is_some() {
+        read_json(&*storage, Artifact::Recipients)?
+    } else {
+        Vec::new()
+    };
+
+    let output = aggregate(AggregateInput {
+        signing_packages: &signing_packages_bytes,
+        expected: signing_request
+            .as_ref()
+            .map(|request| (request.payload_hash.as_str(), request.context.as_str())),
+        recipients: &recipients,
+    })?;
+    let group_signature = output.signature;
+
+    write_json(&*storage, Artifact::ThresholdSignature, &group_signature.to_bytes().to_vec())?;
 
     println!("Aggregation of threshold signature was completed successfully!");
     println!(
-        "The threshold signature was written to: {:?}",
-        file_paths.threshold_signature()
+        "The threshold signature was written to: {}",
+        storage.location(Artifact::ThresholdSignature)
     );
 
     Ok(())
 }
+
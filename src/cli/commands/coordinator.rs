@@ -0,0 +1,7 @@
+use olaf_cli::coordinator::server;
+use crate::cli::CliError;
+
+/// Runs a relay through which participants exchange their round messages instead of local files
+pub async fn run_coordinator(listen: String) -> Result<(), CliError> {
+    server::run(&listen).await
+}
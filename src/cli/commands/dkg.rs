@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use schnorrkel::olaf::simplpedpop::{AllMessage, SPPOutputMessage};
+use subxt::utils::AccountId32;
+use olaf_cli::{
+    files::FilePaths,
+    inspect::{AllMessageView, SPPOutputView},
+    messages::{Collection, MessageBoard},
+    participant::Participant,
+    protocol::{dkg_certificate, parse_recipients, recipients_hash, AllMessageBundle, DkgCertificate, GenerationOutputBundle},
+    session::{read_manifest, resolve_file_paths, SessionPurpose, NO_SESSION},
+    storage::{read_json, write_json, Artifact, Backend},
+};
+use crate::cli::{commands::read_threshold_public_key, CliError};
+
+/// Reports how far the DKG of the directory or session has come: who contributed to round 1
+/// against the expected recipients, which round 2 outputs are present, the threshold and the
+/// threshold public key. Fails with a protocol error if the messages or outputs do not agree.
+pub async fn dkg_status(files: String, backend: &Backend, session: Option<String>, coordinator: Option<String>) -> Result<(), CliError> {
+    let (file_paths, manifest_threshold) = match &session {
+        Some(session) => {
+            let manifest = read_manifest(&files, backend, session).await?;
+
+            if manifest.purpose != SessionPurpose::Dkg {
+                return Err(CliError::input(format!(
+                    "Session {} is a {} session: pass the DKG session it uses instead",
+                    session, manifest.purpose
+                )));
+            }
+
+            (manifest.file_paths(&files), Some(manifest.threshold))
+        }
+        None => (FilePaths::new(&files), None),
+    };
+    let storage = backend.open(&file_paths)?;
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+    let public_keys = parse_recipients(&recipients)?;
+    let participants: Vec<Participant> = public_keys
+        .iter()
+        .enumerate()
+        .map(|(position, public_key)| Participant {
+            index: position as u16 + 1,
+            account: public_key.to_bytes(),
+        })
+        .collect();
+    let expected_hash = recipients_hash(&public_keys);
+
+    let name = |account: &[u8; 32]| match participants.iter().find(|participant| participant.account == *account) {
+        Some(participant) => participant.to_string(),
+        None => format!("{}, who is not a recipient", AccountId32(*account)),
+    };
+
+    let mut issues = Vec::new();
+
+    let mut contributions: HashMap<[u8; 32], usize> = HashMap::new();
+    let mut message_thresholds = Vec::new();
+
+    for (position, entry) in message_board.entries(Collection::AllMessages).await?.iter().enumerate() {
+        let bundle = AllMessageBundle::from_bytes(&entry.message);
+
+        if let Err(e) = AllMessage::from_bytes(&bundle.all_message) {
+            issues.push(format!("Round 1 message {} from {} cannot be parsed: {:?}", position + 1, entry.sender_name(), e));
+            continue;
+        }
+
+        let view = match AllMessageView::parse(&bundle.all_message) {
+            Ok(view) => view,
+            Err(e) => {
+                issues.push(format!("Round 1 message {} from {} cannot be parsed: {}", position + 1, entry.sender_name(), e.message()));
+                continue;
+            }
+        };
+
+        if bundle.sender.is_some_and(|sender| sender != view.sender) {
+            issues.push(format!(
+                "The round 1 message of {} was sent as the message of {}",
+                name(&view.sender),
+                name(&bundle.sender.expect("checked to be some")),
+            ));
+        }
+
+        match bundle.recipients_hash {
+            Some(hash) if hash != expected_hash => {
+                issues.push(format!("The round 1 message of {} was created for a different list of recipients", name(&view.sender)));
+            }
+            Some(_) => {}
+            None => issues.push(format!(
+                "The round 1 message of {} was sent by an older version and is not bound to the recipients: round 2 needs --allow-legacy to accept it",
+                name(&view.sender),
+            )),
+        }
+
+        if view.participants as usize != recipients.len() {
+            issues.push(format!(
+                "The round 1 message of {} was created for {} participants, but there are {} recipients",
+                name(&view.sender),
+                view.participants,
+                recipients.len(),
+            ));
+        }
+
+        message_thresholds.push((view.sender, view.threshold));
+        *contributions.entry(view.sender).or_default() += 1;
+    }
+
+    let mut outputs = Vec::new();
+
+    // The outputs the participants published, then the ones kept in the storage for participants
+    // that published none
+    for entry in message_board.entries(Collection::GenerationOutputs).await? {
+        let bundle = match GenerationOutputBundle::from_bytes(&entry.message) {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                issues.push(format!("The round 2 output from {} cannot be parsed: {}", entry.sender_name(), e.message()));
+                continue;
+            }
+        };
+
+        let Some(participant) = participants.iter().find(|participant| participant.account == bundle.sender) else {
+            issues.push(format!("{} is not a recipient, but published a round 2 output", AccountId32(bundle.sender)));
+            continue;
+        };
+
+        match output_view(&bundle.generation_output) {
+            Ok(view) => outputs.push((*participant, view)),
+            Err(e) => issues.push(format!("The round 2 output of {} cannot be parsed: {}", participant, e.message())),
+        }
+    }
+
+    for participant in &participants {
+        let artifact = Artifact::GenerationOutput(*participant);
+        if outputs.iter().any(|(output_participant, _)| output_participant == participant) || storage.get(artifact)?.is_none() {
+            continue;
+        }
+
+        let output_bytes: Vec<u8> = read_json(&*storage, artifact)?;
+
+        match output_view(&output_bytes) {
+            Ok(view) => outputs.push((*participant, view)),
+            Err(e) => issues.push(format!("The round 2 output of {} cannot be parsed: {}", participant, e.message())),
+        }
+    }
+
+    let threshold = manifest_threshold
+        .or_else(|| most_common(message_thresholds.iter().map(|(_, threshold)| *threshold)))
+        .or_else(|| most_common(outputs.iter().map(|(_, view)| view.threshold)));
+
+    for (sender, message_threshold) in &message_thresholds {
+        if threshold.is_some_and(|threshold| threshold != *message_threshold) {
+            issues.push(format!("The round 1 message of {} uses a threshold of {}", name(sender), message_threshold));
+        }
+    }
+
+    for (account, count) in &contributions {
+        if !participants.iter().any(|participant| participant.account == *account) {
+            issues.push(format!("{} is not a recipient, but contributed a round 1 message", AccountId32(*account)));
+        }
+        if *count > 1 {
+            issues.push(format!("{} contributed {} round 1 messages", name(account), count));
+        }
+    }
+
+    println!("Recipients: {}", recipients.len());
+    match threshold {
+        Some(threshold) => println!("Threshold: {}", threshold),
+        None => println!("Threshold: unknown until a round 1 message is published"),
+    }
+
+    let contributed = participants
+        .iter()
+        .filter(|participant| contributions.contains_key(&participant.account))
+        .count();
+
+    println!("Round 1: {} of {} recipients contributed", contributed, participants.len());
+    for participant in &participants {
+        let state = if contributions.contains_key(&participant.account) { "contributed" } else { "missing" };
+        println!("  {}: {}", participant, state);
+    }
+
+    println!("Round 2: {} of {} outputs found", outputs.len(), participants.len());
+    for participant in &participants {
+        match outputs.iter().find(|(output_participant, _)| output_participant == participant) {
+            Some((_, view)) => println!("  {}: threshold public key {}", participant, AccountId32(view.threshold_public_key)),
+            None => println!("  {}: no output", participant),
+        }
+    }
+
+    for (participant, view) in &outputs {
+        if threshold.is_some_and(|threshold| threshold != view.threshold) {
+            issues.push(format!("The round 2 output of {} uses a threshold of {}", participant, view.threshold));
+        }
+
+        if view.participants as usize != recipients.len() || view.verifying_shares.len() != recipients.len() {
+            issues.push(format!(
+                "The round 2 output of {} has {} verifying shares for {} participants, but there are {} recipients",
+                participant,
+                view.verifying_shares.len(),
+                view.participants,
+                recipients.len(),
+            ));
+        }
+    }
+
+    let group_key = match storage.get(Artifact::ThresholdPublicKey)? {
+        Some(_) => Some(read_threshold_public_key(&*storage)?),
+        None => most_common(outputs.iter().map(|(_, view)| view.threshold_public_key)).map(AccountId32),
+    };
+
+    match &group_key {
+        Some(group_key) => {
+            println!("Threshold public key: {}", group_key);
+
+            for (participant, view) in &outputs {
+                if AccountId32(view.threshold_public_key) != *group_key {
+                    issues.push(format!(
+                        "The round 2 output of {} has threshold public key {}",
+                        participant,
+                        AccountId32(view.threshold_public_key),
+                    ));
+                }
+            }
+        }
+        None => println!("Threshold public key: not generated yet"),
+    }
+
+    match storage.get(Artifact::DkgCertificate)? {
+        Some(_) => {
+            let certificate: DkgCertificate = read_json(&*storage, Artifact::DkgCertificate)?;
+
+            match certificate.verify() {
+                Ok(()) if certificate.recipients != recipients => {
+                    issues.push("The DKG certificate was issued for a different list of recipients".to_string());
+                }
+                Ok(()) if group_key.as_ref().is_some_and(|group_key| group_key.to_string() != certificate.threshold_public_key) => {
+                    issues.push(format!("The DKG certificate is for threshold public key {}", certificate.threshold_public_key));
+                }
+                Ok(()) => println!("Certificate: valid, signed by all {} recipients", certificate.signatures.len()),
+                Err(e) => issues.push(format!("The DKG certificate is invalid: {}", e.message())),
+            }
+        }
+        None => println!("Certificate: none yet, run `olaf-cli dkg certify` once every participant has completed round 2"),
+    }
+
+    if issues.is_empty() {
+        println!("No inconsistencies found");
+        return Ok(());
+    }
+
+    println!("Inconsistencies:");
+    for issue in &issues {
+        println!("  - {}", issue);
+    }
+
+    Err(CliError::protocol(format!("Found {} inconsistencies in the DKG", issues.len())))
+}
+
+/// Checks that every recipient completed round 2 with the same threshold, threshold public key
+/// and verifying shares, from the signed outputs they sent, and writes the DKG certificate
+pub async fn dkg_certify(files: String, backend: &Backend, session: Option<String>, coordinator: Option<String>) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Dkg).await?;
+    let storage = backend.open(&file_paths)?;
+
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+    let bundles = message_board.fetch(Collection::GenerationOutputs).await?;
+
+    let certificate = dkg_certificate(&bundles, &recipients)?;
+
+    if storage.get(Artifact::ThresholdPublicKey)?.is_some() {
+        let threshold_public_key = read_threshold_public_key(&*storage)?;
+
+        if threshold_public_key.to_string() != certificate.threshold_public_key {
+            return Err(CliError::protocol(format!(
+                "All participants agree on the Threshold Public Key {}, but {} holds {}",
+                certificate.threshold_public_key,
+                storage.location(Artifact::ThresholdPublicKey),
+                threshold_public_key,
+            )));
+        }
+    }
+
+    write_json(&*storage, Artifact::DkgCertificate, &certificate)?;
+
+    println!(
+        "All {} participants agree on the Threshold Public Key {} with a threshold of {}",
+        certificate.recipients.len(),
+        certificate.threshold_public_key,
+        certificate.threshold,
+    );
+    println!("The DKG certificate was written to: {}", storage.location(Artifact::DkgCertificate));
+
+    Ok(())
+}
+
+/// The `SPPOutput` of an encoded generation output
+fn output_view(generation_output: &[u8]) -> Result<SPPOutputView, CliError> {
+    SPPOutputMessage::from_bytes(generation_output)
+        .map_err(|e| CliError::protocol(format!("{:?}", e)))
+        .and_then(|output| SPPOutputView::parse(&output.spp_output().to_bytes()))
+}
+
+/// The value that occurs most often, if any
+fn most_common<T: Eq + std::hash::Hash + Clone>(values: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+
+    counts.into_iter().max_by_key(|(_, count)| *count).map(|(value, _)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rand::rngs::OsRng;
+    use schnorrkel::MiniSecretKey;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use super::*;
+    use olaf_cli::{
+        coordinator::{client::CoordinatorClient, server::serve},
+        messages::Entry,
+        protocol::{contributor_keypair, dkg_round1, DkgRound1Input, KeyExpansion},
+        storage::Storage,
+    };
+
+    /// The contributor secret keys of `participants` new participants, and their SS58 accounts
+    fn contributors(participants: usize) -> (Vec<[u8; 32]>, Vec<String>) {
+        let secret_keys: Vec<[u8; 32]> = (0..participants)
+            .map(|_| MiniSecretKey::generate_with(OsRng).to_bytes())
+            .collect();
+        let recipients = secret_keys
+            .iter()
+            .map(|secret_key| AccountId32(contributor_keypair(secret_key, KeyExpansion::Ed25519).unwrap().public.to_bytes()).to_string())
+            .collect();
+
+        (secret_keys, recipients)
+    }
+
+    /// The round 1 message of a contributor, as published
+    fn round1(secret_key: &[u8; 32], threshold: u16, recipients: &[String]) -> Entry {
+        let output = dkg_round1(DkgRound1Input {
+            secret_key,
+            expansion: KeyExpansion::Ed25519,
+            threshold,
+            recipients,
+        })
+        .unwrap();
+
+        Entry::new(&output.account_id.0, output.all_message)
+    }
+
+    /// A base directory whose recipients are three new contributors, with their secret keys
+    fn three_recipients() -> (TempDir, String, Arc<dyn Storage>, Vec<[u8; 32]>, Vec<String>) {
+        let dir = TempDir::new().unwrap();
+        let files = dir.path().to_string_lossy().into_owned();
+        let storage = Backend::Files.open(&FilePaths::new(&files)).unwrap();
+
+        let (secret_keys, recipients) = contributors(3);
+        write_json(&*storage, Artifact::Recipients, &recipients).unwrap();
+
+        (dir, files, storage, secret_keys, recipients)
+    }
+
+    async fn status(files: &str, coordinator: Option<&str>) -> Result<(), CliError> {
+        dkg_status(files.to_string(), &Backend::Files, None, coordinator.map(str::to_string)).await
+    }
+
+    #[tokio::test]
+    async fn the_contributions_of_the_recipients_agree() {
+        let (_dir, files, storage, secret_keys, recipients) = three_recipients();
+
+        status(&files, None).await.unwrap();
+
+        for secret_key in &secret_keys {
+            storage.publish(Collection::AllMessages, round1(secret_key, 2, &recipients)).unwrap();
+        }
+
+        status(&files, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_contributor_outside_the_recipients_is_a_protocol_error() {
+        let (_dir, files, storage, secret_keys, recipients) = three_recipients();
+
+        let (outsider_keys, outsider) = contributors(1);
+        let with_outsider = [recipients.clone(), outsider].concat();
+
+        storage.publish(Collection::AllMessages, round1(&secret_keys[0], 2, &recipients)).unwrap();
+        storage.publish(Collection::AllMessages, round1(&outsider_keys[0], 2, &with_outsider)).unwrap();
+
+        assert!(matches!(status(&files, None).await, Err(CliError::Protocol { .. })));
+    }
+
+    #[tokio::test]
+    async fn a_duplicate_contributor_is_a_protocol_error() {
+        let (_dir, files, storage, secret_keys, recipients) = three_recipients();
+
+        let entry = round1(&secret_keys[0], 2, &recipients);
+        // Entries without a sender, as written by older versions, are never replaced
+        let duplicate = Entry { sender: None, message: entry.message.clone() };
+
+        storage.publish(Collection::AllMessages, entry).unwrap();
+        status(&files, None).await.unwrap();
+
+        storage.publish(Collection::AllMessages, duplicate).unwrap();
+        assert!(matches!(status(&files, None).await, Err(CliError::Protocol { .. })));
+    }
+
+    #[tokio::test]
+    async fn a_threshold_mismatch_is_a_protocol_error() {
+        let (_dir, files, storage, secret_keys, recipients) = three_recipients();
+
+        storage.publish(Collection::AllMessages, round1(&secret_keys[0], 2, &recipients)).unwrap();
+        storage.publish(Collection::AllMessages, round1(&secret_keys[1], 2, &recipients)).unwrap();
+        storage.publish(Collection::AllMessages, round1(&secret_keys[2], 3, &recipients)).unwrap();
+
+        assert!(matches!(status(&files, None).await, Err(CliError::Protocol { .. })));
+    }
+
+    #[tokio::test]
+    async fn the_status_is_read_from_the_coordinator() {
+        let (_dir, files, _storage, secret_keys, recipients) = three_recipients();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener));
+
+        let client = CoordinatorClient::new(&url, NO_SESSION);
+        client.publish(Collection::AllMessages, &round1(&secret_keys[0], 2, &recipients)).await.unwrap();
+        client.publish(Collection::AllMessages, &round1(&secret_keys[1], 3, &recipients)).await.unwrap();
+
+        status(&files, None).await.unwrap();
+        assert!(matches!(status(&files, Some(&url)).await, Err(CliError::Protocol { .. })));
+    }
+}
@@ -0,0 +1,61 @@
+use olaf_cli::{
+    files::FilePaths,
+    keystore::{write_secret, StoredSecret, Unlock},
+    participant::ParticipantArg,
+    session::read_manifest,
+    storage::{Artifact, Backend},
+};
+use crate::cli::{commands::resolve_participant, CliError};
+use zeroize::Zeroizing;
+
+/// Encrypts the plaintext secrets of a participant in place with a password-protected keystore
+pub async fn encrypt_secrets(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    password_file: Option<String>,
+) -> Result<(), CliError> {
+    let file_paths = match session {
+        Some(session) => read_manifest(&files, backend, &session).await?.file_paths(&files),
+        None => FilePaths::new(&files),
+    };
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
+
+    let secrets = [
+        Artifact::ContributorSecretKey(participant),
+        Artifact::SigningShare(participant),
+    ];
+
+    let mut encrypted_count = 0;
+
+    for artifact in secrets {
+        let Some(content) = storage.get(artifact)? else {
+            continue;
+        };
+        let content = Zeroizing::new(content);
+
+        let content = std::str::from_utf8(&content)
+            .map_err(|e| CliError::serialization(format!("Failed to parse {}: {}", artifact, e)).with_source(e))?;
+
+        let stored = StoredSecret::parse(content)
+            .map_err(|e| e.context(format!("Failed to parse {}", artifact)))?;
+
+        if stored.is_encrypted() {
+            println!("The {} at {} is already encrypted", artifact, storage.location(artifact));
+            continue;
+        }
+
+        let secret = stored.reveal(&unlock)?;
+        write_secret(&*storage, artifact, &secret, &unlock)?;
+        encrypted_count += 1;
+
+        println!("Encrypted the {} at {}", artifact, storage.location(artifact));
+    }
+
+    println!("{} secret(s) of {} were encrypted", encrypted_count, participant);
+
+    Ok(())
+}
@@ -1,161 +1,174 @@
-use crate::{cli::CliError, files::FilePaths};
-use schnorrkel::{olaf::simplpedpop::AllMessage, MiniSecretKey, PublicKey};
 use subxt::utils::AccountId32;
-use tokio::{fs::{read_to_string, File}, io::AsyncWriteExt};
-use hex;
-use sp_core::crypto::Ss58Codec; 
+use olaf_cli::{
+    keystore::{write_secret, Unlock},
+    messages::{Collection, Entry, MessageBoard},
+    participant::{Participant, ParticipantArg},
+    protocol::{dkg_round1, dkg_round2, DkgRound1Input, DkgRound2Input, KeyExpansion},
+    session::{resolve_file_paths, SessionPurpose, NO_SESSION},
+    storage::{read_json, write_json, Artifact, Backend, Storage},
+};
+use crate::cli::{commands::{check_slot, publish, read_secret, resolve_participant}, CliError};
 
 /// Generates the message of round 1 of a participant to send to all participants (including itself)
-pub async fn generate_threshold_public_key_round1(threshold: u16, participant: u16, files: String) -> Result<(), CliError> {
-    let file_paths = FilePaths::new(files);
-
-    let secret_key_file_content = read_to_string(file_paths.contributor_secret_key(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to read contributor secret key file for participant {}: {}", participant, e)))?;
-    
-    let secret_key_string: String = serde_json::from_str(&secret_key_file_content)
-        .map_err(|e| CliError(format!("Failed to deserialize secret key content for participant {}: {}", participant, e)))?;
-    
-    let secret_key_string = secret_key_string.trim();
-    let secret_key_hex = secret_key_string.strip_prefix("0x").unwrap_or(secret_key_string);
-    let secret_key_bytes = hex::decode(secret_key_hex)
-        .map_err(|e| CliError(format!("Failed to decode hex secret key for participant {}: {}", participant, e)))?;
-    
-    let keypair = MiniSecretKey::from_bytes(&secret_key_bytes)
-        .map_err(|e| CliError(format!("Failed to generate keypair from secret key bytes for participant {}: {}", participant, e)))?
-        .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
-
-    let recipients_file_content = read_to_string(file_paths.recipients())
-        .await
-        .map_err(|e| CliError(format!("Failed to read recipients file: {}", e)))?;
-    
-    let recipients_strings: Vec<String> = serde_json::from_str(&recipients_file_content)
-        .map_err(|e| CliError(format!("Failed to deserialize recipients data: {}", e)))?;
-    
-    let recipients: Vec<PublicKey> = recipients_strings
-        .iter()
-        .map(|recipient| {
-            let account_id = sp_core::crypto::AccountId32::from_ss58check(recipient)
-                .map_err(|e| CliError(format!("Invalid SS58 recipient {}: {}", recipient, e)))?;
-            let public_key_bytes = account_id.as_ref();
-            PublicKey::from_bytes(public_key_bytes)
-                .map_err(|e| CliError(format!("Failed to parse public key for recipient {}: {}", recipient, e)))
-        })
-        .collect::<Result<_, _>>()?;
-
-    let all_message: AllMessage = keypair.simplpedpop_contribute_all(threshold, recipients)
-        .map_err(|e| CliError(format!("Failed to generate AllMessage: {:?}", e)))?;
-    let all_message_bytes: Vec<u8> = all_message.to_bytes();
-
-    let mut all_message_vec: Vec<Vec<u8>> = if tokio::fs::metadata(file_paths.all_messages()).await.is_ok() {
-        let existing_content = read_to_string(file_paths.all_messages())
-            .await
-            .map_err(|e| CliError(format!("Failed to read existing all_messages file: {}", e)))?;
-        serde_json::from_str(&existing_content)
-            .map_err(|e| CliError(format!("Failed to deserialize existing all_messages data: {}", e)))?
-    } else {
-        Vec::new()
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_threshold_public_key_round1(
+    threshold: Option<u16>,
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+    force: bool,
+) -> Result<(), CliError> {
+    let (file_paths, manifest) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Dkg).await?;
+
+    let threshold = match (threshold, &manifest) {
+        (Some(threshold), Some(manifest)) if threshold != manifest.threshold => {
+            return Err(CliError::input(format!(
+                "--threshold {} does not match the threshold {} of session {}",
+                threshold, manifest.threshold, manifest.id
+            )));
+        }
+        (Some(threshold), _) => threshold,
+        (None, Some(manifest)) => manifest.threshold,
+        (None, None) => return Err(CliError::input("--threshold is required when not running in a session".to_string())),
     };
-    all_message_vec.push(all_message_bytes);
-    let all_message_json = serde_json::to_string(&all_message_vec)
-        .map_err(|e| CliError(format!("Failed to serialize all_messages data: {}", e)))?;
 
-    let mut all_message_file = File::create(file_paths.all_messages())
-        .await
-        .map_err(|e| CliError(format!("Failed to create all_messages file: {}", e)))?;
-    
-    all_message_file
-        .write_all(all_message_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write all_messages data to file: {}", e)))?;
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
 
-    let account_id = AccountId32(keypair.public.to_bytes());
+    let secret_key_bytes = read_secret(&*storage, Artifact::ContributorSecretKey(participant), &unlock)?;
+    check_slot(&secret_key_bytes, expansion, &participant)?;
 
-    println!("The owner of account {} completed round 1 of Threshold Public Key generation successfully!", account_id);
-    println!("The message to all participants was written to: {:?}", file_paths.all_messages());
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+
+    let output = dkg_round1(DkgRound1Input {
+        secret_key: &secret_key_bytes,
+        expansion,
+        threshold,
+        recipients: &recipients,
+    })
+    .map_err(|e| e.context(format!("Round 1 failed for {}", participant)))?;
+
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+    // Round 2 of this participant may already have used the earlier message
+    let in_use = storage.get(Artifact::GenerationOutput(participant))?.is_some();
+    let entry = Entry::new(&output.account_id.0, output.all_message);
+    message_board.check_replaceable(Collection::AllMessages, &entry, in_use, force).await?;
+    publish(&message_board, Collection::AllMessages, entry).await?;
+
+    println!("The owner of account {} completed round 1 of Threshold Public Key generation successfully!", output.account_id);
+    println!("The message to all participants was written to: {}", message_board.location(Collection::AllMessages));
 
     Ok(())
 }
 
 /// Generates the threshold public key and the corresponding secret secret share of the participant, from the messages of round 1 of all participants (including itself)
-pub async fn generate_threshold_public_key_round2(participant: u16, files: String) -> Result<(), CliError> {
-    let file_paths = FilePaths::new(files);
-
-    let secret_key_file_content = read_to_string(file_paths.contributor_secret_key(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to read contributor secret key file for participant {}: {}", participant, e)))?;
-    
-    let secret_key_string: String = serde_json::from_str(&secret_key_file_content)
-        .map_err(|e| CliError(format!("Failed to deserialize secret key content for participant {}: {}", participant, e)))?;
-    
-    let secret_key_string = secret_key_string.trim();
-    let secret_key_hex = secret_key_string.strip_prefix("0x").unwrap_or(secret_key_string);
-    let secret_key_bytes = hex::decode(secret_key_hex)
-        .map_err(|e| CliError(format!("Failed to decode hex secret key for participant {}: {}", participant, e)))?;
-    
-    let keypair = MiniSecretKey::from_bytes(&secret_key_bytes)
-        .map_err(|e| CliError(format!("Failed to generate keypair from secret key bytes for participant {}: {}", participant, e)))?
-        .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
-
-    let all_messages_string = read_to_string(file_paths.all_messages())
-        .await
-        .map_err(|e| CliError(format!("Failed to read all_messages file: {}", e)))?;
-    
-    let all_messages_bytes: Vec<Vec<u8>> = serde_json::from_str(&all_messages_string)
-        .map_err(|e| CliError(format!("Failed to deserialize all_messages data: {}", e)))?;
-
-    let all_messages: Vec<AllMessage> = all_messages_bytes
-        .iter()
-        .map(|all_message| AllMessage::from_bytes(all_message)
-            .map_err(|e| CliError(format!("Failed to parse AllMessage: {:?}", e))))
-        .collect::<Result<_, _>>()?;
-
-    let simplpedpop = keypair.simplpedpop_recipient_all(&all_messages)
-        .map_err(|e| CliError(format!("Failed to process AllMessages for participant {}: {:?}", participant, e)))?;
-    
-    let generation_output = simplpedpop.0;
-    let output_json = serde_json::to_string(&generation_output.to_bytes())
-        .map_err(|e| CliError(format!("Failed to serialize generation output: {}", e)))?;
-    
-    let mut output_file = File::create(file_paths.generation_output(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to create generation output file for participant {}: {:?}", participant, e)))?;
-    
-    output_file
-        .write_all(output_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write generation output to file: {}", e)))?;
-
-    let signing_share = simplpedpop.1;
-    let signing_share_json = serde_json::to_string(&signing_share.to_bytes().to_vec())
-        .map_err(|e| CliError(format!("Failed to serialize signing share: {}", e)))?;
-
-    let mut signing_share_file = File::create(file_paths.signing_share(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to create signing share file for participant {}: {}", participant, e)))?;
-    
-    signing_share_file
-        .write_all(signing_share_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write signing share to file: {}", e)))?;
-
-    let threshold_public_key = AccountId32(generation_output.spp_output().threshold_public_key().0.to_bytes());
-    let threshold_public_key_json = serde_json::to_string(&threshold_public_key)
-        .map_err(|e| CliError(format!("Failed to serialize threshold public key: {}", e)))?;
-
-    let mut threshold_public_key_file = File::create(file_paths.threshold_public_key())
-        .await
-        .map_err(|e| CliError(format!("Failed to create threshold public key file: {}", e)))?;
-    
-    threshold_public_key_file
-        .write_all(threshold_public_key_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write threshold public key to file: {}", e)))?;
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_threshold_public_key_round2(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+    allow_legacy: bool,
+) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Dkg).await?;
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
+
+    let secret_key_bytes = read_secret(&*storage, Artifact::ContributorSecretKey(participant), &unlock)?;
+    check_slot(&secret_key_bytes, expansion, &participant)?;
+
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+    let all_messages = message_board.fetch(Collection::AllMessages).await?;
+
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+
+    let output = dkg_round2(DkgRound2Input {
+        secret_key: &secret_key_bytes,
+        expansion,
+        recipients: &recipients,
+        all_messages: &all_messages,
+        allow_legacy,
+    })
+    .map_err(|e| e.context(format!("Round 2 failed for {}", participant)))?;
+
+    let threshold_public_key = output.threshold_public_key;
+
+    // Checked before anything is written or published, so a participant that disagrees leaves no trace
+    agree_threshold_public_key(&*storage, participant, &threshold_public_key)?;
+
+    write_json(&*storage, Artifact::GenerationOutput(participant), &output.generation_output)?;
+    write_secret(&*storage, Artifact::SigningShare(participant), &output.signing_share[..], &unlock)?;
+
+    publish(&message_board, Collection::GenerationOutputs, Entry::new(&participant.account, output.generation_output_bundle)).await?;
 
     println!("The owner of account {} completed round 2 of Threshold Public Key generation successfully!", threshold_public_key);
-    println!("The output message was written to: {:?}", file_paths.generation_output(participant)); 
-    println!("The signing share was written to: {:?}", file_paths.signing_share(participant)); 
-    println!("The Threshold Public Key is {} and was written to: {:?}", threshold_public_key, file_paths.threshold_public_key());  
+    println!("The output message was written to: {}", storage.location(Artifact::GenerationOutput(participant)));
+    println!("The signing share was written to: {}", storage.location(Artifact::SigningShare(participant)));
+    println!("The signed output was sent to all participants at: {}", message_board.location(Collection::GenerationOutputs));
+    println!("The Threshold Public Key is {} and was written to: {}", threshold_public_key, storage.location(Artifact::ThresholdPublicKey));
+    println!("Once every participant has completed round 2, run `olaf-cli dkg certify` before using it");
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Writes the threshold public key `participant` derived, unless another participant already wrote
+/// a different one, which must not be silently overwritten. Comparing and writing are one update,
+/// so two participants finishing at once cannot both write their own key.
+fn agree_threshold_public_key(storage: &dyn Storage, participant: Participant, threshold_public_key: &AccountId32) -> Result<(), CliError> {
+    let location = storage.location(Artifact::ThresholdPublicKey);
+    let content = serde_json::to_vec(threshold_public_key)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize {}: {}", Artifact::ThresholdPublicKey, e)).with_source(e))?;
+
+    storage.update(Artifact::ThresholdPublicKey, &mut |existing| {
+        let Some(existing) = existing else {
+            return Ok(Some(content.clone()));
+        };
+
+        let existing: AccountId32 = serde_json::from_slice(existing)
+            .map_err(|e| CliError::key_parsing(format!("Failed to parse threshold public key at {}: {}", location, e)).with_source(e))?;
+
+        if existing != *threshold_public_key {
+            return Err(CliError::protocol(format!(
+                "{} derived the Threshold Public Key {}, but {} holds {}, derived by another participant. \
+                 The participants do not agree: run `olaf-cli dkg status` to find out why",
+                participant,
+                threshold_public_key,
+                location,
+                existing,
+            )));
+        }
+
+        Ok(None)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use olaf_cli::storage::MemoryStorage;
+    use crate::cli::commands::read_threshold_public_key;
+
+    const PARTICIPANT: Participant = Participant { index: 1, account: [1u8; 32] };
+
+    #[test]
+    fn a_different_threshold_public_key_is_not_overwritten() {
+        let storage = MemoryStorage::new();
+
+        agree_threshold_public_key(&storage, PARTICIPANT, &AccountId32([1u8; 32])).unwrap();
+        agree_threshold_public_key(&storage, PARTICIPANT, &AccountId32([1u8; 32])).unwrap();
+
+        let refused = agree_threshold_public_key(&storage, PARTICIPANT, &AccountId32([2u8; 32]));
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+        assert_eq!(read_threshold_public_key(&storage).unwrap(), AccountId32([1u8; 32]));
+    }
+}
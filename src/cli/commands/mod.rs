@@ -2,12 +2,116 @@ pub mod sign;
 pub mod generate;
 pub mod submit;
 pub mod aggregate;
+pub mod encrypt;
+pub mod session;
+pub mod coordinator;
+pub mod verify;
+pub mod request;
+pub mod whoami;
+pub mod dkg;
+pub mod refresh;
+pub mod reshare;
 
-use scale_value::{Composite, ValueDef};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use subxt::utils::AccountId32;
+use tokio::fs::read;
+use zeroize::Zeroizing;
+use olaf_cli::{
+    keystore::{self, Unlock},
+    messages::{Collection, Entry, MessageBoard, Published},
+    participant::{Participant, ParticipantArg},
+    protocol::{contributor_keypair, KeyExpansion},
+    storage::{read_json, Artifact, Storage},
+};
+use crate::cli::CliError;
 
-pub(crate) fn value_into_composite(value: scale_value::Value) -> scale_value::Composite<()> {
-	match value.value {
-		ValueDef::Composite(composite) => composite,
-		_ => Composite::Unnamed(vec![value]),
-	}
-}
\ No newline at end of file
+/// Reads a secret artifact, decrypting it if it is an encrypted keystore, with a warning if it is
+/// stored unencrypted
+pub(crate) fn read_secret(storage: &dyn Storage, artifact: Artifact, unlock: &Unlock) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let (secret, warning) = keystore::read_secret(storage, artifact, unlock)?;
+
+    if let Some(warning) = warning {
+        eprintln!("Warning: {}", warning);
+    }
+
+    Ok(secret)
+}
+
+/// Publishes an entry to the collection, telling the user if it was there already or replaced an
+/// earlier one
+pub(crate) async fn publish(message_board: &MessageBoard, collection: Collection, entry: Entry) -> Result<(), CliError> {
+    let sender = entry.sender_name().to_string();
+
+    match (message_board, message_board.publish(collection, entry).await?) {
+        (_, Published::Added) => {}
+        (_, Published::Unchanged) => println!("{} already holds this entry from {}", collection, sender),
+        (MessageBoard::Coordinator(_), Published::Replaced) => println!("{} now holds the extended entry from {}", collection, sender),
+        (MessageBoard::Storage(_), Published::Replaced) => eprintln!("Warning: replaced the earlier entry from {} in {}", sender, collection),
+    }
+
+    Ok(())
+}
+
+/// The context and hex encoded message of a threshold message signature, stored in `signed_message.json`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SignedMessage {
+    pub context: String,
+    pub message: String,
+}
+
+pub(crate) fn read_threshold_public_key(storage: &dyn Storage) -> Result<AccountId32, CliError> {
+    let threshold_public_key_bytes = storage
+        .get(Artifact::ThresholdPublicKey)?
+        .ok_or_else(|| CliError::io(format!("No threshold public key found at {}", storage.location(Artifact::ThresholdPublicKey))))?;
+
+    AccountId32::from_str(&serde_json::from_slice::<String>(&threshold_public_key_bytes)
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse threshold public key: {}", e)).with_source(e))?)
+        .map_err(|e| CliError::key_parsing(format!("Invalid threshold public key format: {}", e)))
+}
+
+/// Identifies the participant named on the command line among the recipients
+pub(crate) fn resolve_participant(storage: &dyn Storage, participant: &ParticipantArg) -> Result<Participant, CliError> {
+    let recipients: Vec<String> = read_json(storage, Artifact::Recipients)
+        .map_err(|e| e.context(format!("The recipients are needed to identify participant {}", participant)))?;
+
+    Participant::resolve(participant, &recipients)
+}
+
+/// Checks that the contributor secret key of `participant` belongs to the account of its slot among the recipients
+pub(crate) fn check_slot(secret_key: &[u8], expansion: KeyExpansion, participant: &Participant) -> Result<(), CliError> {
+    let public_key = contributor_keypair(secret_key, expansion)?.public.to_bytes();
+
+    if public_key != participant.account {
+        return Err(CliError::input(format!(
+            "The contributor secret key of {} belongs to account {}, not to the account of that recipient. Run `olaf-cli whoami` to find its slot",
+            participant,
+            AccountId32(public_key),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Reads a message given inline, as a file or hex encoded. Returns `None` if no source was given.
+pub(crate) async fn resolve_message(
+    message: Option<String>,
+    message_file: Option<String>,
+    message_hex: Option<String>,
+) -> Result<Option<Vec<u8>>, CliError> {
+    match (message, message_file, message_hex) {
+        (None, None, None) => Ok(None),
+        (Some(message), None, None) => Ok(Some(message.into_bytes())),
+        (None, Some(message_file), None) => read(&message_file)
+            .await
+            .map(Some)
+            .map_err(|e| CliError::io(format!("Failed to read message file {}: {}", message_file, e)).with_source(e)),
+        (None, None, Some(message_hex)) => {
+            let message_hex = message_hex.trim();
+            hex::decode(message_hex.strip_prefix("0x").unwrap_or(message_hex))
+                .map(Some)
+                .map_err(|e| CliError::input(format!("Failed to decode hex message: {}", e)).with_source(e))
+        }
+        _ => Err(CliError::input("Only one of --message, --message-file and --message-hex can be given".to_string())),
+    }
+}
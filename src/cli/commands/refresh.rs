@@ -0,0 +1,131 @@
+use olaf_cli::{
+    dealing::Dealing,
+    keystore::{write_secret, Unlock},
+    messages::{Collection, Entry, MessageBoard},
+    participant::ParticipantArg,
+    protocol::{refresh_round1, refresh_round2, KeyExpansion, RefreshRound1Input, RefreshRound2Input},
+    session::{resolve_file_paths, SessionPurpose, NO_SESSION},
+    storage::{read_json, write_json, Artifact, Backend},
+};
+use crate::cli::{commands::{check_slot, publish, read_secret, read_threshold_public_key, resolve_participant}, CliError};
+
+/// Generates the refresh message of a participant to send to all participants (including itself)
+#[allow(clippy::too_many_arguments)]
+pub async fn refresh_signing_shares_round1(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+    force: bool,
+) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Dkg).await?;
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
+
+    let secret_key_bytes = read_secret(&*storage, Artifact::ContributorSecretKey(participant), &unlock)?;
+    check_slot(&secret_key_bytes, expansion, &participant)?;
+
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+    let generation_output: Vec<u8> = read_json(&*storage, Artifact::GenerationOutput(participant))?;
+
+    let output = refresh_round1(RefreshRound1Input {
+        secret_key: &secret_key_bytes,
+        expansion,
+        generation_output: &generation_output,
+        recipients: &recipients,
+    })
+    .map_err(|e| e.context(format!("Refresh round 1 failed for {}", participant)))?;
+
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+    let entry = Entry::new(&output.account_id.0, output.dealing);
+
+    // Other participants may already have refreshed their shares with the earlier message of this
+    // refresh, which is the case if it was made for the same signing shares
+    let binding = Dealing::from_bytes(&entry.message)?.binding;
+    let in_use = message_board
+        .entries(Collection::RefreshMessages)
+        .await?
+        .iter()
+        .filter(|earlier| earlier.sender == entry.sender)
+        .any(|earlier| Dealing::from_bytes(&earlier.message).is_ok_and(|dealing| dealing.binding == binding));
+
+    message_board.check_replaceable(Collection::RefreshMessages, &entry, in_use, force).await?;
+    publish(&message_board, Collection::RefreshMessages, entry).await?;
+
+    println!("The owner of account {} completed round 1 of the signing share refresh successfully!", output.account_id);
+    println!("The message to all participants was written to: {}", message_board.location(Collection::RefreshMessages));
+
+    Ok(())
+}
+
+/// Refreshes the signing share and the generation output of a participant, from the refresh
+/// messages of all participants (including itself)
+pub async fn refresh_signing_shares_round2(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Dkg).await?;
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
+
+    let secret_key_bytes = read_secret(&*storage, Artifact::ContributorSecretKey(participant), &unlock)?;
+    check_slot(&secret_key_bytes, expansion, &participant)?;
+
+    let signing_share = read_secret(&*storage, Artifact::SigningShare(participant), &unlock)?;
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+    let generation_output: Vec<u8> = read_json(&*storage, Artifact::GenerationOutput(participant))?;
+
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+    let dealings = message_board.fetch(Collection::RefreshMessages).await?;
+
+    let output = refresh_round2(RefreshRound2Input {
+        secret_key: &secret_key_bytes,
+        expansion,
+        signing_share: &signing_share,
+        generation_output: &generation_output,
+        recipients: &recipients,
+        dealings: &dealings,
+    })
+    .map_err(|e| e.context(format!("Refresh round 2 failed for {}", participant)))?;
+
+    if storage.get(Artifact::ThresholdPublicKey)?.is_some() && read_threshold_public_key(&*storage)? != output.threshold_public_key {
+        return Err(CliError::protocol(format!(
+            "The generation output of {} is for the Threshold Public Key {}, but {} holds another one",
+            participant,
+            output.threshold_public_key,
+            storage.location(Artifact::ThresholdPublicKey),
+        )));
+    }
+
+    // The old signing share must not be recoverable once it is replaced. The password is obtained
+    // first, so it is only wiped once the new one can be encrypted.
+    unlock.new_password()?;
+    storage.wipe(Artifact::SigningShare(participant))?;
+    write_secret(&*storage, Artifact::SigningShare(participant), &output.signing_share[..], &unlock)?;
+    write_json(&*storage, Artifact::GenerationOutput(participant), &output.generation_output)?;
+
+    publish(&message_board, Collection::GenerationOutputs, Entry::new(&participant.account, output.generation_output_bundle)).await?;
+
+    // The certificate vouches for the verifying shares from before the refresh
+    storage.delete(Artifact::DkgCertificate)?;
+
+    println!("The owner of account {} completed round 2 of the signing share refresh successfully!", participant.ss58());
+    println!("The refreshed signing share was written to: {}", storage.location(Artifact::SigningShare(participant)));
+    println!("The refreshed output message was written to: {}", storage.location(Artifact::GenerationOutput(participant)));
+    println!("The Threshold Public Key is still {}", output.threshold_public_key);
+    println!("Once every participant has completed round 2, run `olaf-cli dkg certify` to certify the refreshed shares");
+
+    Ok(())
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeSet, HashSet};
+use olaf_cli::{
+    extrinsic::{payload_hash, ExtrinsicInfo, SigningRequest},
+    files::FilePaths,
+    inspect::{SPPOutputMessageView, SPPOutputView},
+    messages::{Collection, MessageBoard},
+    nonces::{read_precommitted_nonces, NonceState},
+    participant::{Participant, ParticipantArg},
+    protocol::{DkgCertificate, PrecommitmentBundle},
+    session::{keys_session, list_manifests, resolve_file_paths, SessionManifest, SessionPurpose, NO_SESSION},
+    storage::{read_json, Artifact, Backend, Storage},
+};
+use crate::cli::{commands::{resolve_participant, sign::prepare_extrinsic}, CliError, TransactionArgs};
+
+/// Proposes an extrinsic for all participants to sign. Its nonce, mortality and chain are fixed
+/// once, and written with the hash of the resulting payload to the signing request file.
+///
+/// With `nonce_index`, or with `precommitted` to pick the next free index, participants sign with
+/// their precommitted signing nonces at that index and skip round 1. With `signers`, only those
+/// participants sign, with their signing commitments only.
+#[allow(clippy::too_many_arguments)]
+pub async fn propose_signing_request(
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    url: String,
+    pallet: String,
+    call_name: String,
+    call_data: String,
+    context: String,
+    transaction: TransactionArgs,
+    nonce_index: Option<u32>,
+    precommitted: bool,
+    signers: Vec<ParticipantArg>,
+) -> Result<(), CliError> {
+    let (file_paths, manifest) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
+
+    let mut extrinsic_info = ExtrinsicInfo {
+        url,
+        pallet,
+        call_name,
+        call_data,
+        nonce: None,
+        tip: transaction.tip,
+        mortality: None,
+    };
+
+    let storage = backend.open(&file_paths)?;
+
+    let threshold = match (&manifest, storage.get(Artifact::DkgCertificate)?) {
+        (Some(manifest), _) => Some(manifest.threshold),
+        (None, Some(_)) => Some(read_json::<DkgCertificate>(&*storage, Artifact::DkgCertificate)?.threshold),
+        (None, None) => None,
+    };
+    let signers = resolve_signers(&*storage, &signers, threshold)?;
+
+    let nonce_index = match nonce_index {
+        Some(index) => Some(index),
+        None if precommitted => {
+            let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), keys_session(manifest.as_ref()));
+            Some(next_nonce_index(&files, backend, manifest.as_ref(), &*storage, &message_board, &signers).await?)
+        }
+        None => None,
+    };
+
+    let prepared = prepare_extrinsic(&*storage, &mut extrinsic_info, transaction).await?;
+
+    let signing_request = SigningRequest {
+        extrinsic: extrinsic_info,
+        context,
+        genesis_hash: prepared.genesis_hash,
+        spec_version: prepared.spec_version,
+        transaction_version: prepared.transaction_version,
+        payload_hash: payload_hash(&prepared.payload),
+        nonce_index,
+        signers: signers.iter().map(Participant::ss58).collect(),
+    };
+
+    let signing_request_json = serde_json::to_string_pretty(&signing_request)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize signing request: {}", e)).with_source(e))?;
+
+    storage.put(Artifact::SigningRequest, signing_request_json.as_bytes())?;
+
+    println!("The signing request was created successfully!");
+    println!("Payload hash: {}", signing_request.payload_hash);
+    if !signers.is_empty() {
+        let names: Vec<String> = signers.iter().map(Participant::to_string).collect();
+        println!("Signers: {}", names.join(", "));
+    }
+    if let Some(index) = nonce_index {
+        println!("Participants sign with their precommitted signing nonces at index {}, without round 1", index);
+    }
+    println!("Signing request was written to: {}", storage.location(Artifact::SigningRequest));
+
+    Ok(())
+}
+
+/// The lowest index at which every signer precommitted a signing nonce that is still unused: the
+/// chosen `signers`, or every participant that precommitted if none are chosen.
+///
+/// An index is used once the nonce record of any of the signers holds its tombstone, whether it
+/// was consumed with or without a signing request, or once a signing request for the same key
+/// material pins it, whether its session is open or closed, as signers whose nonces are kept
+/// elsewhere may not have consumed it yet.
+async fn next_nonce_index(
+    files: &str,
+    backend: &Backend,
+    manifest: Option<&SessionManifest>,
+    storage: &dyn Storage,
+    message_board: &MessageBoard,
+    signers: &[Participant],
+) -> Result<u32, CliError> {
+    let entries = message_board.entries(Collection::Precommitments).await?;
+
+    if entries.is_empty() {
+        return Err(CliError::input(format!(
+            "No precommitted signing commitments found at {}, run `olaf-cli threshold-sign-precommit` first",
+            message_board.location(Collection::Precommitments),
+        )));
+    }
+
+    // Bundles are keyed by the verifying share of their sender, known from the `SPPOutput`
+    let bundles = match (signers.is_empty(), local_spp_output(storage)?) {
+        (false, Some(spp_output)) => signers
+            .iter()
+            .map(|signer| {
+                let verifying_share = (signer.index as usize)
+                    .checked_sub(1)
+                    .and_then(|position| spp_output.verifying_shares.get(position))
+                    .map(|(_, verifying_share)| hex::encode(verifying_share))
+                    .ok_or_else(|| CliError::protocol(format!("The generation output has no verifying share for {}", signer)))?;
+
+                let entry = entries
+                    .iter()
+                    .find(|entry| entry.sender.as_deref() == Some(verifying_share.as_str()))
+                    .ok_or_else(|| CliError::input(format!("{} has not precommitted signing commitments yet", signer)))?;
+
+                PrecommitmentBundle::from_bytes(&entry.message)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => entries
+            .iter()
+            .map(|entry| PrecommitmentBundle::from_bytes(&entry.message))
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let participants = match signers {
+        [] => {
+            let recipients: Vec<String> = read_json(storage, Artifact::Recipients)?;
+            (1..=recipients.len() as u16)
+                .map(|index| Participant::resolve(&ParticipantArg::Index(index), &recipients))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        signers => signers.to_vec(),
+    };
+
+    let mut used = HashSet::new();
+
+    for participant in participants {
+        used.extend(
+            read_precommitted_nonces(storage, participant)?
+                .into_iter()
+                .filter(|nonce| matches!(nonce.state, NonceState::Consumed { .. }))
+                .map(|nonce| nonce.index),
+        );
+    }
+
+    let keys = keys_session(manifest);
+
+    if keys == NO_SESSION {
+        used.extend(pinned_nonce_index(&*backend.open(&FilePaths::new(files))?)?);
+    }
+
+    let (manifests, warnings) = list_manifests(files, backend).await?;
+
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    for other in manifests {
+        if other.purpose == SessionPurpose::Sign && keys_session(Some(&other)) == keys {
+            used.extend(pinned_nonce_index(&*backend.open(&other.file_paths(files))?)?);
+        }
+    }
+
+    let common: BTreeSet<u32> = bundles
+        .first()
+        .map(|bundle| bundle.commitments.iter().map(|(index, _)| *index).collect())
+        .unwrap_or_default();
+
+    common
+        .into_iter()
+        .find(|index| !used.contains(index) && bundles.iter().all(|bundle| bundle.commitment(*index).is_some()))
+        .ok_or_else(|| {
+            CliError::input(
+                "Every index precommitted by all signers is used or pinned by a signing request already: run `olaf-cli threshold-sign-precommit` again".to_string(),
+            )
+        })
+}
+
+/// The `SPPOutput` of the key, from the DKG certificate or from the generation output of any
+/// participant in `storage`, if there is one
+fn local_spp_output(storage: &dyn Storage) -> Result<Option<SPPOutputView>, CliError> {
+    if storage.get(Artifact::DkgCertificate)?.is_some() {
+        let certificate: DkgCertificate = read_json(storage, Artifact::DkgCertificate)?;
+        let spp_output = hex::decode(&certificate.spp_output)
+            .map_err(|e| CliError::serialization(format!("Failed to decode SPPOutput of DKG certificate: {}", e)).with_source(e))?;
+
+        return SPPOutputView::parse(&spp_output).map(Some);
+    }
+
+    let recipients: Vec<String> = read_json(storage, Artifact::Recipients)?;
+
+    for index in 1..=recipients.len() as u16 {
+        let participant = Participant::resolve(&ParticipantArg::Index(index), &recipients)?;
+
+        if storage.get(Artifact::GenerationOutput(participant))?.is_some() {
+            let generation_output: Vec<u8> = read_json(storage, Artifact::GenerationOutput(participant))?;
+
+            return SPPOutputView::parse(SPPOutputMessageView::parse(&generation_output)?.spp_output).map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+/// The precommitted nonce index pinned by the signing request in `storage`, if any
+fn pinned_nonce_index(storage: &dyn Storage) -> Result<Option<u32>, CliError> {
+    if storage.get(Artifact::SigningRequest)?.is_none() {
+        return Ok(None);
+    }
+
+    let signing_request: SigningRequest = read_json(storage, Artifact::SigningRequest)?;
+
+    Ok(signing_request.nonce_index)
+}
+
+/// Identifies the chosen signers among the recipients, checking that they are distinct and, if
+/// the threshold is known, that there are enough of them to sign
+fn resolve_signers(storage: &dyn Storage, signers: &[ParticipantArg], threshold: Option<u16>) -> Result<Vec<Participant>, CliError> {
+    let mut resolved: Vec<Participant> = Vec::new();
+
+    for signer in signers {
+        let participant = resolve_participant(storage, signer)?;
+
+        if resolved.contains(&participant) {
+            return Err(CliError::input(format!("{} is given more than once in --signers", participant)));
+        }
+
+        resolved.push(participant);
+    }
+
+    match threshold {
+        Some(threshold) if !resolved.is_empty() && resolved.len() < threshold as usize => Err(CliError::input(format!(
+            "{} signers were chosen, but signing needs at least {}, the threshold",
+            resolved.len(),
+            threshold,
+        ))),
+        _ => Ok(resolved),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sp_core::crypto::{AccountId32, Ss58Codec};
+    use tempfile::TempDir;
+    use super::*;
+    use olaf_cli::{
+        messages::Entry,
+        nonces::PrecommittedNonce,
+        storage::{write_json, MemoryStorage},
+    };
+
+    fn account(index: u8) -> String {
+        AccountId32::from([index; 32]).to_ss58check()
+    }
+
+    /// A storage whose recipients are the accounts of three participants
+    fn three_recipients() -> MemoryStorage {
+        let storage = MemoryStorage::new();
+        write_json(&storage, Artifact::Recipients, &vec![account(1), account(2), account(3)]).unwrap();
+
+        storage
+    }
+
+    #[test]
+    fn signers_are_resolved_by_position_or_account() {
+        let storage = three_recipients();
+
+        let signers = resolve_signers(&storage, &[ParticipantArg::Index(3), ParticipantArg::Account(account(1))], Some(2)).unwrap();
+        assert_eq!(signers.iter().map(|signer| signer.index).collect::<Vec<_>>(), [3, 1]);
+
+        assert!(resolve_signers(&storage, &[], Some(2)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_duplicate_signer_is_refused() {
+        let storage = three_recipients();
+
+        let refused = resolve_signers(&storage, &[ParticipantArg::Index(1), ParticipantArg::Account(account(1))], Some(2));
+        assert!(matches!(refused, Err(CliError::Input { .. })));
+    }
+
+    #[test]
+    fn fewer_signers_than_the_threshold_are_refused() {
+        let storage = three_recipients();
+
+        let refused = resolve_signers(&storage, &[ParticipantArg::Index(1), ParticipantArg::Index(2)], Some(3));
+        assert!(matches!(refused, Err(CliError::Input { .. })));
+    }
+
+    #[test]
+    fn a_signer_outside_the_recipients_is_refused() {
+        let storage = three_recipients();
+
+        for outsider in [ParticipantArg::Index(4), ParticipantArg::Account(account(4))] {
+            let refused = resolve_signers(&storage, &[ParticipantArg::Index(1), outsider], Some(2));
+            assert!(matches!(refused, Err(CliError::Input { .. })));
+        }
+    }
+
+    /// Marks the precommitted nonces of `participant` at `indices` as consumed
+    fn consume(storage: &dyn Storage, participant: Participant, indices: &[u32]) {
+        let nonces: Vec<PrecommittedNonce> = indices
+            .iter()
+            .map(|index| PrecommittedNonce {
+                index: *index,
+                commitment: String::new(),
+                state: NonceState::Consumed {
+                    commitments_hash: String::new(),
+                    payload_hash: String::new(),
+                },
+            })
+            .collect();
+
+        storage.put(Artifact::PrecommittedNonces(participant), &serde_json::to_vec(&nonces).unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_next_nonce_index_is_unused_by_every_signer() {
+        let dir = TempDir::new().unwrap();
+        let files = dir.path().to_string_lossy().into_owned();
+        let storage = Backend::Files.open(&FilePaths::new(&files)).unwrap();
+
+        let recipients = vec![account(1), account(2), account(3)];
+        write_json(&*storage, Artifact::Recipients, &recipients).unwrap();
+        let participants: Vec<Participant> = (1..=3)
+            .map(|index| Participant::resolve(&ParticipantArg::Index(index), &recipients).unwrap())
+            .collect();
+
+        // The verifying share of participant n is [n + 10; 32]
+        let spp_output = SPPOutputView {
+            participants: 3,
+            threshold: 2,
+            threshold_public_key: [0u8; 32],
+            verifying_shares: (1..=3).map(|index| ([index; 32], [index + 10; 32])).collect(),
+        }
+        .to_bytes();
+        let generation_output = SPPOutputMessageView { signer: &[1u8; 32], spp_output: &spp_output, signature: &[0u8; 64] }.to_bytes();
+        write_json(&*storage, Artifact::GenerationOutput(participants[0]), &generation_output).unwrap();
+
+        // Participants 1 and 2 precommitted indices 0 to 3, participant 3 only 0 and 1
+        for (sender, last_index) in [(11u8, 3u32), (12, 3), (13, 1)] {
+            let bundle = PrecommitmentBundle { commitments: (0..=last_index).map(|index| (index, vec![sender; 64])).collect() };
+            storage.publish(Collection::Precommitments, Entry::new(&[sender; 32], bundle.to_bytes())).unwrap();
+        }
+
+        let message_board = MessageBoard::Storage(storage.clone());
+        let next = |signers: Vec<Participant>| {
+            let (files, storage, message_board) = (&files, &storage, &message_board);
+            async move { next_nonce_index(files, &Backend::Files, None, &**storage, message_board, &signers).await }
+        };
+
+        assert_eq!(next(vec![participants[0], participants[1]]).await.unwrap(), 0);
+
+        // Participant 1 signed at index 0 without a signing request
+        consume(&*storage, participants[0], &[0]);
+
+        assert_eq!(next(vec![participants[0], participants[1]]).await.unwrap(), 1);
+        assert_eq!(next(vec![participants[1], participants[2]]).await.unwrap(), 0);
+        assert_eq!(next(Vec::new()).await.unwrap(), 1);
+
+        consume(&*storage, participants[0], &[0, 1]);
+
+        // Participant 3, unchosen, has no nonce at index 2
+        assert_eq!(next(vec![participants[0], participants[1]]).await.unwrap(), 2);
+        assert!(matches!(next(Vec::new()).await, Err(CliError::Input { .. })));
+    }
+}
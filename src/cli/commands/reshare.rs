@@ -0,0 +1,161 @@
+use std::str::FromStr;
+use subxt::utils::AccountId32;
+use olaf_cli::{
+    keystore::{write_secret, Unlock},
+    messages::{Collection, Entry, MessageBoard},
+    participant::ParticipantArg,
+    protocol::{reshare_round1, reshare_round2, KeyExpansion, ReshareMessage, ReshareRound1Input, ReshareRound2Input},
+    session::{resolve_file_paths, SessionPurpose},
+    storage::{read_json, write_json, Artifact, Backend},
+};
+use crate::cli::{commands::{check_slot, publish, read_secret, read_threshold_public_key, resolve_participant}, CliError};
+
+/// Deals the signing share of a current holder to the participants of the DKG session
+/// `new_session`, with its threshold. Only the holders in `dealers`, or the first ones as many as
+/// the current threshold if it is empty, deal.
+#[allow(clippy::too_many_arguments)]
+pub async fn reshare_round1(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    new_session: String,
+    coordinator: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+    dealers: Vec<u16>,
+    force: bool,
+) -> Result<(), CliError> {
+    if session.as_deref() == Some(new_session.as_str()) {
+        return Err(CliError::input(
+            "The new participants need a DKG session of their own: create one with `olaf-cli session new --purpose dkg`".to_string(),
+        ));
+    }
+
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Dkg).await?;
+    let (new_file_paths, new_manifest) = resolve_file_paths(&files, backend, Some(&new_session), SessionPurpose::Dkg).await?;
+    let new_manifest = new_manifest.expect("resolved with a session");
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
+
+    let secret_key_bytes = read_secret(&*storage, Artifact::ContributorSecretKey(participant), &unlock)?;
+    check_slot(&secret_key_bytes, expansion, &participant)?;
+
+    let signing_share = read_secret(&*storage, Artifact::SigningShare(participant), &unlock)?;
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+    let generation_output: Vec<u8> = read_json(&*storage, Artifact::GenerationOutput(participant))?;
+
+    let output = reshare_round1(ReshareRound1Input {
+        secret_key: &secret_key_bytes,
+        expansion,
+        signing_share: &signing_share,
+        generation_output: &generation_output,
+        recipients: &recipients,
+        new_recipients: &new_manifest.participants,
+        new_threshold: new_manifest.threshold,
+        dealers: (!dealers.is_empty()).then_some(&dealers[..]),
+    })
+    .map_err(|e| e.context(format!("Reshare round 1 failed for {}", participant)))?;
+
+    let message_board = MessageBoard::new(backend.open(&new_file_paths)?, coordinator.as_deref(), &new_session);
+    let entry = Entry::new(&output.account_id.0, output.reshare_message);
+
+    // New participants may already have combined the earlier message of this resharing, which is
+    // the case if it was made for the same signing share and new participants
+    let binding = ReshareMessage::from_bytes(&entry.message)?.dealing.binding;
+    let in_use = message_board
+        .entries(Collection::ReshareMessages)
+        .await?
+        .iter()
+        .filter(|earlier| earlier.sender == entry.sender)
+        .any(|earlier| ReshareMessage::from_bytes(&earlier.message).is_ok_and(|message| message.dealing.binding == binding));
+
+    message_board.check_replaceable(Collection::ReshareMessages, &entry, in_use, force).await?;
+    publish(&message_board, Collection::ReshareMessages, entry).await?;
+
+    println!("The owner of account {} reshared its signing share successfully!", output.account_id);
+    println!(
+        "The message to the {} new participants, with a threshold of {}, was written to: {}",
+        new_manifest.participants.len(),
+        new_manifest.threshold,
+        message_board.location(Collection::ReshareMessages),
+    );
+
+    Ok(())
+}
+
+/// Combines the messages of the current holders that deal into the signing share of a participant
+/// of the DKG session `session`, for the same threshold public key
+#[allow(clippy::too_many_arguments)]
+pub async fn reshare_round2(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: String,
+    coordinator: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+    threshold_public_key: Option<String>,
+    dealers: Vec<u16>,
+) -> Result<(), CliError> {
+    let (file_paths, manifest) = resolve_file_paths(&files, backend, Some(&session), SessionPurpose::Dkg).await?;
+    let manifest = manifest.expect("resolved with a session");
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let unlock = Unlock::new(password_file);
+
+    let secret_key_bytes = read_secret(&*storage, Artifact::ContributorSecretKey(participant), &unlock)?;
+    check_slot(&secret_key_bytes, expansion, &participant)?;
+
+    // The key to reshare must come from the new participant, not from the messages of the holders
+    let expected = match (threshold_public_key, storage.get(Artifact::ThresholdPublicKey)?) {
+        (Some(threshold_public_key), _) => AccountId32::from_str(&threshold_public_key)
+            .map_err(|e| CliError::input(format!("Invalid --threshold-public-key {}: {}", threshold_public_key, e)))?,
+        (None, Some(_)) => read_threshold_public_key(&*storage)?,
+        (None, None) => {
+            return Err(CliError::input(format!(
+                "The Threshold Public Key to reshare is unknown: pass --threshold-public-key, or write it to {}",
+                storage.location(Artifact::ThresholdPublicKey),
+            )));
+        }
+    };
+
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), &session);
+    let reshare_messages = message_board.fetch(Collection::ReshareMessages).await?;
+
+    let output = reshare_round2(ReshareRound2Input {
+        secret_key: &secret_key_bytes,
+        expansion,
+        new_recipients: &recipients,
+        new_threshold: manifest.threshold,
+        reshare_messages: &reshare_messages,
+        threshold_public_key: &expected,
+        dealers: (!dealers.is_empty()).then_some(&dealers[..]),
+    })
+    .map_err(|e| e.context(format!("Reshare round 2 failed for {}", participant)))?;
+
+    // A signing share from an earlier attempt must not be recoverable once it is replaced
+    unlock.new_password()?;
+    storage.wipe(Artifact::SigningShare(participant))?;
+    write_secret(&*storage, Artifact::SigningShare(participant), &output.signing_share[..], &unlock)?;
+    write_json(&*storage, Artifact::GenerationOutput(participant), &output.generation_output)?;
+    write_json(&*storage, Artifact::ThresholdPublicKey, &output.threshold_public_key)?;
+
+    publish(&message_board, Collection::GenerationOutputs, Entry::new(&participant.account, output.generation_output_bundle)).await?;
+
+    let dealers: Vec<String> = output.dealers.iter().map(u16::to_string).collect();
+
+    println!("The owner of account {} completed resharing successfully!", participant.ss58());
+    println!("The signing shares of the current holders {} were combined", dealers.join(", "));
+    println!("The output message was written to: {}", storage.location(Artifact::GenerationOutput(participant)));
+    println!("The signing share was written to: {}", storage.location(Artifact::SigningShare(participant)));
+    println!("The Threshold Public Key is still {} and was written to: {}", output.threshold_public_key, storage.location(Artifact::ThresholdPublicKey));
+    println!("Once every new participant has completed round 2, run `olaf-cli dkg certify --session {}`", session);
+
+    Ok(())
+}
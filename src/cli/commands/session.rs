@@ -0,0 +1,303 @@
+use rand::{rngs::OsRng, RngCore};
+use tokio::fs::{create_dir_all, read_to_string};
+use olaf_cli::{
+    files::FilePaths,
+    protocol::validate_recipients,
+    session::{list_manifests, manifest_exists, now, read_manifest, validate_session_id, write_manifest, SessionManifest, SessionPurpose, SessionStatus},
+    storage::{read_json, write_json, Artifact, Backend},
+};
+use crate::cli::CliError;
+
+/// Creates a new session with its manifest
+pub async fn session_new(
+    files: String,
+    backend: &Backend,
+    purpose: SessionPurpose,
+    id: Option<String>,
+    threshold: Option<u16>,
+    recipients: Option<String>,
+    dkg_session: Option<String>,
+) -> Result<(), CliError> {
+    let file_paths = FilePaths::new(&files);
+
+    let id = match id {
+        Some(id) => id,
+        None => {
+            let mut id_bytes = [0u8; 8];
+            OsRng.fill_bytes(&mut id_bytes);
+            hex::encode(id_bytes)
+        }
+    };
+    validate_session_id(&id)?;
+
+    if manifest_exists(&files, backend, &id).await? {
+        return Err(CliError::input(format!("Session {} already exists", id)));
+    }
+
+    let (participants, threshold) = match purpose {
+        SessionPurpose::Dkg => {
+            if dkg_session.is_some() {
+                return Err(CliError::input("--dkg-session is only valid for signing sessions".to_string()));
+            }
+
+            let participants: Vec<String> = match recipients {
+                Some(recipients_path) => {
+                    let recipients_string = read_to_string(&recipients_path)
+                        .await
+                        .map_err(|e| CliError::io(format!("Failed to read recipients file {:?}: {}", recipients_path, e)).with_source(e))?;
+                    serde_json::from_str(&recipients_string)
+                        .map_err(|e| CliError::serialization(format!("Failed to deserialize recipients data: {}", e)).with_source(e))?
+                }
+                None => read_json(&*backend.open(&file_paths)?, Artifact::Recipients)?,
+            };
+
+            let threshold = threshold
+                .ok_or_else(|| CliError::input("--threshold is required for DKG sessions".to_string()))?;
+
+            validate_recipients(&participants, threshold)?;
+
+            (participants, threshold)
+        }
+        SessionPurpose::Sign => {
+            if recipients.is_some() {
+                return Err(CliError::input("--recipients is only valid for DKG sessions, signing sessions take the participants of their DKG".to_string()));
+            }
+
+            match &dkg_session {
+                Some(dkg_session) => {
+                    let dkg_manifest = read_manifest(&files, backend, dkg_session).await?;
+                    if dkg_manifest.purpose != SessionPurpose::Dkg {
+                        return Err(CliError::input(format!("Session {} is not a DKG session", dkg_session)));
+                    }
+                    if threshold.is_some_and(|threshold| threshold != dkg_manifest.threshold) {
+                        return Err(CliError::input(format!(
+                            "--threshold does not match the threshold {} of DKG session {}",
+                            dkg_manifest.threshold, dkg_session
+                        )));
+                    }
+                    (dkg_manifest.participants, dkg_manifest.threshold)
+                }
+                None => {
+                    let participants: Vec<String> = read_json(&*backend.open(&file_paths)?, Artifact::Recipients)?;
+                    let threshold = threshold
+                        .ok_or_else(|| CliError::input("--threshold is required for signing sessions without --dkg-session".to_string()))?;
+
+                    validate_recipients(&participants, threshold)?;
+
+                    (participants, threshold)
+                }
+            }
+        }
+    };
+
+    let manifest = SessionManifest {
+        id: id.clone(),
+        created_at: now(),
+        purpose,
+        participants,
+        threshold,
+        dkg_session,
+        status: SessionStatus::Open,
+        closed_at: None,
+    };
+
+    // Only files need the session directory, a database keeps the artifacts under its path
+    if let Backend::Files = backend {
+        create_dir_all(file_paths.session_dir(&id))
+            .await
+            .map_err(|e| CliError::io(format!("Failed to create session directory: {}", e)).with_source(e))?;
+    }
+
+    write_manifest(&files, backend, &manifest).await?;
+
+    let session_storage = backend.open(&manifest.file_paths(&files))?;
+
+    if purpose == SessionPurpose::Dkg {
+        write_json(&*session_storage, Artifact::Recipients, &manifest.participants)?;
+    }
+
+    println!("Session {} was created in: {}", id, session_storage.location(Artifact::SessionManifest));
+    println!("Pass --session {} to the round commands of this {} ceremony", id, purpose);
+
+    Ok(())
+}
+
+/// Lists all sessions stored under the base directory
+pub async fn session_list(files: String, backend: &Backend) -> Result<(), CliError> {
+    let (manifests, warnings) = list_manifests(&files, backend).await?;
+
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    if manifests.is_empty() {
+        println!("There are no sessions in: {:?}", FilePaths::new(&files).sessions());
+        return Ok(());
+    }
+
+    for manifest in manifests {
+        println!(
+            "{}\t{}\t{}\t{}-of-{}\tcreated at {}",
+            manifest.id,
+            manifest.purpose,
+            manifest.status,
+            manifest.threshold,
+            manifest.participants.len(),
+            manifest.created_at,
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the manifest of a session and the artifacts stored for it
+pub async fn session_show(files: String, backend: &Backend, id: String) -> Result<(), CliError> {
+    let manifest = read_manifest(&files, backend, &id).await?;
+
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize manifest of session {}: {}", id, e)).with_source(e))?;
+
+    println!("{}", manifest_json);
+
+    let storage = backend.open(&manifest.file_paths(&files))?;
+
+    println!("Artifacts of session {}:", id);
+    for name in storage.stored()? {
+        println!("  {}", name);
+    }
+
+    Ok(())
+}
+
+/// Marks a session as closed, after which round commands refuse to use it
+pub async fn session_close(files: String, backend: &Backend, id: String) -> Result<(), CliError> {
+    let mut manifest = read_manifest(&files, backend, &id).await?;
+
+    if manifest.status == SessionStatus::Closed {
+        return Err(CliError::input(format!("Session {} is already closed", id)));
+    }
+
+    manifest.status = SessionStatus::Closed;
+    manifest.closed_at = Some(now());
+
+    write_manifest(&files, backend, &manifest).await?;
+
+    println!("Session {} was closed", id);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use schnorrkel::Keypair;
+    use sp_core::crypto::AccountId32;
+    use tempfile::TempDir;
+    use super::*;
+    use olaf_cli::session::resolve_file_paths;
+
+    /// Every backend, each over its own empty base directory holding three recipients
+    fn backends() -> Vec<(TempDir, String, Backend)> {
+        [false, true]
+            .into_iter()
+            .map(|sqlite| {
+                let dir = TempDir::new().unwrap();
+                let files = dir.path().to_string_lossy().into_owned();
+                let backend = match sqlite {
+                    false => Backend::Files,
+                    true => Backend::Sqlite(dir.path().join("artifacts.db")),
+                };
+
+                let recipients: Vec<String> = (0..3)
+                    .map(|_| AccountId32::from(Keypair::generate_with(OsRng).public.to_bytes()).to_string())
+                    .collect();
+                write_json(&*backend.open(&FilePaths::new(&files)).unwrap(), Artifact::Recipients, &recipients).unwrap();
+
+                (dir, files, backend)
+            })
+            .collect()
+    }
+
+    async fn new_session(files: &str, backend: &Backend, purpose: SessionPurpose, id: &str, threshold: Option<u16>, dkg_session: Option<&str>) -> Result<(), CliError> {
+        session_new(files.to_string(), backend, purpose, Some(id.to_string()), threshold, None, dkg_session.map(str::to_string)).await
+    }
+
+    #[tokio::test]
+    async fn sessions_are_created_listed_shown_and_closed() {
+        for (_dir, files, backend) in backends() {
+            new_session(&files, &backend, SessionPurpose::Dkg, "dkg", Some(2), None).await.unwrap();
+            new_session(&files, &backend, SessionPurpose::Sign, "sign", None, Some("dkg")).await.unwrap();
+
+            let manifests = list_manifests(&files, &backend).await.unwrap().0;
+            assert_eq!(manifests.iter().map(|manifest| manifest.id.as_str()).collect::<Vec<_>>(), ["dkg", "sign"]);
+            assert!(manifests.iter().all(|manifest| manifest.status == SessionStatus::Open && manifest.threshold == 2));
+            assert_eq!(manifests[1].participants, manifests[0].participants);
+
+            let dkg_manifest = read_manifest(&files, &backend, "dkg").await.unwrap();
+            let stored = backend.open(&dkg_manifest.file_paths(&files)).unwrap().stored().unwrap();
+            assert_eq!(stored, ["recipients.json", "session.json"]);
+
+            session_list(files.clone(), &backend).await.unwrap();
+            session_show(files.clone(), &backend, "sign".to_string()).await.unwrap();
+
+            session_close(files.clone(), &backend, "sign".to_string()).await.unwrap();
+
+            let closed = read_manifest(&files, &backend, "sign").await.unwrap();
+            assert_eq!(closed.status, SessionStatus::Closed);
+            assert!(closed.closed_at.is_some());
+
+            assert!(matches!(session_close(files.clone(), &backend, "sign".to_string()).await, Err(CliError::Input { .. })));
+            assert!(matches!(resolve_file_paths(&files, &backend, Some("sign"), SessionPurpose::Sign).await, Err(CliError::Input { .. })));
+            assert!(resolve_file_paths(&files, &backend, Some("dkg"), SessionPurpose::Dkg).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn manifests_are_kept_in_the_chosen_storage() {
+        let dir = TempDir::new().unwrap();
+        let files = dir.path().to_string_lossy().into_owned();
+        let sqlite = Backend::Sqlite(dir.path().join("artifacts.db"));
+
+        let recipients: Vec<String> = (0..2)
+            .map(|_| AccountId32::from(Keypair::generate_with(OsRng).public.to_bytes()).to_string())
+            .collect();
+        write_json(&*sqlite.open(&FilePaths::new(&files)).unwrap(), Artifact::Recipients, &recipients).unwrap();
+
+        new_session(&files, &sqlite, SessionPurpose::Dkg, "dkg", Some(2), None).await.unwrap();
+
+        assert!(!FilePaths::new(&files).session_dir("dkg").join("session.json").exists());
+        assert!(list_manifests(&files, &Backend::Files).await.unwrap().0.is_empty());
+        assert_eq!(list_manifests(&files, &sqlite).await.unwrap().0.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_session_id_is_only_used_once() {
+        for (_dir, files, backend) in backends() {
+            new_session(&files, &backend, SessionPurpose::Dkg, "dkg", Some(2), None).await.unwrap();
+
+            let again = new_session(&files, &backend, SessionPurpose::Dkg, "dkg", Some(3), None).await;
+            assert!(matches!(again, Err(CliError::Input { .. })));
+            assert_eq!(read_manifest(&files, &backend, "dkg").await.unwrap().threshold, 2);
+
+            assert!(matches!(read_manifest(&files, &backend, "missing").await, Err(CliError::Input { .. })));
+        }
+    }
+
+    #[tokio::test]
+    async fn the_threshold_must_be_between_2_and_the_participants() {
+        for (_dir, files, backend) in backends() {
+            for purpose in [SessionPurpose::Dkg, SessionPurpose::Sign] {
+                for threshold in [0, 1, 4] {
+                    let refused = new_session(&files, &backend, purpose, "refused", Some(threshold), None).await;
+                    assert!(matches!(refused, Err(CliError::Input { .. })), "{} session with threshold {}", purpose, threshold);
+                }
+            }
+            assert!(list_manifests(&files, &backend).await.unwrap().0.is_empty());
+
+            new_session(&files, &backend, SessionPurpose::Sign, "sign", Some(3), None).await.unwrap();
+            new_session(&files, &backend, SessionPurpose::Dkg, "dkg", Some(2), None).await.unwrap();
+
+            let mismatched = new_session(&files, &backend, SessionPurpose::Sign, "mismatched", Some(3), Some("dkg")).await;
+            assert!(matches!(mismatched, Err(CliError::Input { .. })));
+        }
+    }
+}
@@ -1,233 +1,780 @@
-use std::str::FromStr;
-use crate::{cli::{commands::value_into_composite, CliError}, files::FilePaths};
-use schnorrkel::olaf::{
-    multisig::{SigningCommitments, SigningNonces},
-    simplpedpop::SPPOutputMessage,
-    SigningKeypair,
+use olaf_cli::{
+    extrinsic::{offline_client, ExtrinsicInfo, Mortality, SigningRequest, TokenUnits},
+    inspect::{SPPOutputMessageView, SPPOutputView},
+    keystore::Unlock,
+    messages::{Collection, Entry, MessageBoard},
+    nonces::{add_precommitted_nonces, consume_nonce, consume_precommitted_nonce, write_nonce, NonceState},
+    participant::{Participant, ParticipantArg},
+    protocol::{
+        parse_signing_commitments, precommitted_signing_commitments, sign_round1, sign_round2, verifying_share,
+        PrecommitmentBundle, SignRound1Input, SignRound2Input,
+    },
+    session::{keys_session, resolve_file_paths, SessionPurpose, NO_SESSION},
+    storage::{read_json, write_json, Artifact, Backend, Storage},
 };
-use subxt::{
-    backend::{legacy::LegacyRpcMethods, rpc::RpcClient}, config::polkadot::PolkadotExtrinsicParamsBuilder, tx, utils::AccountId32, OnlineClient, PolkadotConfig
+use crate::cli::{
+    commands::{publish, read_secret, read_threshold_public_key, resolve_message, resolve_participant, SignedMessage},
+    CliError, TransactionArgs,
 };
-use tokio::{
-    fs::{read_to_string, File},
-    io::AsyncWriteExt,
+use subxt::{
+    backend::{legacy::LegacyRpcMethods, rpc::RpcClient}, OnlineClient, PolkadotConfig
 };
+use std::io::{stdin, IsTerminal, Write};
+use tokio::fs::read_to_string;
 
 #[subxt::subxt(runtime_metadata_path = "metadata.scale")]
 pub mod polkadot {}
 
 /// Generates the secret signing nonce and the corresponding public signing commitment of a participant
-pub async fn threshold_sign_round1(participant: u16, files: String) -> Result<(), CliError> {
-    let file_paths = FilePaths::new(files);
-
-    let signing_share_string = read_to_string(file_paths.signing_share(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to read signing share file for participant {}: {}", participant, e)))?;
-    
-    let signing_share_bytes: Vec<u8> = serde_json::from_str(&signing_share_string)
-        .map_err(|e| CliError(format!("Failed to deserialize signing share for participant {}: {}", participant, e)))?;
-    
-    let signing_share: SigningKeypair = SigningKeypair::from_bytes(&signing_share_bytes)
-        .map_err(|e| CliError(format!("Failed to parse signing share for participant {}: {}", participant, e)))?;
-
-    let (signing_nonces, signing_commitments) = signing_share.commit();
-
-    let signing_nonces_json = serde_json::to_string(&signing_nonces.to_bytes().to_vec())
-        .map_err(|e| CliError(format!("Failed to serialize signing nonces for participant {}: {}", participant, e)))?;
-    
-    let mut signing_nonces_file = File::create(file_paths.signing_nonce(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to create signing nonce file for participant {}: {}", participant, e)))?;
-    
-    signing_nonces_file
-        .write_all(signing_nonces_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write signing nonces to file for participant {}: {}", participant, e)))?;
-
-    let mut signing_commitments_vec: Vec<Vec<u8>> = if tokio::fs::metadata(file_paths.signing_commitments()).await.is_ok() {
-        let existing_content = read_to_string(file_paths.signing_commitments())
-            .await
-            .map_err(|e| CliError(format!("Failed to read existing signing commitments file: {}", e)))?;
-        serde_json::from_str(&existing_content)
-            .map_err(|e| CliError(format!("Failed to deserialize existing signing commitments: {}", e)))?
-    } else {
-        Vec::new()
-    };
-    signing_commitments_vec.push(signing_commitments.to_bytes().to_vec());
+pub async fn threshold_sign_round1(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    password_file: Option<String>,
+    force: bool,
+) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
+    let unlock = Unlock::new(password_file);
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let signing_share_bytes = read_secret(&*storage, Artifact::SigningShare(participant), &unlock)?;
+
+    let output = sign_round1(SignRound1Input { signing_share: &signing_share_bytes })
+        .map_err(|e| e.context(format!("Round 1 failed for {}", participant)))?;
+
+    let nonce_session = session.as_deref().unwrap_or(NO_SESSION);
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), nonce_session);
 
-    let signing_commitments_json = serde_json::to_string(&signing_commitments_vec)
-        .map_err(|e| CliError(format!("Failed to serialize signing commitments: {}", e)))?;
+    // Signing packages over the earlier commitments may already exist. Checked before the nonce
+    // is replaced, so a refused re-run leaves the earlier nonce and commitment usable.
+    let in_use = !message_board.entries(Collection::SigningPackages).await?.is_empty();
+    let entry = Entry::new(&output.verifying_share.to_bytes(), output.signing_commitments.to_bytes().to_vec());
+    message_board.check_replaceable(Collection::SigningCommitments, &entry, in_use, force).await?;
 
-    let mut signing_commitments_file = File::create(file_paths.signing_commitments())
-        .await
-        .map_err(|e| CliError(format!("Failed to create signing commitments file: {}", e)))?;
-    
-    signing_commitments_file
-        .write_all(signing_commitments_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write signing commitments to file: {}", e)))?;
+    write_nonce(&*storage, participant, nonce_session, &output.signing_nonces, &output.signing_commitments, &unlock)?;
+
+    publish(&message_board, Collection::SigningCommitments, entry).await?;
 
     println!("Round 1 of threshold signing was completed successfully!");
-    println!("Signing nonce was written to: {:?}", file_paths.signing_nonce(participant));
+    println!("Signing nonce was written to: {}", storage.location(Artifact::SigningNonce(participant)));
     println!(
-        "Signing commitment was written to: {:?}",
-        file_paths.signing_commitments()
+        "Signing commitment was written to: {}",
+        message_board.location(Collection::SigningCommitments)
     );
 
     Ok(())
 }
 
+/// Generates `count` signing nonces of a participant ahead of any signing session, and publishes
+/// their commitments, with the ones it precommitted earlier, in one bundle. Signing sessions that
+/// pick one of their indices skip round 1.
+#[allow(clippy::too_many_arguments)]
+pub async fn threshold_sign_precommit(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    count: u32,
+    password_file: Option<String>,
+    force: bool,
+) -> Result<(), CliError> {
+    if count == 0 {
+        return Err(CliError::input("--count must be at least 1".to_string()));
+    }
+
+    let (file_paths, manifest) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
+    let unlock = Unlock::new(password_file);
+
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let signing_share_bytes = read_secret(&*storage, Artifact::SigningShare(participant), &unlock)?;
+
+    let generated = (0..count)
+        .map(|_| {
+            sign_round1(SignRound1Input { signing_share: &signing_share_bytes })
+                .map(|output| (output.signing_nonces, output.signing_commitments))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.context(format!("Precommitting failed for {}", participant)))?;
+
+    // Precommitted commitments are shared by every signing session using the same key material
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), keys_session(manifest.as_ref()));
+
+    let nonces = add_precommitted_nonces(&*storage, participant, &generated, &unlock)?;
+
+    let bundle = PrecommitmentBundle {
+        commitments: nonces
+            .iter()
+            .map(|nonce| {
+                hex::decode(&nonce.commitment)
+                    .map(|commitment| (nonce.index, commitment))
+                    .map_err(|e| CliError::serialization(format!("Failed to decode precommitted signing commitment: {}", e)).with_source(e))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    // Signing sessions may already use the earlier commitments, which is only safe if the new
+    // bundle keeps all of them, as it does unless the precommitted nonces were lost
+    let entry = Entry::new(&verifying_share(&signing_share_bytes)?.to_bytes(), bundle.to_bytes());
+    let in_use = message_board
+        .entries(Collection::Precommitments)
+        .await?
+        .iter()
+        .filter(|earlier| earlier.sender == entry.sender)
+        .any(|earlier| PrecommitmentBundle::from_bytes(&earlier.message).map_or(true, |earlier| !bundle.extends(&earlier)));
+
+    message_board.check_replaceable(Collection::Precommitments, &entry, in_use, force).await?;
+    publish(&message_board, Collection::Precommitments, entry).await?;
+
+    let unused = nonces.iter().filter(|nonce| matches!(nonce.state, NonceState::Unused { .. })).count();
+    let last_index = nonces.last().map_or(0, |nonce| nonce.index);
+
+    println!("Precommitted {} signing nonces of {}, up to index {}", count, participant, last_index);
+    println!("{} precommitted signing nonces are unused", unused);
+    println!("Signing nonces were written to: {}", storage.location(Artifact::PrecommittedNonces(participant)));
+    println!("Signing commitments were written to: {}", message_board.location(Collection::Precommitments));
+
+    Ok(())
+}
+
 /// Generates the signing package of a participant from: 
 /// - its secret signing nonce
 /// - the public signing commitments of all participants (including itself)
 /// - the public output of round 2 of the generation of the threshold public key
 /// - the threshold public key
+///
+/// If a signing request exists, its extrinsic is signed instead of the one given by the arguments,
+/// after checking that the rebuilt payload matches the proposed one.
+///
+/// With a nonce index, from the arguments or the signing request, the precommitted signing nonce
+/// at that index is used instead of the one of round 1.
+#[allow(clippy::too_many_arguments)]
 pub async fn threshold_sign_round2(
-    participant: u16,
+    participant: ParticipantArg,
     files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    signing_request: Option<String>,
     url: String,
     pallet: String,
     call_name: String,
     call_data: String,
     context: String,
+    transaction: TransactionArgs,
+    password_file: Option<String>,
+    nonce_index: Option<u32>,
 ) -> Result<(), CliError> {
-    let file_paths = FilePaths::new(files);
-
-    let signing_commitments_string = read_to_string(file_paths.signing_commitments())
-        .await
-        .map_err(|e| CliError(format!("Failed to read signing commitments file: {}", e)))?;
-    
-    let signing_commitments_bytes: Vec<Vec<u8>> = serde_json::from_str(&signing_commitments_string)
-        .map_err(|e| CliError(format!("Failed to deserialize signing commitments: {}", e)))?;
-    
-    let signing_commitments: Vec<SigningCommitments> = signing_commitments_bytes
-        .iter()
-        .map(|sc| SigningCommitments::from_bytes(sc)
-            .map_err(|e| CliError(format!("Failed to parse SigningCommitments: {:?}", e))))
-        .collect::<Result<_, _>>()?;
-
-    let signing_nonces_string = read_to_string(file_paths.signing_nonce(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to read signing nonce file for participant {}: {}", participant, e)))?;
-    
-    let signing_nonces_bytes: Vec<u8> = serde_json::from_str(&signing_nonces_string)
-        .map_err(|e| CliError(format!("Failed to deserialize signing nonces for participant {}: {}", participant, e)))?;
-    
-    let signing_nonces = SigningNonces::from_bytes(&signing_nonces_bytes)
-        .map_err(|e| CliError(format!("Failed to parse signing nonces for participant {}: {:?}", participant, e)))?;
-
-    let signing_share_string = read_to_string(file_paths.signing_share(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to read signing share file for participant {}: {}", participant, e)))?;
-    
-    let signing_share_bytes: Vec<u8> = serde_json::from_str(&signing_share_string)
-        .map_err(|e| CliError(format!("Failed to deserialize signing share for participant {}: {}", participant, e)))?;
-    
-    let signing_share = SigningKeypair::from_bytes(&signing_share_bytes)
-        .map_err(|e| CliError(format!("Failed to parse signing share for participant {}: {}", participant, e)))?;
-
-    let output_string = read_to_string(file_paths.generation_output(participant))
-        .await
-        .map_err(|e| CliError(format!("Failed to read generation output file for participant {}: {}", participant, e)))?;
-    
-    let output_bytes: Vec<u8> = serde_json::from_str(&output_string)
-        .map_err(|e| CliError(format!("Failed to deserialize generation output for participant {}: {}", participant, e)))?;
-    
-    let generation_output = SPPOutputMessage::from_bytes(&output_bytes)
-        .map_err(|e| CliError(format!("Failed to parse generation output for participant {}: {:?}", participant, e)))?;
-
-    let threshold_public_key_string = read_to_string(file_paths.threshold_public_key())
-        .await
-        .map_err(|e| CliError(format!("Failed to read threshold public key file: {}", e)))?;
-    
-    let account_id = AccountId32::from_str(&serde_json::from_str::<String>(&threshold_public_key_string)
-        .map_err(|e| CliError(format!("Failed to parse threshold public key: {}", e)))?)
-        .map_err(|e| CliError(format!("Invalid threshold public key format: {}", e)))?;
-
-    let client = OnlineClient::<PolkadotConfig>::from_url(&url)
-        .await
-        .map_err(|e| CliError(format!("Failed to connect to URL {}: {}", url, e)))?;
-    
-    let rpc_client = RpcClient::from_url(&url)
-        .await
-        .map_err(|e| CliError(format!("Failed to create RPC client from URL {}: {}", url, e)))?;
-    
-    let legacy_rpc = LegacyRpcMethods::<PolkadotConfig>::new(rpc_client);
-
-    let value = scale_value::stringify::from_str(&call_data).0.unwrap();
-    let value_as_composite = value_into_composite(value);
-
-    let call = tx::dynamic(&pallet, &call_name, value_as_composite);
-
-    let nonce = legacy_rpc.system_account_next_index(&account_id)
-        .await
-        .map_err(|e| CliError(format!("Failed to fetch nonce for account {}: {}", account_id, e)))?;
-    
-    let params = PolkadotExtrinsicParamsBuilder::new().nonce(nonce).build();
-    let partial_tx = client.tx().create_partial_signed_offline(&call, params)
-        .map_err(|e| CliError(format!("Failed to create partial signed transaction: {}", e)))?;
-    
-    let payload = partial_tx.signer_payload().to_vec();
-
-    let signing_package = signing_share.sign(
-        context.as_bytes().to_vec(),
-        payload,
-        generation_output.spp_output(),
-        signing_commitments,
-        &signing_nonces,
-    )
-    .map_err(|e| CliError(format!("Failed to create signing package: {:?}", e)))?;
-
-    let mut signing_packages_vec: Vec<Vec<u8>> = if tokio::fs::metadata(file_paths.signing_packages()).await.is_ok() {
-        let existing_content = read_to_string(file_paths.signing_packages())
-            .await
-            .map_err(|e| CliError(format!("Failed to read existing signing packages file: {}", e)))?;
-        serde_json::from_str(&existing_content)
-            .map_err(|e| CliError(format!("Failed to deserialize existing signing packages: {}", e)))?
-    } else {
-        Vec::new()
+    let (file_paths, manifest) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
+    let nonce_session = session.as_deref().unwrap_or(NO_SESSION);
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), nonce_session);
+    let precommitments = MessageBoard::new(storage.clone(), coordinator.as_deref(), keys_session(manifest.as_ref()));
+    let unlock = Unlock::new(password_file);
+
+    let signing_request = read_signing_request(&*storage, signing_request).await?;
+
+    let nonce_index = match (nonce_index, signing_request.as_ref().and_then(|request| request.nonce_index)) {
+        (Some(index), Some(pinned)) if index != pinned => {
+            return Err(CliError::input(format!(
+                "The signing request pins the precommitted signing nonces at index {}, not {}",
+                pinned, index
+            )));
+        }
+        (index, pinned) => index.or(pinned),
     };
-    signing_packages_vec.push(signing_package.to_bytes());
-
-    let signing_package_json = serde_json::to_string(&signing_packages_vec)
-        .map_err(|e| CliError(format!("Failed to serialize signing packages: {}", e)))?;
-
-    let mut signing_package_file = File::create(file_paths.signing_packages())
-        .await
-        .map_err(|e| CliError(format!("Failed to create signing packages file: {}", e)))?;
-    
-    signing_package_file
-        .write_all(signing_package_json.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write signing packages to file: {}", e)))?;
-
-    let extrinsic_info = serde_json::json!({
-        "url": url,
-        "pallet": pallet,
-        "call_name": call_name,
-        "call_data": call_data,
-    });
-
-    let extrinsic_args_string = serde_json::to_string(&extrinsic_info)
-        .map_err(|e| CliError(format!("Failed to serialize extrinsic info: {}", e)))?;
-    
-    let mut extrinsic_args_file = File::create(file_paths.extrinsic_info())
-        .await
-        .map_err(|e| CliError(format!("Failed to create extrinsic info file: {}", e)))?;
-    
-    extrinsic_args_file
-        .write_all(extrinsic_args_string.as_bytes())
-        .await
-        .map_err(|e| CliError(format!("Failed to write extrinsic info to file: {}", e)))?;
+    let nonce_source = NonceSource::new(nonce_session, &precommitments, nonce_index);
+
+    let signers = match &signing_request {
+        Some(request) => chosen_signers(&*storage, request, participant)?,
+        None => Vec::new(),
+    };
+
+    let (mut extrinsic_info, context, transaction) = match &signing_request {
+        Some(request) => (request.extrinsic.clone(), request.context.clone(), pin_transaction(transaction, request)),
+        None => (
+            ExtrinsicInfo {
+                url,
+                pallet,
+                call_name,
+                call_data,
+                nonce: None,
+                tip: transaction.tip,
+                mortality: None,
+            },
+            context,
+            transaction,
+        ),
+    };
+
+    let prepared = prepare_extrinsic(&*storage, &mut extrinsic_info, transaction).await?;
+
+    if let Some(request) = &signing_request {
+        request.check(&prepared.genesis_hash, prepared.spec_version, prepared.transaction_version, &prepared.payload)?;
+        println!("The payload matches signing request {}", request.payload_hash);
+    }
+
+    sign_and_publish(&*storage, &message_board, participant, &signers, nonce_source, &context, prepared.payload, &unlock).await?;
+
+    write_json(&*storage, Artifact::ExtrinsicInfo, &extrinsic_info)?;
 
     println!("Round 2 of threshold signing was completed successfully!");
-    println!("Signing package was written to: {:?}", file_paths.signing_packages());
+    if let Some(index) = nonce_index {
+        println!("Signed with the precommitted signing nonce at index {}", index);
+    }
+    println!("Signing package was written to: {}", message_board.location(Collection::SigningPackages));
     println!(
-        "Extrinsic info was written to: {:?}",
-        file_paths.extrinsic_info()
+        "Extrinsic info was written to: {}",
+        storage.location(Artifact::ExtrinsicInfo)
     );
 
     Ok(())
 }
 
+/// The signer payload of an extrinsic, and the chain it was built for
+pub(crate) struct PreparedExtrinsic {
+    pub payload: Vec<u8>,
+    pub genesis_hash: String,
+    pub spec_version: u32,
+    pub transaction_version: u32,
+}
+
+/// Resolves the nonce and mortality of `extrinsic_info`, from the arguments or from the node,
+/// has the extrinsic reviewed and builds its signer payload
+pub(crate) async fn prepare_extrinsic(
+    storage: &dyn Storage,
+    extrinsic_info: &mut ExtrinsicInfo,
+    transaction: TransactionArgs,
+) -> Result<PreparedExtrinsic, CliError> {
+    if transaction.offline {
+        let (Some(nonce), Some(genesis_hash), Some(spec_version), Some(transaction_version)) = (
+            transaction.nonce,
+            transaction.genesis_hash.as_deref(),
+            transaction.spec_version,
+            transaction.transaction_version,
+        ) else {
+            return Err(CliError::input("--offline requires --nonce, --genesis-hash, --spec-version and --transaction-version".to_string()));
+        };
+
+        let client = offline_client(genesis_hash, spec_version, transaction_version)?;
+
+        extrinsic_info.nonce = Some(nonce);
+        extrinsic_info.mortality = match (transaction.mortality_period, transaction.mortality_block_number, transaction.mortality_block_hash) {
+            (None, _, _) => None,
+            (Some(period), Some(block_number), Some(block_hash)) => Some(Mortality { period, block_number, block_hash }),
+            _ => return Err(CliError::input("An offline mortal extrinsic requires --mortality-block-number and --mortality-block-hash".to_string())),
+        };
+
+        let units = transaction.token_decimals
+            .zip(transaction.token_symbol.clone())
+            .map(|(decimals, symbol)| TokenUnits { decimals, symbol });
+
+        confirm(&extrinsic_info.review(&client, nonce, units.as_ref())?, transaction.yes)?;
+
+        Ok(PreparedExtrinsic {
+            payload: extrinsic_info.partial_extrinsic(&client, nonce)?.signer_payload().to_vec(),
+            genesis_hash: format!("0x{}", hex::encode(client.genesis_hash())),
+            spec_version,
+            transaction_version,
+        })
+    } else {
+        let client = OnlineClient::<PolkadotConfig>::from_url(&extrinsic_info.url)
+            .await
+            .map_err(|e| CliError::rpc(format!("Failed to connect to URL {}: {}", extrinsic_info.url, e)).with_source(e))?;
+
+        let rpc_client = RpcClient::from_url(&extrinsic_info.url)
+            .await
+            .map_err(|e| CliError::rpc(format!("Failed to create RPC client from URL {}: {}", extrinsic_info.url, e)).with_source(e))?;
+
+        let legacy_rpc = LegacyRpcMethods::<PolkadotConfig>::new(rpc_client);
+
+        let nonce = match transaction.nonce {
+            Some(nonce) => nonce,
+            None => {
+                let account_id = read_threshold_public_key(storage)?;
+
+                legacy_rpc
+                    .system_account_next_index(&account_id)
+                    .await
+                    .map_err(|e| CliError::rpc(format!("Failed to fetch nonce for account {}: {}", account_id, e)).with_source(e))?
+            }
+        };
+
+        extrinsic_info.nonce = Some(nonce);
+        extrinsic_info.mortality = match (transaction.mortality_period, transaction.mortality_block_number, transaction.mortality_block_hash) {
+            (None, _, _) => None,
+            (Some(period), Some(block_number), Some(block_hash)) => Some(Mortality { period, block_number, block_hash }),
+            (Some(period), None, None) => {
+                let block = client
+                    .blocks()
+                    .at_latest()
+                    .await
+                    .map_err(|e| CliError::rpc(format!("Failed to fetch the latest block from URL {}: {}", extrinsic_info.url, e)).with_source(e))?;
+
+                Some(Mortality {
+                    period,
+                    block_number: block.number().into(),
+                    block_hash: format!("0x{}", hex::encode(block.hash())),
+                })
+            }
+            _ => return Err(CliError::input("Give both or neither of --mortality-block-number and --mortality-block-hash".to_string())),
+        };
+
+        let units = match transaction.token_decimals.zip(transaction.token_symbol.clone()) {
+            Some((decimals, symbol)) => Some(TokenUnits { decimals, symbol }),
+            None => legacy_rpc
+                .system_properties()
+                .await
+                .ok()
+                .and_then(|properties| TokenUnits::from_properties(&properties)),
+        };
+
+        confirm(&extrinsic_info.review(&client, nonce, units.as_ref())?, transaction.yes)?;
+
+        let runtime_version = client.runtime_version();
+
+        Ok(PreparedExtrinsic {
+            payload: extrinsic_info.partial_extrinsic(&client, nonce)?.signer_payload().to_vec(),
+            genesis_hash: format!("0x{}", hex::encode(client.genesis_hash())),
+            spec_version: runtime_version.spec_version,
+            transaction_version: runtime_version.transaction_version,
+        })
+    }
+}
+
+/// Reads the signing request at `path`, or the one of the session if it exists
+async fn read_signing_request(storage: &dyn Storage, path: Option<String>) -> Result<Option<SigningRequest>, CliError> {
+    let signing_request = match path {
+        Some(path) => {
+            let signing_request_string = read_to_string(&path)
+                .await
+                .map_err(|e| CliError::io(format!("Failed to read signing request file {:?}: {}", path, e)).with_source(e))?;
+
+            let signing_request = serde_json::from_str(&signing_request_string)
+                .map_err(|e| CliError::serialization(format!("Failed to parse signing request {:?}: {}", path, e)).with_source(e))?;
+
+            println!("Signing the extrinsic of the signing request at {:?}", path);
+
+            signing_request
+        }
+        None if storage.get(Artifact::SigningRequest)?.is_some() => {
+            println!("Signing the extrinsic of the signing request at {}", storage.location(Artifact::SigningRequest));
+
+            read_json(storage, Artifact::SigningRequest)?
+        }
+        None => return Ok(None),
+    };
+
+    Ok(Some(signing_request))
+}
+
+/// The signers chosen by the signing request, if it chooses any. Fails if `participant` is not
+/// one of them, since its signature share would not be aggregated.
+fn chosen_signers(storage: &dyn Storage, request: &SigningRequest, participant: Participant) -> Result<Vec<Participant>, CliError> {
+    let signers = request
+        .signers
+        .iter()
+        .map(|signer| resolve_participant(storage, &ParticipantArg::Account(signer.clone())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !signers.is_empty() && !signers.contains(&participant) {
+        return Err(CliError::input(format!(
+            "{} is not among the signers chosen by the signing request: {}",
+            participant,
+            request.signers.join(", "),
+        )));
+    }
+
+    Ok(signers)
+}
+
+/// Replaces the transaction parameters given by a participant with the ones pinned by the signing request.
+/// Whether to build the payload offline and how to review it remain the choice of the participant.
+fn pin_transaction(transaction: TransactionArgs, request: &SigningRequest) -> TransactionArgs {
+    let mortality = request.extrinsic.mortality.clone();
+
+    TransactionArgs {
+        offline: transaction.offline,
+        nonce: request.extrinsic.nonce,
+        tip: request.extrinsic.tip,
+        genesis_hash: Some(request.genesis_hash.clone()),
+        spec_version: Some(request.spec_version),
+        transaction_version: Some(request.transaction_version),
+        mortality_period: mortality.as_ref().map(|mortality| mortality.period),
+        mortality_block_number: mortality.as_ref().map(|mortality| mortality.block_number),
+        mortality_block_hash: mortality.map(|mortality| mortality.block_hash),
+        token_decimals: transaction.token_decimals,
+        token_symbol: transaction.token_symbol,
+        yes: transaction.yes,
+    }
+}
+
+/// Generates the signing package of a participant over an arbitrary message instead of an extrinsic.
+/// Works fully offline: no node is contacted.
+#[allow(clippy::too_many_arguments)]
+pub async fn threshold_sign_message(
+    participant: ParticipantArg,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    message: Option<String>,
+    message_file: Option<String>,
+    message_hex: Option<String>,
+    context: String,
+    password_file: Option<String>,
+    nonce_index: Option<u32>,
+) -> Result<(), CliError> {
+    let (file_paths, manifest) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
+    let nonce_session = session.as_deref().unwrap_or(NO_SESSION);
+    let storage = backend.open(&file_paths)?;
+    let participant = resolve_participant(&*storage, &participant)?;
+    let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), nonce_session);
+    let precommitments = MessageBoard::new(storage.clone(), coordinator.as_deref(), keys_session(manifest.as_ref()));
+    let unlock = Unlock::new(password_file);
+
+    let message = resolve_message(message, message_file, message_hex)
+        .await?
+        .ok_or_else(|| CliError::input("One of --message, --message-file or --message-hex is required".to_string()))?;
+
+    let nonce_source = NonceSource::new(nonce_session, &precommitments, nonce_index);
+    sign_and_publish(&*storage, &message_board, participant, &[], nonce_source, &context, message.clone(), &unlock).await?;
+
+    let signed_message = SignedMessage {
+        context,
+        message: hex::encode(&message),
+    };
+
+    write_json(&*storage, Artifact::SignedMessage, &signed_message)?;
+
+    println!("Round 2 of threshold message signing was completed successfully!");
+    println!("Signing package was written to: {}", message_board.location(Collection::SigningPackages));
+    println!("Signed message was written to: {}", storage.location(Artifact::SignedMessage));
+
+    Ok(())
+}
+
+/// Prints the reviewed extrinsic and asks the participant to confirm it, unless `yes` is set
+fn confirm(review: &str, yes: bool) -> Result<(), CliError> {
+    println!("Extrinsic to sign:\n{}", review);
+
+    if yes {
+        return Ok(());
+    }
+
+    if !stdin().is_terminal() {
+        return Err(CliError::input("Signing requires confirmation: run interactively or pass --yes".to_string()));
+    }
+
+    print!("Sign this extrinsic? [y/N] ");
+    std::io::stdout()
+        .flush()
+        .map_err(|e| CliError::io(format!("Failed to write confirmation prompt: {}", e)).with_source(e))?;
+
+    let mut answer = String::new();
+    stdin()
+        .read_line(&mut answer)
+        .map_err(|e| CliError::io(format!("Failed to read confirmation: {}", e)).with_source(e))?;
+
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(CliError::input("Signing was not confirmed".to_string())),
+    }
+}
+
+/// Where the single-use signing nonce of a signature comes from, with the signing commitments of
+/// all signers
+enum NonceSource<'a> {
+    /// The nonce of round 1 of a signing session, with the commitments of its round 1
+    Session(&'a str),
+    /// A precommitted nonce, with the commitments precommitted at the same index
+    Precommitted { precommitments: &'a MessageBoard, index: u32 },
+}
+
+impl<'a> NonceSource<'a> {
+    fn new(session: &'a str, precommitments: &'a MessageBoard, index: Option<u32>) -> Self {
+        match index {
+            Some(index) => NonceSource::Precommitted { precommitments, index },
+            None => NonceSource::Session(session),
+        }
+    }
+}
+
+/// Signs `payload` with the signing share and the single-use signing nonce of a participant,
+/// together with `signers`, or with every participant that published signing commitments if
+/// empty, and publishes the resulting signing package
+#[allow(clippy::too_many_arguments)]
+async fn sign_and_publish(
+    storage: &dyn Storage,
+    message_board: &MessageBoard,
+    participant: Participant,
+    signers: &[Participant],
+    nonce_source: NonceSource<'_>,
+    context: &str,
+    payload: Vec<u8>,
+    unlock: &Unlock,
+) -> Result<(), CliError> {
+    let output_bytes: Vec<u8> = read_json(storage, Artifact::GenerationOutput(participant))?;
+    let spp_output = SPPOutputView::parse(SPPOutputMessageView::parse(&output_bytes)?.spp_output)?;
+    let signing_share_bytes = read_secret(storage, Artifact::SigningShare(participant), unlock)?;
+
+    // Signers are found in the `SPPOutput` by their position among the recipients, which only
+    // holds if the local recipients are in the order the key was generated for
+    if spp_output.position(&verifying_share(&signing_share_bytes)?.to_bytes()) != Some(participant.index as usize - 1) {
+        return Err(CliError::protocol(format!(
+            "The verifying share of {} is not at its position in the generation output: the recipients are not the ones, \
+             in the same order, the Threshold Public Key was generated for",
+            participant,
+        )));
+    }
+
+    let signing_commitments_bytes = select_signing_commitments(message_board, signers, &spp_output, &nonce_source).await?;
+    let signing_commitments = parse_signing_commitments(&signing_commitments_bytes)?;
+
+    let signing_nonces = match nonce_source {
+        NonceSource::Session(session) => consume_nonce(storage, participant, session, &signing_commitments, &payload, unlock)?,
+        NonceSource::Precommitted { index, .. } => {
+            consume_precommitted_nonce(storage, participant, index, &signing_commitments, &payload, unlock)?
+        }
+    };
+
+    let output = sign_round2(SignRound2Input {
+        signing_share: &signing_share_bytes,
+        generation_output: &output_bytes,
+        signing_commitments: &signing_commitments_bytes,
+        signing_nonces: &signing_nonces,
+        context: context.as_bytes(),
+        payload,
+    })
+    .map_err(|e| e.context(format!("Round 2 failed for {}", participant)))?;
+
+    publish(message_board, Collection::SigningPackages, Entry::new(&output.verifying_share.to_bytes(), output.signing_package)).await?;
+
+    Ok(())
+}
+
+/// The signing commitments to sign with: the ones of `signers`, or of every participant that
+/// published some if empty. Fails if a chosen signer has none, or if fewer than the threshold of
+/// `spp_output` are available.
+async fn select_signing_commitments(
+    message_board: &MessageBoard,
+    signers: &[Participant],
+    spp_output: &SPPOutputView,
+    nonce_source: &NonceSource<'_>,
+) -> Result<Vec<Vec<u8>>, CliError> {
+    let (board, collection) = match nonce_source {
+        NonceSource::Session(_) => (message_board, Collection::SigningCommitments),
+        NonceSource::Precommitted { precommitments, .. } => (*precommitments, Collection::Precommitments),
+    };
+
+    let mut entries = board.entries(collection).await?;
+
+    // Entries are keyed by the verifying share of their sender, which is at the position of the
+    // signer among the recipients in the `SPPOutput`, as `sign_and_publish` checked
+    if !signers.is_empty() {
+        let verifying_shares = signers
+            .iter()
+            .map(|signer| {
+                (signer.index as usize)
+                    .checked_sub(1)
+                    .and_then(|position| spp_output.verifying_shares.get(position))
+                    .map(|(_, verifying_share)| hex::encode(verifying_share))
+                    .ok_or_else(|| CliError::protocol(format!("The generation output has no verifying share for {}", signer)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let missing: Vec<String> = signers
+            .iter()
+            .zip(&verifying_shares)
+            .filter(|(_, verifying_share)| !entries.iter().any(|entry| entry.sender.as_ref() == Some(*verifying_share)))
+            .map(|(signer, _)| signer.to_string())
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(CliError::protocol(format!(
+                "No signing commitments of the chosen signers {} were found in {}",
+                missing.join(", "),
+                board.location(collection),
+            )));
+        }
+
+        entries.retain(|entry| entry.sender.as_ref().is_some_and(|sender| verifying_shares.contains(sender)));
+    }
+
+    let messages: Vec<Vec<u8>> = entries.into_iter().map(|entry| entry.message).collect();
+
+    let signing_commitments = match nonce_source {
+        NonceSource::Session(_) => messages,
+        NonceSource::Precommitted { index, .. } => {
+            let signing_commitments = precommitted_signing_commitments(&messages, *index)?;
+
+            if !signers.is_empty() && signing_commitments.len() != signers.len() {
+                return Err(CliError::protocol(format!(
+                    "Not every chosen signer precommitted signing commitments at index {}",
+                    index
+                )));
+            }
+
+            signing_commitments
+        }
+    };
+
+    if signing_commitments.len() < spp_output.threshold as usize {
+        return Err(CliError::protocol(format!(
+            "Only {} signing commitments are available in {}, but signing needs at least {}, the threshold",
+            signing_commitments.len(),
+            board.location(collection),
+            spp_output.threshold,
+        )));
+    }
+
+    Ok(signing_commitments)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use sp_core::crypto::{AccountId32, Ss58Codec};
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use super::*;
+    use olaf_cli::{
+        coordinator::{client::CoordinatorClient, server::serve},
+        files::FilePaths,
+        storage::MemoryStorage,
+    };
+
+    const SESSION: NonceSource<'static> = NonceSource::Session("signing");
+
+    fn signer(index: u16) -> Participant {
+        Participant { index, account: [index as u8; 32] }
+    }
+
+    /// An `SPPOutput` of three participants with a threshold of two, and a board holding the signing
+    /// commitments of each of them, keyed by their verifying shares
+    fn board_of_three() -> (SPPOutputView, MessageBoard) {
+        let spp_output = SPPOutputView {
+            participants: 3,
+            threshold: 2,
+            threshold_public_key: [0u8; 32],
+            verifying_shares: (1..=3).map(|index| ([index; 32], [index + 10; 32])).collect(),
+        };
+
+        let storage = Arc::new(MemoryStorage::new());
+        for (_, verifying_share) in &spp_output.verifying_shares {
+            storage.publish(Collection::SigningCommitments, Entry::new(verifying_share, verifying_share.to_vec())).unwrap();
+        }
+
+        (spp_output, MessageBoard::Storage(storage))
+    }
+
+    #[tokio::test]
+    async fn every_signer_signs_without_a_choice() {
+        let (spp_output, board) = board_of_three();
+
+        let selected = select_signing_commitments(&board, &[], &spp_output, &SESSION).await.unwrap();
+        assert_eq!(selected, [vec![11u8; 32], vec![12u8; 32], vec![13u8; 32]]);
+    }
 
+    #[tokio::test]
+    async fn commitments_of_unchosen_signers_are_dropped() {
+        let (spp_output, board) = board_of_three();
+
+        let selected = select_signing_commitments(&board, &[signer(3), signer(1)], &spp_output, &SESSION).await.unwrap();
+        assert_eq!(selected, [vec![11u8; 32], vec![13u8; 32]]);
+    }
+
+    #[tokio::test]
+    async fn a_chosen_signer_without_commitments_is_refused() {
+        let (mut spp_output, board) = board_of_three();
+        spp_output.verifying_shares[1].1 = [20u8; 32];
+
+        let refused = select_signing_commitments(&board, &[signer(1), signer(2)], &spp_output, &SESSION).await;
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+    }
+
+    #[tokio::test]
+    async fn a_signer_outside_the_key_is_refused() {
+        let (spp_output, board) = board_of_three();
+
+        for outsider in [0, 4] {
+            let refused = select_signing_commitments(&board, &[signer(1), signer(outsider)], &spp_output, &SESSION).await;
+            assert!(matches!(refused, Err(CliError::Protocol { .. })));
+        }
+    }
+
+    #[tokio::test]
+    async fn fewer_commitments_than_the_threshold_are_refused() {
+        let (mut spp_output, board) = board_of_three();
+
+        spp_output.threshold = 3;
+        let refused = select_signing_commitments(&board, &[signer(1), signer(2)], &spp_output, &SESSION).await;
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+
+        spp_output.threshold = 4;
+        let refused = select_signing_commitments(&board, &[], &spp_output, &SESSION).await;
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+    }
+
+    /// Starts a coordinator on a free local port and returns its URL
+    async fn start() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn precommitments_grow_through_a_coordinator() {
+        let url = start().await;
+        let dir = TempDir::new().unwrap();
+        let files = dir.path().to_string_lossy().into_owned();
+        let storage = Backend::Files.open(&FilePaths::new(&files)).unwrap();
+
+        let participant = Participant { index: 1, account: [1u8; 32] };
+        let signing_share = schnorrkel::Keypair::generate_with(rand::rngs::OsRng).to_bytes().to_vec();
+        write_json(&*storage, Artifact::Recipients, &vec![AccountId32::from(participant.account).to_ss58check()]).unwrap();
+        write_json(&*storage, Artifact::SigningShare(participant), &signing_share).unwrap();
+
+        let password_file = dir.path().join("password");
+        std::fs::write(&password_file, "password").unwrap();
+        let password_file = password_file.to_string_lossy().into_owned();
+
+        for count in [2, 3] {
+            threshold_sign_precommit(
+                ParticipantArg::Index(1),
+                files.clone(),
+                &Backend::Files,
+                None,
+                Some(url.clone()),
+                count,
+                Some(password_file.clone()),
+                false,
+            )
+            .await
+            .unwrap();
+        }
+
+        let client = CoordinatorClient::new(&url, NO_SESSION);
+        let entries = client.fetch(Collection::Precommitments).await.unwrap();
+        let sender = Entry::new(&verifying_share(&signing_share).unwrap().to_bytes(), Vec::new()).sender;
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sender, sender);
+
+        let bundle = PrecommitmentBundle::from_bytes(&entries[0].message).unwrap();
+        let indices: Vec<u32> = bundle.commitments.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+
+        // A bundle dropping commitments that were already published is still refused
+        let shrunk = PrecommitmentBundle { commitments: bundle.commitments[..2].to_vec() };
+        let forged = Entry { sender, message: shrunk.to_bytes() };
+        assert!(matches!(client.publish(Collection::Precommitments, &forged).await, Err(CliError::Input { .. })));
+    }
+}
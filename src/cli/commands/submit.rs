@@ -1,90 +1,59 @@
-use std::str::FromStr;
-use crate::{cli::{commands::value_into_composite, CliError}, files::FilePaths};
+use olaf_cli::{
+    extrinsic::ExtrinsicInfo,
+    session::{resolve_file_paths, SessionPurpose},
+    storage::{read_json, Artifact, Backend},
+};
+use crate::cli::{commands::read_threshold_public_key, CliError};
 use schnorrkel::Signature;
 use subxt::{
-    tx,
     backend::{legacy::LegacyRpcMethods, rpc::RpcClient},
-    config::polkadot::PolkadotExtrinsicParamsBuilder,
-    utils::{AccountId32, MultiSignature},
+    utils::MultiSignature,
     OnlineClient, PolkadotConfig,
 };
-use tokio::fs::read_to_string;
 
-/// Submits the threshold extrinsic to the provided url
+/// Submits the threshold extrinsic to the url it was signed for, or to `url` if given
 pub async fn submit_threshold_extrinsic(
     files: String,
+    backend: &Backend,
+    session: Option<String>,
+    url: Option<String>,
 ) -> Result<(), CliError> {
-    let file_paths = FilePaths::new(files);
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
 
-    let threshold_public_key_string = read_to_string(file_paths.threshold_public_key())
-        .await
-        .map_err(|e| CliError(format!("Failed to read threshold public key file: {}", e)))?;
-    
-    let account_id = AccountId32::from_str(&serde_json::from_str::<String>(&threshold_public_key_string)
-        .map_err(|e| CliError(format!("Failed to parse threshold public key: {}", e)))?)
-        .map_err(|e| CliError(format!("Invalid threshold public key format: {}", e)))?;
+    let storage = backend.open(&file_paths)?;
 
-    let signature_string = read_to_string(file_paths.threshold_signature())
-        .await
-        .map_err(|e| CliError(format!("Failed to read threshold signature file: {}", e)))?;
-    
-    let signature_bytes: Vec<u8> = serde_json::from_str(&signature_string)
-        .map_err(|e| CliError(format!("Failed to deserialize threshold signature: {}", e)))?;
-    
-    let group_signature = Signature::from_bytes(&signature_bytes)
-        .map_err(|e| CliError(format!("Failed to parse threshold signature: {}", e)))?;
+    let account_id = read_threshold_public_key(&*storage)?;
 
-    let extrinsic_info_string = read_to_string(file_paths.extrinsic_info())
-        .await
-        .map_err(|e| CliError(format!("Failed to read extrinsic info file: {}", e)))?;
+    let signature_bytes: Vec<u8> = read_json(&*storage, Artifact::ThresholdSignature)?;
     
-    let extrinsic_info: serde_json::Value = serde_json::from_str(&extrinsic_info_string)
-        .map_err(|e| CliError(format!("Failed to parse extrinsic info: {}", e)))?;
-
-    let url = extrinsic_info
-        .get("url")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError("Missing 'url' in extrinsic info".to_string()))?;
-
-    let pallet = extrinsic_info
-        .get("pallet")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError("Missing 'pallet' in extrinsic info".to_string()))?;
-
-    let call_name = extrinsic_info
-        .get("call_name")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError("Missing 'call_name' in extrinsic info".to_string()))?;
-
-    let call_data = extrinsic_info
-        .get("call_data")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| CliError("Missing 'call_data' in extrinsic info".to_string()))?;
+    let group_signature = Signature::from_bytes(&signature_bytes)
+        .map_err(|e| CliError::protocol(format!("Failed to parse threshold signature: {}", e)).with_source(e))?;
 
-    let value = scale_value::stringify::from_str(&call_data).0
-        .map_err(|_| CliError(format!("Failed to parse call data: {}", call_data)))?;
-    let value_as_composite = value_into_composite(value);
+    let extrinsic_info: ExtrinsicInfo = read_json(&*storage, Artifact::ExtrinsicInfo)?;
 
-    let call = tx::dynamic(pallet, call_name, value_as_composite);
+    let url = url.unwrap_or_else(|| extrinsic_info.url.clone());
 
     let client = OnlineClient::<PolkadotConfig>::from_url(&url)
         .await
-        .map_err(|e| CliError(format!("Failed to connect to URL {}: {}", url, e)))?;
+        .map_err(|e| CliError::rpc(format!("Failed to connect to URL {}: {}", url, e)).with_source(e))?;
 
-    let rpc_client = RpcClient::from_url(&url)
-        .await
-        .map_err(|e| CliError(format!("Failed to create RPC client from URL {}: {}", url, e)))?;
-    
-    let legacy_rpc = LegacyRpcMethods::<PolkadotConfig>::new(rpc_client);
+    // Extrinsics signed offline record their nonce, which must be reused for the signature to match.
+    let nonce = match extrinsic_info.nonce {
+        Some(nonce) => nonce,
+        None => {
+            let rpc_client = RpcClient::from_url(&url)
+                .await
+                .map_err(|e| CliError::rpc(format!("Failed to create RPC client from URL {}: {}", url, e)).with_source(e))?;
 
-    let nonce = legacy_rpc.system_account_next_index(&account_id)
-        .await
-        .map_err(|e| CliError(format!("Failed to fetch nonce for account {}: {}", account_id, e)))?;
+            let legacy_rpc = LegacyRpcMethods::<PolkadotConfig>::new(rpc_client);
 
-    let params = PolkadotExtrinsicParamsBuilder::new().nonce(nonce).build();
+            legacy_rpc.system_account_next_index(&account_id)
+                .await
+                .map_err(|e| CliError::rpc(format!("Failed to fetch nonce for account {}: {}", account_id, e)).with_source(e))?
+        }
+    };
 
-    let partial_extrinsic = client.tx().create_partial_signed_offline(&call, params)
-        .map_err(|e| CliError(format!("Failed to create partial signed extrinsic: {}", e)))?;
+    let partial_extrinsic = extrinsic_info.partial_extrinsic(&client, nonce)?;
 
     let signature = subxt_signer::sr25519::Signature(group_signature.to_bytes());
 
@@ -97,7 +66,7 @@ pub async fn submit_threshold_extrinsic(
     extrinsic
         .submit()
         .await
-        .map_err(|e| CliError(format!("Failed to submit extrinsic: {}", e)))?;
+        .map_err(|e| CliError::rpc(format!("Failed to submit extrinsic: {}", e)).with_source(e))?;
 
     println!("Submission of threshold extrinsic was completed successfully!");
 
@@ -0,0 +1,434 @@
+use schnorrkel::{PublicKey, Signature};
+use subxt::{OnlineClient, PolkadotConfig};
+use olaf_cli::{
+    extrinsic::{offline_client, payload_hash, ExtrinsicInfo, SigningRequest},
+    inspect::{SPPOutputView, SigningPackageView},
+    messages::{Collection, MessageBoard},
+    protocol::{check_signing_packages, CheckSigningPackagesInput},
+    session::{resolve_file_paths, SessionPurpose, NO_SESSION},
+    storage::{read_json, Artifact, Backend, Storage},
+};
+use crate::cli::{
+    commands::{read_threshold_public_key, resolve_message, SignedMessage},
+    ChainArgs, CliError,
+};
+
+const DEFAULT_CONTEXT: &str = "substrate";
+
+/// Verifies a threshold signature over a message or an extrinsic against the threshold public key,
+/// or with `signing_packages`, checks each entry of the signing packages on its own instead.
+///
+/// Without a message, the one recorded in `signed_message.json` by threshold message signing is
+/// used, and otherwise the signer payload of the extrinsic in `extrinsic_info.json`, which is
+/// always used with `extrinsic`.
+#[allow(clippy::too_many_arguments)]
+pub async fn verify_threshold_signature(
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    coordinator: Option<String>,
+    message: Option<String>,
+    message_file: Option<String>,
+    message_hex: Option<String>,
+    extrinsic: bool,
+    chain: ChainArgs,
+    context: Option<String>,
+    signature: Option<String>,
+    signing_packages: bool,
+) -> Result<(), CliError> {
+    let (file_paths, _) = resolve_file_paths(&files, backend, session.as_deref(), SessionPurpose::Sign).await?;
+    let storage = backend.open(&file_paths)?;
+
+    let signed = resolve_signed(&*storage, message, message_file, message_hex, extrinsic, chain, context).await?;
+
+    if signing_packages {
+        let message_board = MessageBoard::new(storage.clone(), coordinator.as_deref(), session.as_deref().unwrap_or(NO_SESSION));
+        return verify_signing_packages(&*storage, &message_board, signed).await;
+    }
+
+    let (message, context) = signed.ok_or_else(|| {
+        CliError::input(format!(
+            "No message was given, and neither {} nor {} exist",
+            storage.location(Artifact::SignedMessage),
+            storage.location(Artifact::ExtrinsicInfo),
+        ))
+    })?;
+
+    let signature_bytes = match signature {
+        Some(signature) => {
+            let signature = signature.trim();
+            hex::decode(signature.strip_prefix("0x").unwrap_or(signature))
+                .map_err(|e| CliError::input(format!("Failed to decode hex signature: {}", e)).with_source(e))?
+        }
+        None => read_json(&*storage, Artifact::ThresholdSignature)?,
+    };
+
+    let group_signature = Signature::from_bytes(&signature_bytes)
+        .map_err(|e| CliError::protocol(format!("Failed to parse threshold signature: {}", e)).with_source(e))?;
+
+    let account_id = read_threshold_public_key(&*storage)?;
+
+    let threshold_public_key = PublicKey::from_bytes(&account_id.0)
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse threshold public key: {}", e)).with_source(e))?;
+
+    threshold_public_key
+        .verify_simple(context.as_bytes(), &message, &group_signature)
+        .map_err(|e| CliError::protocol(format!("The threshold signature is not valid for threshold public key {}: {}", account_id, e)).with_source(e))?;
+
+    println!("The threshold signature is valid for threshold public key {}", account_id);
+
+    Ok(())
+}
+
+/// The message and context that were signed: the given message, the signed message of threshold
+/// message signing, or the signer payload of the signed extrinsic. `None` if there is neither.
+async fn resolve_signed(
+    storage: &dyn Storage,
+    message: Option<String>,
+    message_file: Option<String>,
+    message_hex: Option<String>,
+    extrinsic: bool,
+    chain: ChainArgs,
+    context: Option<String>,
+) -> Result<Option<(Vec<u8>, String)>, CliError> {
+    if let Some(message) = resolve_message(message, message_file, message_hex).await? {
+        if extrinsic {
+            return Err(CliError::input("--extrinsic cannot be combined with a message".to_string()));
+        }
+
+        return Ok(Some((message, context.unwrap_or_else(|| DEFAULT_CONTEXT.to_string()))));
+    }
+
+    if !extrinsic && storage.get(Artifact::SignedMessage)?.is_some() {
+        let signed_message: SignedMessage = read_json(storage, Artifact::SignedMessage)?;
+
+        let message = hex::decode(&signed_message.message)
+            .map_err(|e| CliError::protocol(format!("Failed to decode signed message: {}", e)).with_source(e))?;
+
+        return Ok(Some((message, context.unwrap_or(signed_message.context))));
+    }
+
+    if !extrinsic && storage.get(Artifact::ExtrinsicInfo)?.is_none() {
+        return Ok(None);
+    }
+
+    let signing_request = match storage.get(Artifact::SigningRequest)? {
+        Some(_) => Some(read_json::<SigningRequest>(storage, Artifact::SigningRequest)?),
+        None => None,
+    };
+
+    let payload = extrinsic_payload(storage, chain, signing_request.as_ref()).await?;
+    let context = context
+        .or_else(|| signing_request.map(|request| request.context))
+        .unwrap_or_else(|| DEFAULT_CONTEXT.to_string());
+
+    Ok(Some((payload, context)))
+}
+
+/// Rebuilds the signer payload of the extrinsic in `extrinsic_info.json`, for the chain given by
+/// the arguments or pinned by the signing request, and otherwise for the chain of its node
+async fn extrinsic_payload(storage: &dyn Storage, chain: ChainArgs, signing_request: Option<&SigningRequest>) -> Result<Vec<u8>, CliError> {
+    let extrinsic_info: ExtrinsicInfo = read_json(storage, Artifact::ExtrinsicInfo)?;
+
+    let nonce = extrinsic_info.nonce.ok_or_else(|| {
+        CliError::input(format!(
+            "The extrinsic in {} records no nonce, so its payload cannot be rebuilt",
+            storage.location(Artifact::ExtrinsicInfo)
+        ))
+    })?;
+
+    let pinned = match (chain.genesis_hash, chain.spec_version, chain.transaction_version) {
+        (Some(genesis_hash), Some(spec_version), Some(transaction_version)) => Some((genesis_hash, spec_version, transaction_version)),
+        (None, None, None) => signing_request.map(|request| (request.genesis_hash.clone(), request.spec_version, request.transaction_version)),
+        _ => return Err(CliError::input("Give all or none of --genesis-hash, --spec-version and --transaction-version".to_string())),
+    };
+
+    let payload = match pinned {
+        Some((genesis_hash, spec_version, transaction_version)) => {
+            let client = offline_client(&genesis_hash, spec_version, transaction_version)?;
+            extrinsic_info.partial_extrinsic(&client, nonce)?.signer_payload().to_vec()
+        }
+        None => {
+            let client = OnlineClient::<PolkadotConfig>::from_url(&extrinsic_info.url)
+                .await
+                .map_err(|e| CliError::rpc(format!("Failed to connect to URL {}: {}", extrinsic_info.url, e)).with_source(e))?;
+
+            extrinsic_info.partial_extrinsic(&client, nonce)?.signer_payload().to_vec()
+        }
+    };
+
+    println!("Rebuilt the payload of the extrinsic in {}", storage.location(Artifact::ExtrinsicInfo));
+
+    Ok(payload)
+}
+
+/// Checks each entry of the signing packages on its own, against `signed` if it is known, and
+/// prints a verdict for each. Fails if any of them is invalid.
+async fn verify_signing_packages(storage: &dyn Storage, message_board: &MessageBoard, signed: Option<(Vec<u8>, String)>) -> Result<(), CliError> {
+    let entries = message_board.entries(Collection::SigningPackages).await?;
+
+    if entries.is_empty() {
+        return Err(CliError::io(format!("No signing packages found at {}", message_board.location(Collection::SigningPackages))));
+    }
+
+    let signing_packages: Vec<(Option<[u8; 32]>, Vec<u8>)> = entries
+        .iter()
+        .map(|entry| {
+            let signer = entry
+                .sender
+                .as_deref()
+                .and_then(|sender| hex::decode(sender).ok())
+                .and_then(|sender| sender.try_into().ok());
+
+            (signer, entry.message.clone())
+        })
+        .collect();
+
+    let expected_hash = signed.as_ref().map(|(payload, _)| payload_hash(payload));
+
+    let output = check_signing_packages(CheckSigningPackagesInput {
+        signing_packages: &signing_packages,
+        expected: expected_hash.as_deref().zip(signed.as_ref().map(|(_, context)| context.as_str())),
+    })?;
+
+    // Without a recipients file, signers are named by their position among the recipients
+    let recipients: Vec<String> = if storage.get(Artifact::Recipients)?.is_some() {
+        read_json(storage, Artifact::Recipients)?
+    } else {
+        Vec::new()
+    };
+
+    let name = |(signer, bytes): &(Option<[u8; 32]>, Vec<u8>)| {
+        let position = signer.and_then(|signer| {
+            SigningPackageView::parse(bytes)
+                .and_then(|view| SPPOutputView::parse(&view.spp_output))
+                .ok()
+                .and_then(|spp_output| spp_output.position(&signer))
+        });
+
+        match (position, position.and_then(|position| recipients.get(position))) {
+            (Some(position), Some(account)) => format!("participant {} ({})", position + 1, account),
+            (Some(position), None) => format!("participant {}", position + 1),
+            (None, _) => match signer {
+                Some(signer) => format!("signer with verifying share 0x{}", hex::encode(signer)),
+                None => "an unknown signer".to_string(),
+            },
+        }
+    };
+
+    for (position, (signing_package, problems)) in signing_packages.iter().zip(&output.problems).enumerate() {
+        if problems.is_empty() {
+            println!("Signing package {} from {}: valid", position + 1, name(signing_package));
+        } else {
+            println!("Signing package {} from {}: invalid, {}", position + 1, name(signing_package), problems.join("; "));
+        }
+    }
+
+    if signed.is_none() {
+        println!("The payload was not checked: give a message or --extrinsic to check it");
+    }
+
+    let invalid = output.problems.iter().filter(|problems| !problems.is_empty()).count();
+
+    if invalid > 0 {
+        return Err(CliError::protocol(format!("{} of {} signing packages are invalid", invalid, signing_packages.len())));
+    }
+
+    if output.shares_checked {
+        println!("All {} signing packages are valid", signing_packages.len());
+    } else {
+        println!(
+            "No problems were found in the {} signing packages, but their signature shares were not checked: that needs the signing packages of every signer",
+            signing_packages.len(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rand::rngs::OsRng;
+    use schnorrkel::MiniSecretKey;
+    use sp_core::crypto::AccountId32;
+    use tempfile::TempDir;
+    use super::*;
+    use olaf_cli::{
+        files::FilePaths,
+        messages::Entry,
+        protocol::{
+            aggregate, contributor_keypair, dkg_round1, dkg_round2, sign_round1, sign_round2, AggregateInput, DkgRound1Input,
+            DkgRound2Input, DkgRound2Output, KeyExpansion, SignRound1Input, SignRound1Output, SignRound2Input,
+        },
+        storage::write_json,
+    };
+
+    const MESSAGE: &str = "message";
+
+    /// The recipients and the outputs of a DKG among two new participants with a threshold of two
+    fn generate() -> (Vec<String>, Vec<DkgRound2Output>) {
+        let secret_keys: Vec<[u8; 32]> = (0..2).map(|_| MiniSecretKey::generate_with(OsRng).to_bytes()).collect();
+        let recipients: Vec<String> = secret_keys
+            .iter()
+            .map(|secret_key| AccountId32(contributor_keypair(secret_key, KeyExpansion::Ed25519).unwrap().public.to_bytes()).to_string())
+            .collect();
+
+        let all_messages: Vec<Vec<u8>> = secret_keys
+            .iter()
+            .map(|secret_key| {
+                dkg_round1(DkgRound1Input {
+                    secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    threshold: 2,
+                    recipients: &recipients,
+                })
+                .unwrap()
+                .all_message
+            })
+            .collect();
+
+        let outputs = secret_keys
+            .iter()
+            .map(|secret_key| {
+                dkg_round2(DkgRound2Input {
+                    secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    recipients: &recipients,
+                    all_messages: &all_messages,
+                    allow_legacy: false,
+                })
+                .unwrap()
+            })
+            .collect();
+
+        (recipients, outputs)
+    }
+
+    /// The signing packages of every holder over [`MESSAGE`], as published, the last one signed in
+    /// `last_context` instead of the default context
+    fn signing_packages(holders: &[DkgRound2Output], last_context: &str) -> Vec<Entry> {
+        let round1: Vec<SignRound1Output> = holders
+            .iter()
+            .map(|holder| sign_round1(SignRound1Input { signing_share: &holder.signing_share }).unwrap())
+            .collect();
+        let signing_commitments: Vec<Vec<u8>> = round1
+            .iter()
+            .map(|output| output.signing_commitments.to_bytes().to_vec())
+            .collect();
+
+        holders
+            .iter()
+            .zip(&round1)
+            .enumerate()
+            .map(|(index, (holder, output))| {
+                let context = if index + 1 == holders.len() { last_context } else { DEFAULT_CONTEXT };
+                let output = sign_round2(SignRound2Input {
+                    signing_share: &holder.signing_share,
+                    generation_output: &holder.generation_output,
+                    signing_commitments: &signing_commitments,
+                    signing_nonces: &output.signing_nonces,
+                    context: context.as_bytes(),
+                    payload: MESSAGE.as_bytes().to_vec(),
+                })
+                .unwrap();
+
+                Entry::new(&output.verifying_share.to_bytes(), output.signing_package)
+            })
+            .collect()
+    }
+
+    fn threshold_signature(recipients: &[String], holders: &[DkgRound2Output]) -> String {
+        let signing_packages: Vec<Vec<u8>> = signing_packages(holders, DEFAULT_CONTEXT)
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+
+        let output = aggregate(AggregateInput {
+            signing_packages: &signing_packages,
+            expected: None,
+            recipients,
+        })
+        .unwrap();
+
+        hex::encode(output.signature.to_bytes())
+    }
+
+    /// A base directory holding `threshold_public_key`
+    fn directory(threshold_public_key: &AccountId32) -> (TempDir, String, Arc<dyn Storage>) {
+        let dir = TempDir::new().unwrap();
+        let files = dir.path().to_string_lossy().into_owned();
+        let storage = Backend::Files.open(&FilePaths::new(&files)).unwrap();
+
+        write_json(&*storage, Artifact::ThresholdPublicKey, &threshold_public_key.to_string()).unwrap();
+
+        (dir, files, storage)
+    }
+
+    /// Verifies `signature` over the message, or without one, the published signing packages
+    async fn verify(files: &str, message: &str, context: &str, signature: Option<&str>) -> Result<(), CliError> {
+        verify_threshold_signature(
+            files.to_string(),
+            &Backend::Files,
+            None,
+            None,
+            Some(message.to_string()),
+            None,
+            None,
+            false,
+            ChainArgs { genesis_hash: None, spec_version: None, transaction_version: None },
+            Some(context.to_string()),
+            signature.map(str::to_string),
+            signature.is_none(),
+        )
+        .await
+    }
+
+    /// Refusals are protocol errors, so the command exits with a non-zero code
+    fn assert_refused(verified: Result<(), CliError>) {
+        let error = verified.unwrap_err();
+
+        assert!(matches!(error, CliError::Protocol { .. }));
+        assert_eq!(error.exit_code(), 7);
+    }
+
+    #[tokio::test]
+    async fn a_threshold_signature_verifies_for_its_message_and_context_only() {
+        let (recipients, holders) = generate();
+        let signature = threshold_signature(&recipients, &holders);
+        let (_dir, files, _) = directory(&holders[0].threshold_public_key);
+
+        verify(&files, MESSAGE, DEFAULT_CONTEXT, Some(&signature)).await.unwrap();
+
+        assert_refused(verify(&files, "another message", DEFAULT_CONTEXT, Some(&signature)).await);
+        assert_refused(verify(&files, MESSAGE, "another context", Some(&signature)).await);
+    }
+
+    #[tokio::test]
+    async fn a_threshold_signature_of_another_key_is_refused() {
+        let (recipients, holders) = generate();
+        let signature = threshold_signature(&recipients, &holders);
+
+        let (_, others) = generate();
+        let (_dir, files, _) = directory(&others[0].threshold_public_key);
+
+        assert_refused(verify(&files, MESSAGE, DEFAULT_CONTEXT, Some(&signature)).await);
+    }
+
+    #[tokio::test]
+    async fn a_signing_package_of_another_context_is_refused() {
+        let (_, holders) = generate();
+        let (_dir, files, storage) = directory(&holders[0].threshold_public_key);
+
+        for entry in signing_packages(&holders, DEFAULT_CONTEXT) {
+            storage.publish(Collection::SigningPackages, entry).unwrap();
+        }
+        verify(&files, MESSAGE, DEFAULT_CONTEXT, None).await.unwrap();
+
+        // Published again by the same signers, each entry replaces the previous one
+        for entry in signing_packages(&holders, "another context") {
+            storage.publish(Collection::SigningPackages, entry).unwrap();
+        }
+        assert_refused(verify(&files, MESSAGE, DEFAULT_CONTEXT, None).await);
+    }
+}
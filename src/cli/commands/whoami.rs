@@ -0,0 +1,56 @@
+use subxt::utils::AccountId32;
+use tokio::fs::read_to_string;
+use zeroize::Zeroizing;
+use olaf_cli::{
+    files::FilePaths,
+    keystore::{StoredSecret, Unlock},
+    protocol::{check_recipient, contributor_keypair, parse_recipients, KeyExpansion},
+    session::read_manifest,
+    storage::{read_json, Artifact, Backend},
+};
+use crate::cli::CliError;
+
+/// Shows the account of a contributor secret key and which slot among the recipients it occupies
+pub async fn whoami(
+    secret_key: String,
+    files: String,
+    backend: &Backend,
+    session: Option<String>,
+    expansion: KeyExpansion,
+    password_file: Option<String>,
+) -> Result<(), CliError> {
+    let file_paths = match session {
+        Some(session) => read_manifest(&files, backend, &session).await?.file_paths(&files),
+        None => FilePaths::new(&files),
+    };
+    let storage = backend.open(&file_paths)?;
+    let unlock = Unlock::new(password_file);
+
+    let content = Zeroizing::new(
+        read_to_string(&secret_key)
+            .await
+            .map_err(|e| CliError::io(format!("Failed to read secret key file {:?}: {}", secret_key, e)).with_source(e))?,
+    );
+
+    let secret_key_bytes = StoredSecret::parse(&content)
+        .and_then(|stored| stored.reveal(&unlock))
+        .map_err(|e| e.context(format!("Failed to load secret key file {:?}", secret_key)))?;
+
+    let account_id = AccountId32(contributor_keypair(&secret_key_bytes, expansion)?.public.to_bytes());
+    println!("Account: {}", account_id);
+
+    let recipients: Vec<String> = read_json(&*storage, Artifact::Recipients)?;
+    check_recipient(&secret_key_bytes, expansion, &recipients)?;
+
+    let public_keys = parse_recipients(&recipients)?;
+    let index = public_keys
+        .iter()
+        .position(|public_key| public_key.to_bytes() == account_id.0)
+        .expect("checked to be among the recipients")
+        + 1;
+
+    println!("Recipient slot: {} of {}", index, recipients.len());
+    println!("Pass --participant {} or --participant {} to the round commands", index, account_id);
+
+    Ok(())
+}
@@ -1,42 +1,226 @@
 pub mod commands;
 
-use std::fmt;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use olaf_cli::{participant::ParticipantArg, protocol::KeyExpansion, session::SessionPurpose};
+pub use olaf_cli::error::CliError;
 
 #[derive(Parser)]
-#[command(name = "app", about = "An application.", version = "1.0")]
+#[command(
+    name = "olaf-cli",
+    about = "Threshold key generation and threshold signing for Substrate accounts with the OLAF protocols",
+    version = "1.0"
+)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Print errors to stderr as JSON objects with their kind, exit code and message
+    #[arg(long, global = true)]
+    pub json_errors: bool,
+    /// Where protocol artifacts are stored: `files` or `sqlite:<path>`
+    #[arg(long, global = true, default_value = "files")]
+    pub storage: String,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     GenerateThresholdPublicKeyRound1 {
         #[arg(long)]
-        threshold: u16,
+        threshold: Option<u16>,
+        /// The SS58 account of the participant, or its position among the recipients
         #[arg(long)]
-        participant: u16,
+        participant: ParticipantArg,
         #[arg(long, default_value = ".")]
         files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How the contributor secret key is expanded, which must match how the account was created
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Replace the earlier entry of this participant even if a later round may already use it. A coordinator never replaces entries
+        #[arg(long)]
+        force: bool,
     },
     GenerateThresholdPublicKeyRound2 {
+        /// The SS58 account of the participant, or its position among the recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How the contributor secret key is expanded, which must match how the account was created
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Accept round 1 messages of older versions, which are not bound to the recipients
+        #[arg(long)]
+        allow_legacy: bool,
+    },
+    RefreshSigningSharesRound1 {
+        /// The SS58 account of the participant, or its position among the recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How the contributor secret key is expanded, which must match how the account was created
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Replace the earlier message of this participant even if others may already have used it. A coordinator never replaces entries
+        #[arg(long)]
+        force: bool,
+    },
+    RefreshSigningSharesRound2 {
+        /// The SS58 account of the participant, or its position among the recipients
         #[arg(long)]
-        participant: u16,
+        participant: ParticipantArg,
         #[arg(long, default_value = ".")]
         files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How the contributor secret key is expanded, which must match how the account was created
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+    ReshareRound1 {
+        /// The SS58 account of the current holder, or its position among the current recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        /// The DKG session of the signing share to reshare, if not the base directory
+        #[arg(long)]
+        session: Option<String>,
+        /// The DKG session of the new participants, holding their recipients and threshold
+        #[arg(long)]
+        new_session: String,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How the contributor secret key is expanded, which must match how the account was created
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// The positions among the current recipients of the holders that deal, separated by commas.
+        /// Without it, the first holders deal, as many as the current threshold.
+        #[arg(long, value_delimiter = ',')]
+        dealers: Vec<u16>,
+        /// Replace the earlier message of this holder even if new participants may already have used it. A coordinator never replaces entries
+        #[arg(long)]
+        force: bool,
+    },
+    ReshareRound2 {
+        /// The SS58 account of the new participant, or its position among the new recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        /// The DKG session of the new participants
+        #[arg(long)]
+        session: String,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How the contributor secret key is expanded, which must match how the account was created
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// The SS58 threshold public key the reshared signing shares must belong to, required unless
+        /// the session already holds it
+        #[arg(long)]
+        threshold_public_key: Option<String>,        /// The holders that deal, exactly as given to `reshare-round1`
+        #[arg(long, value_delimiter = ',')]
+        dealers: Vec<u16>,
     },
     ThresholdSignRound1 {
+        /// The SS58 account of the participant, or its position among the recipients
         #[arg(long)]
-        participant: u16,
+        participant: ParticipantArg,
         #[arg(long, default_value = ".")]
         files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Replace the earlier entry of this participant even if a later round may already use it. A coordinator never replaces entries
+        #[arg(long)]
+        force: bool,
+    },
+    ThresholdSignPrecommit {
+        /// The SS58 account of the participant, or its position among the recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        /// A signing session using the key material to precommit for, if not the base directory
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        /// How many signing nonces to generate
+        #[arg(long)]
+        count: u32,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Replace the earlier commitments of this participant even if signing sessions may already use them. A coordinator never replaces entries
+        #[arg(long)]
+        force: bool,
     },
     ThresholdSignRound2 {
+        /// The SS58 account of the participant, or its position among the recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        #[arg(long)]
+        signing_request: Option<String>,
+        #[arg(long, default_value = "wss://westend-rpc.polkadot.io")]
+        url: String,
+        #[arg(long, default_value = "System")]
+        pallet: String,
+        #[arg(long, default_value = "remark")]
+        call_name: String,
+        #[arg(long, default_value = "((197, 38))")]
+        call_data: String,
+        #[arg(long, default_value = "substrate")]
+        context: String,
+        #[command(flatten)]
+        transaction: TransactionArgs,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Sign with the precommitted signing nonce at this index instead of the one of round 1
         #[arg(long)]
-        participant: u16,
+        nonce_index: Option<u32>,
+    },
+    ProposeSigningRequest {
         #[arg(long, default_value = ".")]
         files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
         #[arg(long, default_value = "wss://westend-rpc.polkadot.io")]
         url: String,
         #[arg(long, default_value = "System")]
@@ -47,22 +231,252 @@ pub enum Commands {
         call_data: String,
         #[arg(long, default_value = "substrate")]
         context: String,
+        #[command(flatten)]
+        transaction: TransactionArgs,
+        /// Have participants sign with their precommitted signing nonces at this index, without round 1
+        #[arg(long, conflicts_with = "precommitted")]
+        nonce_index: Option<u32>,
+        /// Like --nonce-index, with the lowest index no other signing request pins yet
+        #[arg(long)]
+        precommitted: bool,
+        /// The participants chosen to sign, as SS58 accounts or positions among the recipients,
+        /// separated by commas. Without it, every participant that publishes signing commitments signs.
+        #[arg(long, value_delimiter = ',')]
+        signers: Vec<ParticipantArg>,
+    },
+    ThresholdSignMessage {
+        /// The SS58 account of the participant, or its position among the recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        #[arg(long)]
+        message: Option<String>,
+        #[arg(long)]
+        message_file: Option<String>,
+        #[arg(long)]
+        message_hex: Option<String>,
+        #[arg(long, default_value = "substrate")]
+        context: String,
+        #[arg(long)]
+        password_file: Option<String>,
+        /// Sign with the precommitted signing nonce at this index instead of the one of round 1
+        #[arg(long)]
+        nonce_index: Option<u32>,
     },
     AggregateThresholdSignature {
         #[arg(long, default_value = ".")]
         files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
     },
     SubmitThresholdExtrinsic {
         #[arg(long, default_value = ".")]
         files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        url: Option<String>,
+    },
+    Verify {
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+        #[arg(long)]
+        message: Option<String>,
+        #[arg(long)]
+        message_file: Option<String>,
+        #[arg(long)]
+        message_hex: Option<String>,
+        /// Verify against the signer payload of the extrinsic in extrinsic_info.json, even if a signed message exists
+        #[arg(long)]
+        extrinsic: bool,
+        #[command(flatten)]
+        chain: ChainArgs,
+        #[arg(long)]
+        context: Option<String>,
+        #[arg(long)]
+        signature: Option<String>,
+        /// Check each entry of signing_packages.json on its own instead of the threshold signature
+        #[arg(long, conflicts_with = "signature")]
+        signing_packages: bool,
+    },
+    EncryptSecrets {
+        /// The SS58 account of the participant, or its position among the recipients
+        #[arg(long)]
+        participant: ParticipantArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+    Whoami {
+        /// The contributor secret key file: a secret seed, a secret URI, a polkadot-js export or an encrypted keystore
+        #[arg(long)]
+        secret_key: String,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long, value_enum, default_value_t = ExpansionModeArg::Ed25519)]
+        expansion_mode: ExpansionModeArg,
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+    Dkg {
+        #[command(subcommand)]
+        command: DkgCommands,
     },
+    Session {
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+    Coordinator {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+}
+
+/// Transaction parameters of the extrinsic to sign. With `--offline`, the signer payload is built
+/// from the bundled metadata and these parameters only, without contacting a node.
+///
+/// The extrinsic is decoded and shown for review before signing. Balances are shown in the units
+/// reported by the node, or given with `--token-decimals` and `--token-symbol`.
+#[derive(Args)]
+pub struct TransactionArgs {
+    #[arg(long, requires_all = ["nonce", "genesis_hash", "spec_version", "transaction_version"])]
+    pub offline: bool,
+    #[arg(long)]
+    pub nonce: Option<u64>,
+    #[arg(long)]
+    pub tip: Option<u128>,
+    #[arg(long)]
+    pub genesis_hash: Option<String>,
+    #[arg(long)]
+    pub spec_version: Option<u32>,
+    #[arg(long)]
+    pub transaction_version: Option<u32>,
+    #[arg(long)]
+    pub mortality_period: Option<u64>,
+    #[arg(long, requires = "mortality_period")]
+    pub mortality_block_number: Option<u64>,
+    #[arg(long, requires = "mortality_period")]
+    pub mortality_block_hash: Option<String>,
+    #[arg(long, requires = "token_symbol")]
+    pub token_decimals: Option<u32>,
+    #[arg(long, requires = "token_decimals")]
+    pub token_symbol: Option<String>,
+    /// Sign without asking for confirmation of the reviewed extrinsic
+    #[arg(long)]
+    pub yes: bool,
+}
+
+/// How a contributor secret key is expanded, as given with `--expansion-mode`
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExpansionModeArg {
+    Ed25519,
+    Uniform,
 }
 
-#[derive(Debug)]
-pub struct CliError(pub String);
+impl From<ExpansionModeArg> for KeyExpansion {
+    fn from(expansion_mode: ExpansionModeArg) -> Self {
+        match expansion_mode {
+            ExpansionModeArg::Ed25519 => KeyExpansion::Ed25519,
+            ExpansionModeArg::Uniform => KeyExpansion::Uniform,
+        }
+    }
+}
+
+/// What a session is for, as given with `--purpose`
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PurposeArg {
+    /// Generation of a threshold public key and its signing shares
+    Dkg,
+    /// Threshold signing with the key material of a DKG
+    Sign,
+}
 
-impl fmt::Display for CliError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Cli Error: {:?}", self.0)
+impl From<PurposeArg> for SessionPurpose {
+    fn from(purpose: PurposeArg) -> Self {
+        match purpose {
+            PurposeArg::Dkg => SessionPurpose::Dkg,
+            PurposeArg::Sign => SessionPurpose::Sign,
+        }
     }
-}
\ No newline at end of file
+}
+
+/// The chain an extrinsic was signed for, to rebuild its signer payload without a node
+#[derive(Args)]
+pub struct ChainArgs {
+    #[arg(long, requires_all = ["spec_version", "transaction_version"])]
+    pub genesis_hash: Option<String>,
+    #[arg(long, requires_all = ["genesis_hash", "transaction_version"])]
+    pub spec_version: Option<u32>,
+    #[arg(long, requires_all = ["genesis_hash", "spec_version"])]
+    pub transaction_version: Option<u32>,
+}
+
+#[derive(Subcommand)]
+pub enum DkgCommands {
+    /// Show who contributed to the DKG, which outputs are present and whether they agree
+    Status {
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+    },
+    /// Check that every participant derived the same outcome and write the signed DKG certificate
+    Certify {
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        coordinator: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommands {
+    New {
+        #[arg(long, value_enum)]
+        purpose: PurposeArg,
+        #[arg(long, default_value = ".")]
+        files: String,
+        #[arg(long)]
+        id: Option<String>,
+        #[arg(long)]
+        threshold: Option<u16>,
+        #[arg(long)]
+        recipients: Option<String>,
+        #[arg(long)]
+        dkg_session: Option<String>,
+    },
+    List {
+        #[arg(long, default_value = ".")]
+        files: String,
+    },
+    Show {
+        id: String,
+        #[arg(long, default_value = ".")]
+        files: String,
+    },
+    Close {
+        id: String,
+        #[arg(long, default_value = ".")]
+        files: String,
+    },
+}
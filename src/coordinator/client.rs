@@ -0,0 +1,68 @@
+use crate::{
+    error::CliError,
+    messages::{decode_entries, Collection, Entry, Published},
+};
+
+/// Reads and publishes the collections of one session on a coordinator
+pub struct CoordinatorClient {
+    http: reqwest::Client,
+    session_url: String,
+}
+
+impl CoordinatorClient {
+    pub fn new(url: &str, session: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            session_url: format!("{}/sessions/{}", url.trim_end_matches('/'), session),
+        }
+    }
+
+    pub fn collection_url(&self, collection: Collection) -> String {
+        format!("{}/{}", self.session_url, collection)
+    }
+
+    pub async fn fetch(&self, collection: Collection) -> Result<Vec<Entry>, CliError> {
+        let url = self.collection_url(collection);
+
+        let content = self.http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| CliError::rpc(format!("Failed to fetch {} from coordinator {}: {}", collection, url, e)).with_source(e))?
+            .bytes()
+            .await
+            .map_err(|e| CliError::rpc(format!("Failed to fetch {} from coordinator {}: {}", collection, url, e)).with_source(e))?;
+
+        decode_entries(collection, &content)
+            .map_err(|e| e.context(format!("Coordinator {}", url)))
+    }
+
+    pub async fn publish(&self, collection: Collection, entry: &Entry) -> Result<Published, CliError> {
+        let url = self.collection_url(collection);
+
+        let response = self.http
+            .post(&url)
+            .json(entry)
+            .send()
+            .await
+            .map_err(|e| CliError::rpc(format!("Failed to publish {} to coordinator {}: {}", collection, url, e)).with_source(e))?;
+
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            let reason = response.text().await.unwrap_or_default();
+
+            return Err(CliError::input(format!(
+                "Coordinator {} refused to replace an entry: {}. A coordinator never replaces entries, even with --force: \
+                 run the round again in a new session",
+                url, reason
+            )));
+        }
+
+        response
+            .error_for_status()
+            .map_err(|e| CliError::rpc(format!("Failed to publish {} to coordinator {}: {}", collection, url, e)).with_source(e))?
+            .json()
+            .await
+            .map_err(|e| CliError::serialization(format!("Failed to deserialize the response of coordinator {}: {}", url, e)).with_source(e))
+    }
+}
@@ -0,0 +1,12 @@
+//! A relay for the protocol messages participants would otherwise exchange out of band.
+//!
+//! Every session is a room holding one list of entries per [`Collection`](crate::messages::Collection).
+//! Participants publish their own entry with `POST /sessions/{session}/{collection}`, which is
+//! refused if they already published a different one, read all entries with
+//! `GET /sessions/{session}/{collection}`, and can follow new entries as they arrive over a
+//! WebSocket at `GET /sessions/{session}/{collection}/ws`.
+//! Entries are JSON [`Entry`](crate::messages::Entry) objects, the same encoding used by the local
+//! message files.
+
+pub mod client;
+pub mod server;
@@ -0,0 +1,225 @@
+use std::{collections::HashMap, sync::Arc};
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, Mutex},
+};
+use crate::{
+    error::CliError,
+    messages::{decode_entry, extends, upsert, Collection, Entry, Published},
+};
+
+const SUBSCRIBER_CAPACITY: usize = 64;
+
+/// The entries of one collection of one session, and the subscribers following it
+struct Room {
+    entries: Vec<Entry>,
+    sender: broadcast::Sender<Entry>,
+}
+
+impl Default for Room {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            sender: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Rooms(Arc<Mutex<HashMap<(String, Collection), Room>>>);
+
+/// Runs the coordinator on `listen` until the process is stopped
+pub async fn run(listen: &str) -> Result<(), CliError> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| CliError::io(format!("Failed to listen on {}: {}", listen, e)).with_source(e))?;
+
+    println!("The coordinator is listening on: http://{}", listen);
+
+    serve(listener).await
+}
+
+/// Runs the coordinator on a listener that is already bound until the process is stopped
+pub async fn serve(listener: TcpListener) -> Result<(), CliError> {
+    let app = Router::new()
+        .route("/sessions/:session/:collection", get(fetch).post(publish))
+        .route("/sessions/:session/:collection/ws", get(subscribe))
+        .with_state(Rooms::default());
+
+    axum::serve(listener, app)
+        .await
+        .map_err(|e| CliError::io(format!("Coordinator failed: {}", e)).with_source(e))
+}
+
+async fn fetch(
+    State(rooms): State<Rooms>,
+    Path((session, collection)): Path<(String, Collection)>,
+) -> Json<Vec<Entry>> {
+    let rooms = rooms.0.lock().await;
+
+    let entries = rooms
+        .get(&(session, collection))
+        .map(|room| room.entries.clone())
+        .unwrap_or_default();
+
+    Json(entries)
+}
+
+async fn publish(
+    State(rooms): State<Rooms>,
+    Path((session, collection)): Path<(String, Collection)>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Published>), (StatusCode, String)> {
+    let entry = decode_entry(&body).map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid entry: {}", e)))?;
+
+    let mut rooms = rooms.0.lock().await;
+    let room = rooms.entry((session.clone(), collection)).or_default();
+
+    // Nothing proves an entry comes from the sender it names, so an entry is never replaced: anyone
+    // could otherwise swap the message of another participant for their own. A bundle of
+    // precommitments may still grow, as it keeps every commitment already published.
+    if room
+        .entries
+        .iter()
+        .any(|earlier| earlier.sender.is_some() && earlier.sender == entry.sender && *earlier != entry && !extends(collection, earlier, &entry))
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("The {} of session {} already hold a different entry from {}", collection, session, entry.sender_name()),
+        ));
+    }
+
+    let published = upsert(&mut room.entries, entry.clone());
+
+    if published != Published::Unchanged {
+        // Nobody following the room is not an error.
+        let _ = room.sender.send(entry);
+    }
+
+    let status = match published {
+        Published::Added => StatusCode::CREATED,
+        Published::Unchanged | Published::Replaced => StatusCode::OK,
+    };
+
+    Ok((status, Json(published)))
+}
+
+async fn subscribe(
+    State(rooms): State<Rooms>,
+    Path((session, collection)): Path<(String, Collection)>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| stream_entries(socket, rooms, session, collection))
+}
+
+/// Sends the entries already in the room, then every new entry until either side goes away
+async fn stream_entries(mut socket: WebSocket, rooms: Rooms, session: String, collection: Collection) {
+    let (existing_entries, mut receiver) = {
+        let mut rooms = rooms.0.lock().await;
+        let room = rooms.entry((session, collection)).or_default();
+        (room.entries.clone(), room.sender.subscribe())
+    };
+
+    for entry in existing_entries {
+        if send_entry(&mut socket, &entry).await.is_err() {
+            return;
+        }
+    }
+
+    // A subscriber that lags behind is disconnected and has to fetch the room again.
+    while let Ok(entry) = receiver.recv().await {
+        if send_entry(&mut socket, &entry).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn send_entry(socket: &mut WebSocket, entry: &Entry) -> Result<(), ()> {
+    let entry_json = serde_json::to_string(entry).map_err(|_| ())?;
+    socket.send(Message::Text(entry_json)).await.map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+    use tokio_tungstenite::{connect_async, tungstenite};
+    use super::*;
+    use crate::{coordinator::client::CoordinatorClient, messages::MessageBoard, storage::MemoryStorage};
+
+    /// Starts a coordinator on a free local port and returns its URL
+    async fn start() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+
+        format!("http://{}", address)
+    }
+
+    fn entry(sender: u8, message: &[u8]) -> Entry {
+        Entry::new(&[sender; 32], message.to_vec())
+    }
+
+    async fn next_entry<S>(socket: &mut S) -> Entry
+    where
+        S: StreamExt<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        let message = socket.next().await.unwrap().unwrap();
+        serde_json::from_str(&message.into_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn entries_are_published_fetched_and_followed() {
+        let url = start().await;
+        let client = CoordinatorClient::new(&url, "session");
+
+        assert_eq!(client.publish(Collection::AllMessages, &entry(1, b"a")).await.unwrap(), Published::Added);
+        assert_eq!(client.publish(Collection::AllMessages, &entry(1, b"a")).await.unwrap(), Published::Unchanged);
+
+        let ws_url = format!("{}/ws", client.collection_url(Collection::AllMessages)).replacen("http://", "ws://", 1);
+        let (mut socket, _) = connect_async(ws_url).await.unwrap();
+
+        assert_eq!(next_entry(&mut socket).await, entry(1, b"a"));
+
+        assert_eq!(client.publish(Collection::AllMessages, &entry(2, b"b")).await.unwrap(), Published::Added);
+
+        assert_eq!(next_entry(&mut socket).await, entry(2, b"b"));
+        assert_eq!(client.fetch(Collection::AllMessages).await.unwrap(), [entry(1, b"a"), entry(2, b"b")]);
+
+        assert!(client.fetch(Collection::SigningCommitments).await.unwrap().is_empty());
+        assert!(CoordinatorClient::new(&url, "other").fetch(Collection::AllMessages).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_entry_is_never_replaced() {
+        let url = start().await;
+        let client = CoordinatorClient::new(&url, "session");
+
+        client.publish(Collection::AllMessages, &entry(1, b"a")).await.unwrap();
+
+        assert!(matches!(client.publish(Collection::AllMessages, &entry(1, b"forged")).await, Err(CliError::Input { .. })));
+        assert_eq!(client.fetch(Collection::AllMessages).await.unwrap(), [entry(1, b"a")]);
+    }
+
+    #[tokio::test]
+    async fn force_does_not_replace_an_entry_on_a_coordinator() {
+        let url = start().await;
+        let message_board = MessageBoard::new(Arc::new(MemoryStorage::new()), Some(&url), "session");
+
+        message_board.publish(Collection::AllMessages, entry(1, b"a")).await.unwrap();
+        message_board.check_replaceable(Collection::AllMessages, &entry(1, b"a"), true, true).await.unwrap();
+
+        let refused = message_board.check_replaceable(Collection::AllMessages, &entry(1, b"b"), false, true).await.unwrap_err();
+        assert!(refused.message().contains("never replaces"));
+    }
+}
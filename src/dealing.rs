@@ -0,0 +1,251 @@
+//! Dealing a secret to a list of participants with verifiable secret sharing, the building block
+//! of share refresh and resharing.
+//!
+//! The dealer picks a random polynomial of degree `t - 1` with the secret as its constant term,
+//! commits to its coefficients and sends each participant its evaluation at the identifier of the
+//! participant, encrypted to its contributor key. Each participant checks its evaluation against
+//! the commitments, and anyone can compute the verifying share of an evaluation from them.
+
+use crypto_secretbox::{
+    aead::{Aead, KeyInit},
+    Key, Nonce, XSalsa20Poly1305,
+};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use rand::rngs::OsRng;
+use schnorrkel::{Keypair, PublicKey, Signature};
+use sp_core::hashing::blake2_256;
+use zeroize::Zeroizing;
+use crate::{error::CliError, inspect::Reader};
+
+const DEALING_MAGIC: &[u8; 8] = b"olafdeal";
+const DEALING_CONTEXT: &[u8] = b"olaf-cli dealing";
+const ENCRYPTED_SHARE_LENGTH: usize = 48;
+
+/// The dealing of one participant: commitments to its polynomial and the encrypted evaluation
+/// for every recipient, signed by the dealer
+pub struct Dealing {
+    /// The public key the dealing is signed with: the contributor keypair of the dealer for a
+    /// refresh, and the signing share it deals for a resharing
+    pub sender: [u8; 32],
+    /// What the dealing is for, such as the `SPPOutput` whose shares it refreshes. Dealings for
+    /// anything else are rejected.
+    pub binding: [u8; 32],
+    pub ephemeral_key: [u8; 32],
+    /// Commitments to the coefficients of the polynomial, from the constant term up
+    pub commitments: Vec<[u8; 32]>,
+    /// The evaluation of the polynomial for each recipient, in order, encrypted to it
+    pub encrypted_shares: Vec<[u8; ENCRYPTED_SHARE_LENGTH]>,
+    pub signature: [u8; 64],
+}
+
+/// A recipient of a dealing: its contributor public key and its identifier in the threshold scheme
+pub struct DealingRecipient {
+    pub public_key: PublicKey,
+    pub identifier: Scalar,
+}
+
+impl Dealing {
+    /// Deals `secret` to `recipients` with a threshold of `threshold`
+    pub fn deal(
+        keypair: &Keypair,
+        binding: [u8; 32],
+        secret: Scalar,
+        threshold: u16,
+        recipients: &[DealingRecipient],
+    ) -> Result<Self, CliError> {
+        if threshold == 0 {
+            return Err(CliError::input("The threshold of a dealing must be at least 1".to_string()));
+        }
+
+        let coefficients: Zeroizing<Vec<Scalar>> = Zeroizing::new(
+            std::iter::once(secret)
+                .chain((1..threshold).map(|_| Scalar::random(&mut OsRng)))
+                .collect(),
+        );
+
+        let commitments = coefficients
+            .iter()
+            .map(|coefficient| (coefficient * RISTRETTO_BASEPOINT_POINT).compress().to_bytes())
+            .collect();
+
+        let ephemeral_secret = Zeroizing::new(Scalar::random(&mut OsRng));
+        let ephemeral_key = (*ephemeral_secret * RISTRETTO_BASEPOINT_POINT).compress().to_bytes();
+
+        let encrypted_shares = recipients
+            .iter()
+            .map(|recipient| {
+                let share = Zeroizing::new(evaluate(&coefficients, &recipient.identifier));
+                let shared_secret = *ephemeral_secret * recipient.public_key.as_point();
+                let key = share_key(&binding, &ephemeral_key, &recipient.public_key, &shared_secret);
+
+                let ciphertext = XSalsa20Poly1305::new(Key::from_slice(&key[..]))
+                    .encrypt(Nonce::from_slice(&[0u8; 24]), share.as_bytes().as_slice())
+                    .map_err(|e| CliError::protocol(format!("Failed to encrypt share: {}", e)))?;
+
+                Ok(ciphertext.try_into().expect("a 32 byte share encrypts to 48 bytes"))
+            })
+            .collect::<Result<_, CliError>>()?;
+
+        let mut dealing = Self {
+            sender: keypair.public.to_bytes(),
+            binding,
+            ephemeral_key,
+            commitments,
+            encrypted_shares,
+            signature: [0u8; 64],
+        };
+        dealing.signature = keypair.sign_simple(DEALING_CONTEXT, &dealing.signed_bytes()).to_bytes();
+
+        Ok(dealing)
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(DEALING_MAGIC);
+        bytes.extend_from_slice(&self.sender);
+        bytes.extend_from_slice(&self.binding);
+        bytes.extend_from_slice(&self.ephemeral_key);
+        bytes.extend_from_slice(&(self.commitments.len() as u32).to_le_bytes());
+        self.commitments.iter().for_each(|commitment| bytes.extend_from_slice(commitment));
+        bytes.extend_from_slice(&(self.encrypted_shares.len() as u32).to_le_bytes());
+        self.encrypted_shares.iter().for_each(|share| bytes.extend_from_slice(share));
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.signed_bytes();
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CliError> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.take(DEALING_MAGIC.len())? != DEALING_MAGIC {
+            return Err(CliError::protocol("Not a dealing".to_string()));
+        }
+
+        let sender = reader.array()?;
+        let binding = reader.array()?;
+        let ephemeral_key = reader.array()?;
+        let commitments = (0..reader.length()?).map(|_| reader.array()).collect::<Result<_, _>>()?;
+        let encrypted_shares = (0..reader.length()?).map(|_| reader.array()).collect::<Result<_, _>>()?;
+        let signature = reader.array()?;
+
+        if !reader.is_empty() {
+            return Err(CliError::protocol("Trailing bytes after dealing".to_string()));
+        }
+
+        Ok(Self {
+            sender,
+            binding,
+            ephemeral_key,
+            commitments,
+            encrypted_shares,
+            signature,
+        })
+    }
+
+    /// Checks the signature of the dealer
+    pub fn verify_signature(&self) -> Result<(), CliError> {
+        let sender = PublicKey::from_bytes(&self.sender)
+            .map_err(|e| CliError::protocol(format!("Invalid sender of dealing: {}", e)).with_source(e))?;
+        let signature = Signature::from_bytes(&self.signature)
+            .map_err(|e| CliError::protocol(format!("Invalid signature of dealing: {}", e)).with_source(e))?;
+
+        sender
+            .verify_simple(DEALING_CONTEXT, &self.signed_bytes(), &signature)
+            .map_err(|_| CliError::protocol("The dealing is not signed by its sender".to_string()))
+    }
+
+    /// The commitment to the constant term, the secret that was dealt
+    pub fn secret_commitment(&self) -> Result<RistrettoPoint, CliError> {
+        self.commitments
+            .first()
+            .ok_or_else(|| CliError::protocol("The dealing has no commitments".to_string()))
+            .and_then(decompress)
+    }
+
+    /// Evaluates the committed polynomial at `identifier`: the verifying share of the evaluation
+    /// dealt to the recipient with that identifier
+    pub fn commitment_at(&self, identifier: &Scalar) -> Result<RistrettoPoint, CliError> {
+        let mut result = RistrettoPoint::identity();
+        for commitment in self.commitments.iter().rev() {
+            result = result * identifier + decompress(commitment)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Decrypts the evaluation dealt to the recipient at `position`, with its contributor
+    /// keypair, and checks it against the commitments
+    pub fn open(&self, position: usize, keypair: &Keypair, identifier: &Scalar) -> Result<Zeroizing<Scalar>, CliError> {
+        let ciphertext = self.encrypted_shares
+            .get(position)
+            .ok_or_else(|| CliError::protocol(format!("The dealing has no share for recipient {}", position + 1)))?;
+
+        let ephemeral_key = decompress(&self.ephemeral_key)?;
+        let contributor_secret = Zeroizing::new(keypair.secret.to_bytes());
+        let shared_secret = *secret_scalar(&contributor_secret[..])? * ephemeral_key;
+        let key = share_key(&self.binding, &self.ephemeral_key, &keypair.public, &shared_secret);
+
+        let plaintext = Zeroizing::new(
+            XSalsa20Poly1305::new(Key::from_slice(&key[..]))
+                .decrypt(Nonce::from_slice(&[0u8; 24]), ciphertext.as_slice())
+                .map_err(|_| CliError::protocol("Failed to decrypt the share of the dealing".to_string()))?,
+        );
+
+        let share = Zeroizing::new(
+            <[u8; 32]>::try_from(plaintext.as_slice())
+                .ok()
+                .and_then(|bytes| Scalar::from_canonical_bytes(bytes).into_option())
+                .ok_or_else(|| CliError::protocol("The share of the dealing is not a scalar".to_string()))?,
+        );
+
+        if *share * RISTRETTO_BASEPOINT_POINT != self.commitment_at(identifier)? {
+            return Err(CliError::protocol("The share of the dealing does not match its commitments".to_string()));
+        }
+
+        Ok(share)
+    }
+}
+
+/// The secret scalar of a keypair, such as a contributor keypair or a signing share
+pub fn secret_scalar(keypair_bytes: &[u8]) -> Result<Zeroizing<Scalar>, CliError> {
+    let key: [u8; 32] = keypair_bytes
+        .get(..32)
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| CliError::key_parsing("Truncated keypair".to_string()))?;
+
+    Ok(Zeroizing::new(Scalar::from_bytes_mod_order(key)))
+}
+
+pub fn decompress(bytes: &[u8; 32]) -> Result<RistrettoPoint, CliError> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or_else(|| CliError::protocol(format!("Invalid point 0x{}", hex::encode(bytes))))
+}
+
+fn evaluate(coefficients: &[Scalar], x: &Scalar) -> Scalar {
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::ZERO, |result, coefficient| result * x + coefficient)
+}
+
+fn share_key(binding: &[u8; 32], ephemeral_key: &[u8; 32], recipient: &PublicKey, shared_secret: &RistrettoPoint) -> Zeroizing<[u8; 32]> {
+    // Every ephemeral key is used once per recipient, so each key encrypts a single share
+    let mut input = Zeroizing::new(Vec::with_capacity(DEALING_CONTEXT.len() + 128));
+    input.extend_from_slice(DEALING_CONTEXT);
+    input.extend_from_slice(binding);
+    input.extend_from_slice(ephemeral_key);
+    input.extend_from_slice(&recipient.to_bytes());
+    input.extend_from_slice(shared_secret.compress().as_bytes());
+
+    Zeroizing::new(blake2_256(&input))
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+/// The underlying error that caused a [`CliError`], such as a failed read or a malformed file
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync>;
+
+/// The errors of the CLI, by what failed. Each kind exits with its own status code, see [`CliError::exit_code`].
+///
+/// The message describes the error in full, including its cause. Where the cause is an error of
+/// its own, it is also kept as the `source`, and returned by [`std::error::Error::source`].
+#[derive(Debug)]
+pub enum CliError {
+    /// Reading or writing a file failed
+    Io { message: String, source: Option<ErrorSource> },
+    /// A file or message could not be encoded or decoded
+    Serialization { message: String, source: Option<ErrorSource> },
+    /// A secret key, signing share, public key or keystore could not be parsed or unlocked
+    KeyParsing { message: String, source: Option<ErrorSource> },
+    /// A protocol message is invalid, or the protocol failed
+    Protocol { message: String, source: Option<ErrorSource> },
+    /// A node or coordinator could not be reached, or rejected a request
+    Rpc { message: String, source: Option<ErrorSource> },
+    /// The arguments or the state of a session do not allow the command
+    Input { message: String, source: Option<ErrorSource> },
+}
+
+impl CliError {
+    pub fn io(message: impl Into<String>) -> Self {
+        CliError::Io { message: message.into(), source: None }
+    }
+
+    pub fn serialization(message: impl Into<String>) -> Self {
+        CliError::Serialization { message: message.into(), source: None }
+    }
+
+    pub fn key_parsing(message: impl Into<String>) -> Self {
+        CliError::KeyParsing { message: message.into(), source: None }
+    }
+
+    pub fn protocol(message: impl Into<String>) -> Self {
+        CliError::Protocol { message: message.into(), source: None }
+    }
+
+    pub fn rpc(message: impl Into<String>) -> Self {
+        CliError::Rpc { message: message.into(), source: None }
+    }
+
+    pub fn input(message: impl Into<String>) -> Self {
+        CliError::Input { message: message.into(), source: None }
+    }
+
+    /// Keeps the error that caused this one as its source
+    pub fn with_source(mut self, source: impl Into<ErrorSource>) -> Self {
+        *self.parts_mut().1 = Some(source.into());
+        self
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CliError::Io { .. } => "io",
+            CliError::Serialization { .. } => "serialization",
+            CliError::KeyParsing { .. } => "key_parsing",
+            CliError::Protocol { .. } => "protocol",
+            CliError::Rpc { .. } => "rpc",
+            CliError::Input { .. } => "input",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CliError::Io { message, .. }
+            | CliError::Serialization { message, .. }
+            | CliError::KeyParsing { message, .. }
+            | CliError::Protocol { message, .. }
+            | CliError::Rpc { message, .. }
+            | CliError::Input { message, .. } => message,
+        }
+    }
+
+    fn parts_mut(&mut self) -> (&mut String, &mut Option<ErrorSource>) {
+        match self {
+            CliError::Io { message, source }
+            | CliError::Serialization { message, source }
+            | CliError::KeyParsing { message, source }
+            | CliError::Protocol { message, source }
+            | CliError::Rpc { message, source }
+            | CliError::Input { message, source } => (message, source),
+        }
+    }
+
+    /// The process exit code. 1 is left to panics and 2 to argument errors reported by clap.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Input { .. } => 3,
+            CliError::Io { .. } => 4,
+            CliError::Serialization { .. } => 5,
+            CliError::KeyParsing { .. } => 6,
+            CliError::Protocol { .. } => 7,
+            CliError::Rpc { .. } => 8,
+        }
+    }
+
+    /// Prefixes the message with what was being done, keeping the kind and source of the error
+    pub fn context(mut self, context: impl fmt::Display) -> Self {
+        let message = self.parts_mut().0;
+        *message = format!("{}: {}", context, message);
+        self
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "error": self.kind(),
+            "code": self.exit_code(),
+            "message": self.message(),
+        })
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cli Error ({}): {}", self.kind(), self.message())
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Io { source, .. }
+            | CliError::Serialization { source, .. }
+            | CliError::KeyParsing { source, .. }
+            | CliError::Protocol { source, .. }
+            | CliError::Rpc { source, .. }
+            | CliError::Input { source, .. } => source.as_deref().map(|source| source as &(dyn std::error::Error + 'static)),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use scale_value::{Composite, ValueDef};
+use serde::{Deserialize, Serialize};
+use sp_core::hashing::blake2_256;
+use subxt::{
+    backend::legacy::rpc_methods::SystemProperties,
+    client::{OfflineClientT, RuntimeVersion},
+    config::polkadot::PolkadotExtrinsicParamsBuilder,
+    ext::codec::Decode,
+    tx::{self, DynamicPayload, PartialExtrinsic, Payload},
+    utils::H256,
+    Metadata, OfflineClient, PolkadotConfig,
+};
+use crate::error::CliError;
+
+/// The metadata the crate was built against, used to build extrinsics without a node
+const BUNDLED_METADATA: &[u8] = include_bytes!("../metadata.scale");
+
+/// The block from which a mortal extrinsic is valid, and for how many blocks
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Mortality {
+    pub period: u64,
+    pub block_number: u64,
+    pub block_hash: String,
+}
+
+/// Decimals and symbol of the native token of a chain, used to show balances in units
+#[derive(Clone, Debug)]
+pub struct TokenUnits {
+    pub decimals: u32,
+    pub symbol: String,
+}
+
+impl TokenUnits {
+    /// Reads `tokenDecimals` and `tokenSymbol` from the `system_properties` of a node
+    pub fn from_properties(properties: &SystemProperties) -> Option<Self> {
+        // Multi-token chains report lists, whose first entry is the native token
+        let first = |value: &serde_json::Value| match value {
+            serde_json::Value::Array(values) => values.first().cloned(),
+            value => Some(value.clone()),
+        };
+
+        let decimals = first(properties.get("tokenDecimals")?)?.as_u64()?;
+        let symbol = first(properties.get("tokenSymbol")?)?.as_str()?.to_string();
+
+        Some(Self {
+            decimals: u32::try_from(decimals).ok()?,
+            symbol,
+        })
+    }
+
+    pub fn format(&self, amount: u128) -> String {
+        let Some(unit) = 10u128.checked_pow(self.decimals) else {
+            return amount.to_string();
+        };
+
+        let fraction = format!("{:0width$}", amount % unit, width = self.decimals as usize);
+        let fraction = fraction.trim_end_matches('0');
+
+        if fraction.is_empty() {
+            format!("{} {} ({} plancks)", amount / unit, self.symbol, amount)
+        } else {
+            format!("{}.{} {} ({} plancks)", amount / unit, fraction, self.symbol, amount)
+        }
+    }
+}
+
+/// Everything needed to rebuild the extrinsic that was signed, stored in `extrinsic_info.json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtrinsicInfo {
+    pub url: String,
+    pub pallet: String,
+    pub call_name: String,
+    pub call_data: String,
+    #[serde(default)]
+    pub nonce: Option<u64>,
+    #[serde(default)]
+    pub tip: Option<u128>,
+    #[serde(default)]
+    pub mortality: Option<Mortality>,
+}
+
+/// The fields of a call, given as one composite value or as the single unnamed field
+fn value_into_composite(value: scale_value::Value) -> Composite<()> {
+    match value.value {
+        ValueDef::Composite(composite) => composite,
+        _ => Composite::Unnamed(vec![value]),
+    }
+}
+
+impl ExtrinsicInfo {
+    pub fn call(&self) -> Result<DynamicPayload, CliError> {
+        let value = scale_value::stringify::from_str(&self.call_data).0
+            .map_err(|_| CliError::input(format!("Failed to parse call data: {}", self.call_data)))?;
+
+        Ok(tx::dynamic(&self.pallet, &self.call_name, value_into_composite(value)))
+    }
+
+    /// Builds the unsigned extrinsic with the recorded nonce, tip and mortality
+    pub fn partial_extrinsic<C: OfflineClientT<PolkadotConfig>>(
+        &self,
+        client: &C,
+        nonce: u64,
+    ) -> Result<PartialExtrinsic<PolkadotConfig, C>, CliError> {
+        let mut params = PolkadotExtrinsicParamsBuilder::<PolkadotConfig>::new().nonce(nonce);
+
+        if let Some(tip) = self.tip {
+            params = params.tip(tip);
+        }
+
+        if let Some(mortality) = &self.mortality {
+            params = params.mortal_unchecked(mortality.block_number, parse_hash(&mortality.block_hash)?, mortality.period);
+        }
+
+        client
+            .tx()
+            .create_partial_signed_offline(&self.call()?, params.build())
+            .map_err(|e| CliError::rpc(format!("Failed to create partial signed transaction: {}", e)).with_source(e))
+    }
+
+    /// Describes the extrinsic that would be signed with `nonce`, decoding the call arguments
+    /// against the metadata of `client`. Balances are shown in `units` when they are known.
+    pub fn review<C: OfflineClientT<PolkadotConfig>>(
+        &self,
+        client: &C,
+        nonce: u64,
+        units: Option<&TokenUnits>,
+    ) -> Result<String, CliError> {
+        let metadata = client.metadata();
+
+        let call_data = self.call()?
+            .encode_call_data(&metadata)
+            .map_err(|e| CliError::rpc(format!("Failed to encode call {}::{}: {}", self.pallet, self.call_name, e)).with_source(e))?;
+
+        let call = metadata
+            .pallet_by_name(&self.pallet)
+            .and_then(|pallet| pallet.call_variant_by_name(&self.call_name))
+            .ok_or_else(|| CliError::rpc(format!("Call {}::{} is not in the metadata", self.pallet, self.call_name)))?;
+
+        let balance = |amount: u128| match units {
+            Some(units) => units.format(amount),
+            None => amount.to_string(),
+        };
+
+        let mut lines = vec![format!("Call:                {}::{}", self.pallet, self.call_name)];
+
+        // The first two bytes of the call data are the pallet and call indices
+        let mut arguments = &call_data[2..];
+
+        for field in &call.fields {
+            let value = scale_value::scale::decode_as_type(&mut arguments, field.ty.id, metadata.types())
+                .map_err(|e| CliError::rpc(format!("Failed to decode argument of call {}::{}: {}", self.pallet, self.call_name, e)))?;
+
+            let is_balance = field.type_name.as_deref().is_some_and(|type_name| type_name.contains("Balance"));
+
+            let value = match value.as_u128() {
+                Some(amount) if is_balance => balance(amount),
+                _ => value.to_string(),
+            };
+
+            lines.push(format!(
+                "  {} ({}): {}",
+                field.name.as_deref().unwrap_or("_"),
+                field.type_name.as_deref().unwrap_or("?"),
+                value,
+            ));
+        }
+
+        lines.push(format!("Nonce:               {}", nonce));
+        lines.push(format!("Tip:                 {}", balance(self.tip.unwrap_or_default())));
+        lines.push(match &self.mortality {
+            Some(mortality) => format!(
+                "Era:                 mortal for {} blocks from block #{} ({})",
+                mortality.period, mortality.block_number, mortality.block_hash,
+            ),
+            None => "Era:                 immortal".to_string(),
+        });
+        lines.push(format!("Genesis hash:        0x{}", hex::encode(client.genesis_hash())));
+        lines.push(format!("Spec version:        {}", client.runtime_version().spec_version));
+        lines.push(format!("Transaction version: {}", client.runtime_version().transaction_version));
+
+        Ok(lines.join("\n"))
+    }
+}
+
+/// One extrinsic proposed for all participants to sign, stored in `signing_request.json`.
+///
+/// Every parameter of the signer payload is pinned, so that participants sign identical payloads
+/// and can check that against `payload_hash` before signing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningRequest {
+    pub extrinsic: ExtrinsicInfo,
+    pub context: String,
+    pub genesis_hash: String,
+    pub spec_version: u32,
+    pub transaction_version: u32,
+    pub payload_hash: String,
+    /// The index of the precommitted signing nonces to sign with, instead of running round 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce_index: Option<u32>,
+    /// The SS58 accounts of the participants chosen to sign, or empty for every participant that
+    /// published signing commitments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signers: Vec<String>,
+}
+
+impl SigningRequest {
+    /// Checks that a payload rebuilt by a participant is the one that was proposed
+    pub fn check(&self, genesis_hash: &str, spec_version: u32, transaction_version: u32, payload: &[u8]) -> Result<(), CliError> {
+        if parse_hash(genesis_hash)? != parse_hash(&self.genesis_hash)? {
+            return Err(CliError::protocol(format!(
+                "The signing request is for the chain with genesis hash {}, not {}",
+                self.genesis_hash, genesis_hash,
+            )));
+        }
+
+        if (spec_version, transaction_version) != (self.spec_version, self.transaction_version) {
+            return Err(CliError::protocol(format!(
+                "The signing request is for spec version {} and transaction version {}, not {} and {}",
+                self.spec_version, self.transaction_version, spec_version, transaction_version,
+            )));
+        }
+
+        if payload_hash(payload) != self.payload_hash.trim_start_matches("0x") {
+            return Err(CliError::protocol(format!(
+                "The rebuilt payload has hash {}, but the signing request has {}: refusing to sign",
+                payload_hash(payload), self.payload_hash,
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The hex encoded blake2-256 hash of a signer payload
+pub fn payload_hash(payload: &[u8]) -> String {
+    hex::encode(blake2_256(payload))
+}
+
+/// A client for the chain the bundled metadata describes, which never connects to a node
+pub fn offline_client(
+    genesis_hash: &str,
+    spec_version: u32,
+    transaction_version: u32,
+) -> Result<OfflineClient<PolkadotConfig>, CliError> {
+    let metadata = Metadata::decode(&mut &BUNDLED_METADATA[..])
+        .map_err(|e| CliError::serialization(format!("Failed to decode bundled metadata: {}", e)).with_source(e))?;
+
+    Ok(OfflineClient::new(
+        parse_hash(genesis_hash)?,
+        RuntimeVersion { spec_version, transaction_version },
+        metadata,
+    ))
+}
+
+pub fn parse_hash(hash: &str) -> Result<H256, CliError> {
+    let hash = hash.trim();
+    let bytes = hex::decode(hash.strip_prefix("0x").unwrap_or(hash))
+        .map_err(|e| CliError::input(format!("Failed to decode hex block hash {}: {}", hash, e)).with_source(e))?;
+
+    if bytes.len() != 32 {
+        return Err(CliError::input(format!("Invalid block hash {}: expected 32 bytes, got {}", hash, bytes.len())));
+    }
+
+    Ok(H256::from_slice(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HASH: &str = "0x91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3";
+    const OTHER_GENESIS_HASH: &str = "0xb0a8d493285c2df73290dfb7e61f870f17b41801197a149ca93654499ea3dafe";
+    const SPEC_VERSION: u32 = 1_000_000;
+    const TRANSACTION_VERSION: u32 = 25;
+
+    fn extrinsic(call_data: &str, mortality: Option<Mortality>) -> ExtrinsicInfo {
+        ExtrinsicInfo {
+            url: "ws://127.0.0.1:9944".to_string(),
+            pallet: "System".to_string(),
+            call_name: "remark".to_string(),
+            call_data: call_data.to_string(),
+            nonce: None,
+            tip: None,
+            mortality,
+        }
+    }
+
+    fn mortal(period: u64) -> Option<Mortality> {
+        Some(Mortality {
+            period,
+            block_number: 100,
+            block_hash: OTHER_GENESIS_HASH.to_string(),
+        })
+    }
+
+    /// The signer payload of `extrinsic_info` with `nonce`, on the chain of `genesis_hash`
+    fn payload(extrinsic_info: &ExtrinsicInfo, nonce: u64, genesis_hash: &str) -> Vec<u8> {
+        let client = offline_client(genesis_hash, SPEC_VERSION, TRANSACTION_VERSION).unwrap();
+
+        extrinsic_info.partial_extrinsic(&client, nonce).unwrap().signer_payload().to_vec()
+    }
+
+    /// The signing request proposing `extrinsic_info` with nonce 7
+    fn propose(extrinsic_info: &ExtrinsicInfo) -> SigningRequest {
+        SigningRequest {
+            extrinsic: extrinsic_info.clone(),
+            context: "substrate".to_string(),
+            genesis_hash: GENESIS_HASH.to_string(),
+            spec_version: SPEC_VERSION,
+            transaction_version: TRANSACTION_VERSION,
+            payload_hash: payload_hash(&payload(extrinsic_info, 7, GENESIS_HASH)),
+            nonce_index: None,
+            signers: Vec::new(),
+        }
+    }
+
+    fn check(request: &SigningRequest, genesis_hash: &str, payload: &[u8]) -> Result<(), CliError> {
+        request.check(genesis_hash, SPEC_VERSION, TRANSACTION_VERSION, payload)
+    }
+
+    #[test]
+    fn the_proposed_payload_is_accepted() {
+        let extrinsic_info = extrinsic("((197, 38))", mortal(64));
+        let request = propose(&extrinsic_info);
+
+        check(&request, GENESIS_HASH, &payload(&extrinsic_info, 7, GENESIS_HASH)).unwrap();
+    }
+
+    #[test]
+    fn a_payload_with_other_call_data_is_refused() {
+        let request = propose(&extrinsic("((197, 38))", None));
+        let other = payload(&extrinsic("((197, 39))", None), 7, GENESIS_HASH);
+
+        assert!(matches!(check(&request, GENESIS_HASH, &other), Err(CliError::Protocol { .. })));
+    }
+
+    #[test]
+    fn a_payload_with_another_nonce_is_refused() {
+        let extrinsic_info = extrinsic("((197, 38))", None);
+        let request = propose(&extrinsic_info);
+        let other = payload(&extrinsic_info, 8, GENESIS_HASH);
+
+        assert!(matches!(check(&request, GENESIS_HASH, &other), Err(CliError::Protocol { .. })));
+    }
+
+    #[test]
+    fn a_payload_with_another_era_is_refused() {
+        let request = propose(&extrinsic("((197, 38))", mortal(64)));
+
+        for mortality in [None, mortal(128)] {
+            let other = payload(&extrinsic("((197, 38))", mortality), 7, GENESIS_HASH);
+
+            assert!(matches!(check(&request, GENESIS_HASH, &other), Err(CliError::Protocol { .. })));
+        }
+    }
+
+    #[test]
+    fn a_payload_for_another_chain_is_refused() {
+        let extrinsic_info = extrinsic("((197, 38))", None);
+        let request = propose(&extrinsic_info);
+        let other = payload(&extrinsic_info, 7, OTHER_GENESIS_HASH);
+
+        let refused = check(&request, OTHER_GENESIS_HASH, &other).unwrap_err();
+        assert!(matches!(refused, CliError::Protocol { .. }));
+        assert!(refused.message().contains("genesis hash"));
+
+        // A participant claiming the proposed chain still rebuilds a payload with another hash
+        assert!(matches!(check(&request, GENESIS_HASH, &other), Err(CliError::Protocol { .. })));
+
+        let refused = request.check(GENESIS_HASH, SPEC_VERSION + 1, TRANSACTION_VERSION, &payload(&extrinsic_info, 7, GENESIS_HASH));
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+    }
+}
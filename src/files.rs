@@ -1,57 +1,152 @@
 use std::path::{Path, PathBuf};
+use crate::participant::Participant;
 
+/// Locations of the files exchanged and produced by the protocols.
+///
+/// Without a session everything lives flat in the base directory. A session gets its own
+/// subdirectory for the messages of its ceremony, while the key material it signs with lives in
+/// the directory of the DKG session that produced it.
+#[derive(Clone)]
 pub struct FilePaths {
     base: PathBuf,
+    session: PathBuf,
+    keys: PathBuf,
 }
 
 impl FilePaths {
     pub fn new(base: impl AsRef<Path>) -> Self {
+        let base = base.as_ref().to_path_buf();
+
+        Self {
+            session: base.clone(),
+            keys: base.clone(),
+            base,
+        }
+    }
+
+    /// Paths for a session stored in `session`, using the key material stored in `keys`
+    pub fn with_session(base: impl AsRef<Path>, session: impl AsRef<Path>, keys: impl AsRef<Path>) -> Self {
         Self {
             base: base.as_ref().to_path_buf(),
+            session: session.as_ref().to_path_buf(),
+            keys: keys.as_ref().to_path_buf(),
         }
     }
 
-    pub fn contributor_secret_key(&self, participant: u16) -> PathBuf {
-        self.base.join(format!("contributor_secret_key{}.json", participant))
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    pub fn sessions(&self) -> PathBuf {
+        self.base.join("sessions")
+    }
+
+    pub fn session_dir(&self, session_id: &str) -> PathBuf {
+        self.sessions().join(session_id)
+    }
+
+    /// The directory of the messages of the session, or the base directory without one
+    pub fn session(&self) -> &Path {
+        &self.session
+    }
+
+    pub fn session_manifest(&self) -> PathBuf {
+        self.session.join("session.json")
+    }
+
+    pub fn contributor_secret_key(&self, participant: &Participant) -> PathBuf {
+        self.base.join(format!("contributor_secret_key_{}.json", participant.ss58()))
     }
 
     pub fn recipients(&self) -> PathBuf {
-        self.base.join("recipients.json")
+        self.keys.join("recipients.json")
     }
 
     pub fn all_messages(&self) -> PathBuf {
-        self.base.join("all_messages.json")
+        self.session.join("all_messages.json")
     }
 
-    pub fn generation_output(&self, participant: u16) -> PathBuf {
-        self.base.join(format!("generation_output{}.json", participant))
+    pub fn generation_output(&self, participant: &Participant) -> PathBuf {
+        self.keys.join(format!("generation_output_{}.json", participant.ss58()))
     }
 
-    pub fn signing_share(&self, participant: u16) -> PathBuf {
-        self.base.join(format!("signing_share{}.json", participant))
+    pub fn generation_outputs(&self) -> PathBuf {
+        self.session.join("generation_outputs.json")
+    }
+
+    pub fn refresh_messages(&self) -> PathBuf {
+        self.session.join("refresh_messages.json")
+    }
+
+    pub fn reshare_messages(&self) -> PathBuf {
+        self.session.join("reshare_messages.json")
+    }
+
+    pub fn dkg_certificate(&self) -> PathBuf {
+        self.keys.join("dkg_certificate.json")
+    }
+
+    pub fn signing_share(&self, participant: &Participant) -> PathBuf {
+        self.keys.join(format!("signing_share_{}.json", participant.ss58()))
     }
 
     pub fn threshold_public_key(&self) -> PathBuf {
-        self.base.join("threshold_public_key.json")
+        self.keys.join("threshold_public_key.json")
+    }
+
+    pub fn signing_nonce(&self, participant: &Participant) -> PathBuf {
+        self.session.join(format!("signing_nonce_{}.json", participant.ss58()))
     }
 
-    pub fn signing_nonce(&self, participant: u16) -> PathBuf {
-        self.base.join(format!("signing_nonce{}.json", participant))
+    /// The signing nonces a participant generated ahead of signing sessions, which any session
+    /// using the same key material can pick from
+    pub fn precommitted_nonces(&self, participant: &Participant) -> PathBuf {
+        self.keys.join(format!("precommitted_nonces_{}.json", participant.ss58()))
+    }
+
+    pub fn precommitments(&self) -> PathBuf {
+        self.keys.join("precommitments.json")
     }
 
     pub fn signing_commitments(&self) -> PathBuf {
-        self.base.join("signing_commitments.json")
+        self.session.join("signing_commitments.json")
     }
 
     pub fn signing_packages(&self) -> PathBuf {
-        self.base.join("signing_packages.json")
+        self.session.join("signing_packages.json")
     }
 
     pub fn threshold_signature(&self) -> PathBuf {
-        self.base.join("threshold_signature.json")
+        self.session.join("threshold_signature.json")
+    }
+
+    pub fn signing_request(&self) -> PathBuf {
+        self.session.join("signing_request.json")
     }
 
     pub fn extrinsic_info(&self) -> PathBuf {
-        self.base.join("extrinsic_info.json")
+        self.session.join("extrinsic_info.json")
+    }
+
+    pub fn signed_message(&self) -> PathBuf {
+        self.session.join("signed_message.json")
+    }
+
+    /// Where older versions kept the files of a participant, named by its position among the
+    /// recipients rather than by its account. Still read and written if they exist.
+    pub fn legacy_contributor_secret_key(&self, participant: &Participant) -> PathBuf {
+        self.base.join(format!("contributor_secret_key{}.json", participant.index))
+    }
+
+    pub fn legacy_generation_output(&self, participant: &Participant) -> PathBuf {
+        self.keys.join(format!("generation_output{}.json", participant.index))
+    }
+
+    pub fn legacy_signing_share(&self, participant: &Participant) -> PathBuf {
+        self.keys.join(format!("signing_share{}.json", participant.index))
+    }
+
+    pub fn legacy_signing_nonce(&self, participant: &Participant) -> PathBuf {
+        self.session.join(format!("signing_nonce{}.json", participant.index))
     }
 }
@@ -0,0 +1,179 @@
+//! Views into the byte encodings of protocol messages, for the diagnostics that the `olaf` types
+//! do not expose accessors for, and for encoding the `SPPOutput`s of refreshed and reshared
+//! signing shares.
+
+use crate::error::CliError;
+
+/// The fields of an encoded `SigningPackage`
+pub struct SigningPackageView {
+    pub signature_share: [u8; 32],
+    pub payload: Vec<u8>,
+    pub context: Vec<u8>,
+    pub signing_commitments: Vec<[u8; 64]>,
+    pub spp_output: Vec<u8>,
+}
+
+impl SigningPackageView {
+    pub fn parse(bytes: &[u8]) -> Result<Self, CliError> {
+        let mut reader = Reader { bytes };
+
+        let signature_share = reader.array()?;
+        let payload_length = reader.length()?;
+        let payload = reader.take(payload_length)?.to_vec();
+        let context_length = reader.length()?;
+        let context = reader.take(context_length)?.to_vec();
+
+        let signing_commitments = (0..reader.length()?)
+            .map(|_| reader.array())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            signature_share,
+            payload,
+            context,
+            signing_commitments,
+            spp_output: reader.bytes.to_vec(),
+        })
+    }
+}
+
+/// The leading fields of an encoded `AllMessage`: its sender and the parameters it was created with
+pub struct AllMessageView {
+    pub sender: [u8; 32],
+    pub participants: u16,
+    pub threshold: u16,
+}
+
+impl AllMessageView {
+    pub fn parse(bytes: &[u8]) -> Result<Self, CliError> {
+        let mut reader = Reader { bytes };
+
+        let sender = reader.array()?;
+        let _encryption_nonce: [u8; 16] = reader.array()?;
+        let participants = u16::from_le_bytes(reader.array()?);
+        let threshold = u16::from_le_bytes(reader.array()?);
+
+        Ok(Self {
+            sender,
+            participants,
+            threshold,
+        })
+    }
+}
+
+/// The fields of an encoded `SPPOutput`
+pub struct SPPOutputView {
+    pub participants: u16,
+    pub threshold: u16,
+    pub threshold_public_key: [u8; 32],
+    /// The identifier and verifying share of each participant, in the order of the recipients
+    pub verifying_shares: Vec<([u8; 32], [u8; 32])>,
+}
+
+impl SPPOutputView {
+    pub fn parse(bytes: &[u8]) -> Result<Self, CliError> {
+        let mut reader = Reader { bytes };
+
+        let participants = u16::from_le_bytes(reader.array()?);
+        let threshold = u16::from_le_bytes(reader.array()?);
+        let threshold_public_key = reader.array()?;
+
+        let verifying_shares = (0..reader.length()?)
+            .map(|_| Ok((reader.array()?, reader.array()?)))
+            .collect::<Result<_, CliError>>()?;
+
+        Ok(Self {
+            participants,
+            threshold,
+            threshold_public_key,
+            verifying_shares,
+        })
+    }
+
+    /// Encodes the view back into an `SPPOutput`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(40 + self.verifying_shares.len() * 64);
+        bytes.extend_from_slice(&self.participants.to_le_bytes());
+        bytes.extend_from_slice(&self.threshold.to_le_bytes());
+        bytes.extend_from_slice(&self.threshold_public_key);
+        bytes.extend_from_slice(&(self.verifying_shares.len() as u32).to_le_bytes());
+        for (identifier, verifying_share) in &self.verifying_shares {
+            bytes.extend_from_slice(identifier);
+            bytes.extend_from_slice(verifying_share);
+        }
+        bytes
+    }
+
+    /// The position of a verifying share among the recipients
+    pub fn position(&self, verifying_share: &[u8; 32]) -> Option<usize> {
+        self.verifying_shares.iter().position(|(_, share)| share == verifying_share)
+    }
+}
+
+/// The parts of an encoded `SPPOutputMessage`: the `SPPOutput` between the verifying share of its
+/// signer and its signature
+pub struct SPPOutputMessageView<'a> {
+    pub signer: &'a [u8],
+    pub spp_output: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+impl<'a> SPPOutputMessageView<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, CliError> {
+        let mut reader = Reader { bytes };
+
+        let signer = reader.take(32)?;
+        let spp_output_length = reader.bytes.len().checked_sub(64)
+            .ok_or_else(|| CliError::protocol(format!("Truncated SPPOutputMessage: {} bytes left for the SPPOutput and signature", reader.bytes.len())))?;
+        let spp_output = reader.take(spp_output_length)?;
+
+        Ok(Self {
+            signer,
+            spp_output,
+            signature: reader.bytes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.signer, self.spp_output, self.signature].concat()
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Whether every byte was read
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The bytes that were not read yet
+    pub(crate) fn rest(self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub(crate) fn take(&mut self, length: usize) -> Result<&'a [u8], CliError> {
+        if self.bytes.len() < length {
+            return Err(CliError::protocol(format!("Truncated message: expected {} more bytes, got {}", length, self.bytes.len())));
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], CliError> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    pub(crate) fn length(&mut self) -> Result<usize, CliError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+}
@@ -0,0 +1,179 @@
+//! Loading contributor keys the way Substrate wallets create them.
+//!
+//! Besides the raw mini secret of `subkey generate`, a contributor secret key can be a secret URI
+//! (a BIP39 phrase or hex seed, with `//hard` and `/soft` junctions and a `///password`) or the
+//! encrypted JSON export of polkadot-js. Each resolves to the same sr25519 keypair the wallet shows.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use crypto_secretbox::{
+    aead::{Aead, KeyInit},
+    Key, Nonce, XSalsa20Poly1305,
+};
+use schnorrkel::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use sp_core::{crypto::Pair as _, sr25519};
+use zeroize::Zeroizing;
+use crate::error::CliError;
+
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+const SECRET_KEY_LENGTH: usize = 64;
+const PUBLIC_KEY_LENGTH: usize = 32;
+const SCRYPT_SALT_LENGTH: usize = 32;
+const SCRYPT_PARAMS_LENGTH: usize = 12;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+
+/// Derives the keypair of a secret URI, as `subkey inspect` does, and returns it encoded
+pub fn keypair_from_secret_uri(secret_uri: &str) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let pair = sr25519::Pair::from_string(secret_uri, None)
+        .map_err(|e| CliError::key_parsing(format!("Invalid secret URI: {:?}", e)))?;
+
+    let secret_key_bytes = Zeroizing::new(pair.to_raw_vec());
+    let secret_key = SecretKey::from_bytes(&secret_key_bytes)
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse derived secret key: {}", e)).with_source(e))?;
+
+    Ok(Zeroizing::new(secret_key.to_keypair().to_bytes().to_vec()))
+}
+
+/// The encoding of a polkadot-js JSON export
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PolkadotJsEncoding {
+    pub content: Vec<String>,
+    #[serde(rename = "type")]
+    pub kind: Vec<String>,
+    pub version: String,
+}
+
+/// An account exported from polkadot-js, encrypted with the password of the account
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PolkadotJsExport {
+    pub encoded: String,
+    pub encoding: PolkadotJsEncoding,
+    pub address: String,
+}
+
+impl PolkadotJsExport {
+    /// Decrypts the exported sr25519 keypair and returns it encoded
+    pub fn open(&self, password: &str) -> Result<Zeroizing<Vec<u8>>, CliError> {
+        let supported = self.encoding.version == "3"
+            && self.encoding.content.iter().any(|content| content == "sr25519")
+            && self.encoding.kind.iter().any(|kind| kind == "scrypt")
+            && self.encoding.kind.iter().any(|kind| kind == "xsalsa20-poly1305");
+
+        if !supported {
+            return Err(CliError::serialization(format!(
+                "Unsupported polkadot-js export of {}: only version 3 sr25519 exports encrypted with scrypt and xsalsa20-poly1305 are supported",
+                self.address
+            )));
+        }
+
+        let encoded = BASE64
+            .decode(&self.encoded)
+            .map_err(|e| CliError::serialization(format!("Failed to decode polkadot-js export: {}", e)).with_source(e))?;
+
+        if encoded.len() < SCRYPT_SALT_LENGTH + SCRYPT_PARAMS_LENGTH + NONCE_LENGTH {
+            return Err(CliError::serialization("The polkadot-js export is too short".to_string()));
+        }
+
+        let (salt, rest) = encoded.split_at(SCRYPT_SALT_LENGTH);
+        let (params, rest) = rest.split_at(SCRYPT_PARAMS_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+        let param = |index: usize| u32::from_le_bytes(params[index * 4..index * 4 + 4].try_into().expect("4 bytes"));
+        let (n, p, r) = (param(0), param(1), param(2));
+
+        if !n.is_power_of_two() {
+            return Err(CliError::serialization(format!("Invalid scrypt parameter N of polkadot-js export: {}", n)));
+        }
+
+        let params = scrypt::Params::new(n.trailing_zeros() as u8, r, p, KEY_LENGTH)
+            .map_err(|e| CliError::serialization(format!("Invalid scrypt parameters of polkadot-js export: {}", e)))?;
+
+        let mut key = Zeroizing::new([0u8; KEY_LENGTH]);
+        scrypt::scrypt(password.as_bytes(), salt, &params, &mut key[..])
+            .map_err(|e| CliError::key_parsing(format!("Failed to derive polkadot-js export key: {}", e)))?;
+
+        let pkcs8 = Zeroizing::new(
+            XSalsa20Poly1305::new(Key::from_slice(&key[..]))
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| CliError::key_parsing(format!("Failed to decrypt polkadot-js export of {}: wrong password", self.address)))?,
+        );
+
+        decode_pkcs8(&pkcs8)
+    }
+}
+
+/// Decodes the PKCS#8 encoding of polkadot-js, which holds the secret key in its ed25519 form
+fn decode_pkcs8(pkcs8: &[u8]) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let secret_start = PKCS8_HEADER.len();
+    let divider_start = secret_start + SECRET_KEY_LENGTH;
+    let public_start = divider_start + PKCS8_DIVIDER.len();
+
+    if pkcs8.len() < public_start + PUBLIC_KEY_LENGTH
+        || pkcs8[..secret_start] != PKCS8_HEADER
+        || pkcs8[divider_start..public_start] != PKCS8_DIVIDER
+    {
+        return Err(CliError::key_parsing("Invalid PKCS#8 encoding in polkadot-js export".to_string()));
+    }
+
+    let secret_key = SecretKey::from_ed25519_bytes(&pkcs8[secret_start..divider_start])
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse secret key of polkadot-js export: {}", e)).with_source(e))?;
+
+    let public_key = PublicKey::from_bytes(&pkcs8[public_start..public_start + PUBLIC_KEY_LENGTH])
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse public key of polkadot-js export: {}", e)).with_source(e))?;
+
+    let keypair = secret_key.to_keypair();
+    if keypair.public != public_key {
+        return Err(CliError::key_parsing("The secret key of the polkadot-js export does not match its public key".to_string()));
+    }
+
+    Ok(Zeroizing::new(keypair.to_bytes().to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use schnorrkel::Keypair;
+    use sp_core::crypto::{AccountId32, Ss58Codec, DEV_PHRASE};
+    use super::*;
+
+    /// An export of the account of the development phrase, in the version 3 format of
+    /// polkadot-js, encrypted with the password `olaf-cli`
+    const DEV_EXPORT: &str = r#"{
+        "encoded": "kb2Ye4ZfettwAwgrfNrTpr5DbUk1fvow3jL7CGNHMQ4AgAAAAQAAAAgAAADBoADK945qNRHKajeZXdP+pfoyPGVwQsHxrWc0Btnv2NCZRvl4eLGKu8vr9kJjqjhB0OAI6bgwRYb8ZAsea4mXtyuSYdcqfB68rD6UVGEFtybNl1RLlzCzjO7T44wLc9E3z+Ucu+AadpW+wF8WIEKmjrRbd/cgcjV8qxZvTnWfTSHDWi1Q2VtytJdR0/WZmUWYdb9yT3TgvjDQx9KN",
+        "encoding": { "content": ["pkcs8", "sr25519"], "type": ["scrypt", "xsalsa20-poly1305"], "version": "3" },
+        "address": "5DfhGyQdFobKM8NsWvEeAKk5EQQgYe9AydgJ7rMB6E1EqRzV",
+        "meta": { "genesisHash": "", "name": "dev", "whenCreated": 0 }
+    }"#;
+
+    fn ss58(keypair_bytes: &[u8]) -> String {
+        let keypair = Keypair::from_bytes(keypair_bytes).unwrap();
+        AccountId32::from(keypair.public.to_bytes()).to_ss58check()
+    }
+
+    #[test]
+    fn secret_uri_yields_the_subkey_account() {
+        let alice = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+
+        assert_eq!(ss58(&keypair_from_secret_uri("//Alice").unwrap()), alice);
+        assert_eq!(ss58(&keypair_from_secret_uri(&format!("{}//Alice", DEV_PHRASE)).unwrap()), alice);
+        assert_eq!(ss58(&keypair_from_secret_uri(DEV_PHRASE).unwrap()), "5DfhGyQdFobKM8NsWvEeAKk5EQQgYe9AydgJ7rMB6E1EqRzV");
+    }
+
+    #[test]
+    fn polkadot_js_export_yields_its_account() {
+        let export: PolkadotJsExport = serde_json::from_str(DEV_EXPORT).unwrap();
+        let keypair_bytes = export.open("olaf-cli").unwrap();
+
+        assert_eq!(ss58(&keypair_bytes), export.address);
+        assert_eq!(ss58(&keypair_bytes), "5DfhGyQdFobKM8NsWvEeAKk5EQQgYe9AydgJ7rMB6E1EqRzV");
+        assert_eq!(*keypair_bytes, *keypair_from_secret_uri(DEV_PHRASE).unwrap());
+    }
+
+    #[test]
+    fn polkadot_js_export_refuses_the_wrong_password() {
+        let export: PolkadotJsExport = serde_json::from_str(DEV_EXPORT).unwrap();
+
+        assert!(matches!(export.open("wrong password"), Err(CliError::KeyParsing { .. })));
+    }
+}
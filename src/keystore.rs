@@ -0,0 +1,401 @@
+use std::{path::PathBuf, sync::OnceLock};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use crate::{
+    error::CliError,
+    keys::{keypair_from_secret_uri, PolkadotJsExport},
+    storage::{Artifact, Storage},
+};
+
+/// Environment variable checked for the keystore password before prompting
+pub const PASSWORD_ENV: &str = "OLAF_PASSWORD";
+
+/// Environment variable checked for the password of an account exported from polkadot-js before
+/// prompting
+pub const IMPORT_PASSWORD_ENV: &str = "OLAF_IMPORT_PASSWORD";
+
+const KEYSTORE_VERSION: u8 = 1;
+const KDF_NAME: &str = "scrypt";
+const CIPHER_NAME: &str = "xchacha20poly1305";
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+
+/// Parameters of the key derivation function used to turn a password into an encryption key
+#[derive(Serialize, Deserialize)]
+pub struct KdfParams {
+    pub name: String,
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    pub salt: String,
+}
+
+/// A secret encrypted at rest with a password-derived key
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedSecret {
+    pub version: u8,
+    pub kdf: KdfParams,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl EncryptedSecret {
+    /// Encrypts `secret` under a key derived from `password` with a fresh salt and nonce
+    pub fn seal(secret: &[u8], password: &str) -> Result<Self, CliError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+
+        let kdf = KdfParams {
+            name: KDF_NAME.to_string(),
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: hex::encode(salt),
+        };
+
+        let key = derive_key(password, &kdf)?;
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key[..]))
+            .encrypt(XNonce::from_slice(&nonce), secret)
+            .map_err(|e| CliError::key_parsing(format!("Failed to encrypt secret: {}", e)))?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            kdf,
+            cipher: CIPHER_NAME.to_string(),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypts the secret, failing if the password is wrong or the ciphertext was tampered with
+    pub fn open(&self, password: &str) -> Result<Zeroizing<Vec<u8>>, CliError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(CliError::serialization(format!("Unsupported keystore version: {}", self.version)));
+        }
+        if self.cipher != CIPHER_NAME {
+            return Err(CliError::serialization(format!("Unsupported keystore cipher: {}", self.cipher)));
+        }
+
+        let nonce = hex::decode(&self.nonce)
+            .map_err(|e| CliError::serialization(format!("Failed to decode keystore nonce: {}", e)).with_source(e))?;
+        if nonce.len() != NONCE_LENGTH {
+            return Err(CliError::serialization(format!("Invalid keystore nonce length: {}", nonce.len())));
+        }
+        let ciphertext = hex::decode(&self.ciphertext)
+            .map_err(|e| CliError::serialization(format!("Failed to decode keystore ciphertext: {}", e)).with_source(e))?;
+
+        let key = derive_key(password, &self.kdf)?;
+        let secret = XChaCha20Poly1305::new(Key::from_slice(&key[..]))
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| CliError::key_parsing("Failed to decrypt secret: wrong password or corrupted keystore".to_string()))?;
+
+        Ok(Zeroizing::new(secret))
+    }
+}
+
+fn derive_key(password: &str, kdf: &KdfParams) -> Result<Zeroizing<[u8; KEY_LENGTH]>, CliError> {
+    if kdf.name != KDF_NAME {
+        return Err(CliError::serialization(format!("Unsupported keystore KDF: {}", kdf.name)));
+    }
+
+    let salt = hex::decode(&kdf.salt)
+        .map_err(|e| CliError::serialization(format!("Failed to decode keystore salt: {}", e)).with_source(e))?;
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, KEY_LENGTH)
+        .map_err(|e| CliError::serialization(format!("Invalid keystore KDF parameters: {}", e)))?;
+
+    let mut key = Zeroizing::new([0u8; KEY_LENGTH]);
+    scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key[..])
+        .map_err(|e| CliError::key_parsing(format!("Failed to derive keystore key: {}", e)))?;
+
+    Ok(key)
+}
+
+/// A secret as found on disk: either a legacy plaintext file or an encrypted keystore
+pub enum StoredSecret {
+    /// A JSON hex string (contributor secret keys) or a JSON byte array (signing shares and nonces)
+    Plain(Zeroizing<Vec<u8>>),
+    /// A JSON string holding a secret URI or BIP39 phrase (contributor secret keys)
+    SecretUri(Zeroizing<String>),
+    /// An account exported from polkadot-js (contributor secret keys)
+    PolkadotJs(PolkadotJsExport),
+    Encrypted(EncryptedSecret),
+}
+
+impl StoredSecret {
+    pub fn parse(content: &str) -> Result<Self, CliError> {
+        let value: serde_json::Value = serde_json::from_str(content)
+            .map_err(|e| CliError::serialization(format!("Failed to deserialize secret: {}", e)).with_source(e))?;
+
+        match value {
+            serde_json::Value::String(string) => {
+                let string = Zeroizing::new(string);
+                let trimmed = string.trim();
+
+                // Anything that is not plain hex, such as a phrase or a path with junctions, is a secret URI
+                match hex::decode(trimmed.strip_prefix("0x").unwrap_or(trimmed)) {
+                    Ok(bytes) => Ok(StoredSecret::Plain(Zeroizing::new(bytes))),
+                    Err(_) => Ok(StoredSecret::SecretUri(Zeroizing::new(trimmed.to_string()))),
+                }
+            }
+            serde_json::Value::Array(_) => {
+                let bytes: Vec<u8> = serde_json::from_value(value)
+                    .map_err(|e| CliError::serialization(format!("Failed to deserialize secret bytes: {}", e)).with_source(e))?;
+                Ok(StoredSecret::Plain(Zeroizing::new(bytes)))
+            }
+            serde_json::Value::Object(ref object) if object.contains_key("encoded") => {
+                let export: PolkadotJsExport = serde_json::from_value(value)
+                    .map_err(|e| CliError::serialization(format!("Failed to deserialize polkadot-js export: {}", e)).with_source(e))?;
+                Ok(StoredSecret::PolkadotJs(export))
+            }
+            serde_json::Value::Object(_) => {
+                let encrypted: EncryptedSecret = serde_json::from_value(value)
+                    .map_err(|e| CliError::serialization(format!("Failed to deserialize encrypted secret: {}", e)).with_source(e))?;
+                Ok(StoredSecret::Encrypted(encrypted))
+            }
+            _ => Err(CliError::serialization("Unrecognized secret file format".to_string())),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, StoredSecret::Encrypted(_) | StoredSecret::PolkadotJs(_))
+    }
+
+    /// Returns the secret bytes. Secret URIs and polkadot-js exports reveal the encoded keypair
+    /// they stand for, rather than a mini secret key.
+    pub fn reveal(self, unlock: &Unlock) -> Result<Zeroizing<Vec<u8>>, CliError> {
+        match self {
+            StoredSecret::Plain(bytes) => Ok(bytes),
+            StoredSecret::SecretUri(secret_uri) => keypair_from_secret_uri(&secret_uri),
+            StoredSecret::PolkadotJs(export) => export.open(unlock.import_password()?),
+            StoredSecret::Encrypted(encrypted) => encrypted.open(unlock.password()?),
+        }
+    }
+}
+
+/// Obtains the keystore password, in order of preference, from a password file,
+/// the `OLAF_PASSWORD` environment variable or an interactive prompt.
+///
+/// The password of an account exported from polkadot-js is obtained and cached separately, from
+/// the `OLAF_IMPORT_PASSWORD` environment variable or its own prompt, so it never ends up
+/// encrypting the keystore.
+pub struct Unlock {
+    password_file: Option<PathBuf>,
+    password: OnceLock<Zeroizing<String>>,
+    import_password: OnceLock<Zeroizing<String>>,
+}
+
+impl Unlock {
+    pub fn new(password_file: Option<String>) -> Self {
+        Self {
+            password_file: password_file.map(PathBuf::from),
+            password: OnceLock::new(),
+            import_password: OnceLock::new(),
+        }
+    }
+
+    /// Like [`Unlock::new`], with the password of accounts exported from polkadot-js given rather
+    /// than taken from `OLAF_IMPORT_PASSWORD` or a prompt
+    pub fn with_import_password(password_file: Option<String>, import_password: Zeroizing<String>) -> Self {
+        Self {
+            import_password: OnceLock::from(import_password),
+            ..Self::new(password_file)
+        }
+    }
+
+    /// Returns the password, asking for it at most once per command
+    pub fn password(&self) -> Result<&str, CliError> {
+        if let Some(password) = self.password.get() {
+            return Ok(password.as_str());
+        }
+
+        let password = self.obtain_password(false)?;
+        Ok(self.password.get_or_init(|| password).as_str())
+    }
+
+    /// Returns the password to encrypt new secrets with, asking twice when prompting
+    pub fn new_password(&self) -> Result<&str, CliError> {
+        if let Some(password) = self.password.get() {
+            return Ok(password.as_str());
+        }
+
+        let password = self.obtain_password(true)?;
+        Ok(self.password.get_or_init(|| password).as_str())
+    }
+
+    /// Returns the password of an account exported from polkadot-js, asking for it at most once
+    /// per command
+    pub fn import_password(&self) -> Result<&str, CliError> {
+        if let Some(password) = self.import_password.get() {
+            return Ok(password.as_str());
+        }
+
+        let password = match std::env::var(IMPORT_PASSWORD_ENV) {
+            Ok(password) => Zeroizing::new(password),
+            Err(_) => Zeroizing::new(
+                rpassword::prompt_password("Password of the polkadot-js account: ")
+                    .map_err(|e| CliError::io(format!("Failed to read password: {}", e)).with_source(e))?,
+            ),
+        };
+
+        Ok(self.import_password.get_or_init(|| password).as_str())
+    }
+
+    fn obtain_password(&self, confirm: bool) -> Result<Zeroizing<String>, CliError> {
+        let password = if let Some(path) = &self.password_file {
+            let content = Zeroizing::new(
+                std::fs::read_to_string(path)
+                    .map_err(|e| CliError::io(format!("Failed to read password file {:?}: {}", path, e)).with_source(e))?,
+            );
+            Zeroizing::new(content.trim_end_matches(['\r', '\n']).to_string())
+        } else if let Ok(password) = std::env::var(PASSWORD_ENV) {
+            Zeroizing::new(password)
+        } else {
+            prompt_password(confirm)?
+        };
+
+        // Checked once for every source, so an empty file or variable never encrypts a secret
+        if password.is_empty() {
+            return Err(CliError::input("The keystore password must not be empty".to_string()));
+        }
+
+        Ok(password)
+    }
+}
+
+fn prompt_password(confirm: bool) -> Result<Zeroizing<String>, CliError> {
+    let password = Zeroizing::new(
+        rpassword::prompt_password("Keystore password: ")
+            .map_err(|e| CliError::io(format!("Failed to read password: {}", e)).with_source(e))?,
+    );
+
+    if confirm {
+        let confirmation = Zeroizing::new(
+            rpassword::prompt_password("Confirm keystore password: ")
+                .map_err(|e| CliError::io(format!("Failed to read password: {}", e)).with_source(e))?,
+        );
+        if *password != *confirmation {
+            return Err(CliError::input("Passwords do not match".to_string()));
+        }
+    }
+
+    Ok(password)
+}
+
+/// Reads a secret artifact, decrypting it if it is an encrypted keystore. A secret stored
+/// unencrypted comes with a warning for the user.
+pub fn read_secret(storage: &dyn Storage, artifact: Artifact, unlock: &Unlock) -> Result<(Zeroizing<Vec<u8>>, Option<String>), CliError> {
+    let content = Zeroizing::new(
+        storage
+            .get(artifact)?
+            .ok_or_else(|| CliError::io(format!("No {} found at {}", artifact, storage.location(artifact))))?,
+    );
+
+    let content = std::str::from_utf8(&content)
+        .map_err(|e| CliError::serialization(format!("Failed to parse {}: {}", artifact, e)).with_source(e))?;
+
+    let stored = StoredSecret::parse(content)
+        .map_err(|e| e.context(format!("Failed to parse {}", artifact)))?;
+
+    let warning = (!stored.is_encrypted()).then(|| {
+        format!(
+            "The {} at {} is stored unencrypted, run `olaf-cli encrypt-secrets` to protect it with a password",
+            artifact,
+            storage.location(artifact),
+        )
+    });
+
+    let secret = stored
+        .reveal(unlock)
+        .map_err(|e| e.context(format!("Failed to unlock {}", artifact)))?;
+
+    Ok((secret, warning))
+}
+
+/// Encrypts a secret and stores it as `artifact`, replacing any previous content
+pub fn write_secret(storage: &dyn Storage, artifact: Artifact, secret: &[u8], unlock: &Unlock) -> Result<(), CliError> {
+    let encrypted = EncryptedSecret::seal(secret, unlock.new_password()?)?;
+    let encrypted_json = serde_json::to_string_pretty(&encrypted)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize encrypted secret: {}", e)).with_source(e))?;
+
+    storage.put(artifact, encrypted_json.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use super::*;
+
+    fn unlock_with(dir: &TempDir, password: &str) -> Unlock {
+        let password_file = dir.path().join("password");
+        std::fs::write(&password_file, password).unwrap();
+
+        Unlock::new(Some(password_file.to_string_lossy().into_owned()))
+    }
+
+    #[test]
+    fn seal_and_open_round_trip() {
+        let encrypted = EncryptedSecret::seal(b"secret", "password").unwrap();
+
+        assert_eq!(&encrypted.open("password").unwrap()[..], b"secret");
+    }
+
+    #[test]
+    fn open_fails_with_the_wrong_password() {
+        let encrypted = EncryptedSecret::seal(b"secret", "password").unwrap();
+
+        assert!(matches!(encrypted.open("wrong password"), Err(CliError::KeyParsing { .. })));
+    }
+
+    #[test]
+    fn open_fails_on_tampered_ciphertext() {
+        let mut encrypted = EncryptedSecret::seal(b"secret", "password").unwrap();
+
+        let mut ciphertext = hex::decode(&encrypted.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        encrypted.ciphertext = hex::encode(ciphertext);
+
+        assert!(matches!(encrypted.open("password"), Err(CliError::KeyParsing { .. })));
+    }
+
+    #[test]
+    fn password_file_ends_at_the_line_break() {
+        let dir = TempDir::new().unwrap();
+
+        assert_eq!(unlock_with(&dir, "password\r\n").password().unwrap(), "password");
+    }
+
+    #[test]
+    fn import_password_is_kept_apart_from_the_keystore_password() {
+        let dir = TempDir::new().unwrap();
+        let password_file = dir.path().join("password");
+        std::fs::write(&password_file, "keystore password").unwrap();
+        let unlock = Unlock::with_import_password(
+            Some(password_file.to_string_lossy().into_owned()),
+            Zeroizing::new("import password".to_string()),
+        );
+
+        assert_eq!(unlock.import_password().unwrap(), "import password");
+        assert_eq!(unlock.password().unwrap(), "keystore password");
+        assert_eq!(unlock.new_password().unwrap(), "keystore password");
+    }
+
+    #[test]
+    fn empty_password_file_is_refused() {
+        let dir = TempDir::new().unwrap();
+
+        assert!(matches!(unlock_with(&dir, "").password(), Err(CliError::Input { .. })));
+        assert!(matches!(unlock_with(&dir, "\n").new_password(), Err(CliError::Input { .. })));
+    }
+}
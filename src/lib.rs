@@ -0,0 +1,23 @@
+//! Threshold public key generation and threshold signing for Substrate accounts, with the OLAF
+//! protocols of schnorrkel.
+//!
+//! The rounds of the protocols are available as IO-free functions in [`protocol`], with the
+//! places their artifacts are kept abstracted by [`storage::Storage`]. The `olaf-cli` binary is a
+//! thin layer over them.
+
+pub mod coordinator;
+pub mod dealing;
+pub mod error;
+pub mod extrinsic;
+pub mod files;
+pub mod inspect;
+pub mod keys;
+pub mod keystore;
+pub mod messages;
+pub mod nonces;
+pub mod participant;
+pub mod protocol;
+pub mod session;
+pub mod storage;
+
+pub use error::CliError as Error;
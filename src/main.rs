@@ -1,35 +1,113 @@
 mod cli;
-mod files;
 
-use crate::cli::commands::{
-    sign::{threshold_sign_round1, threshold_sign_round2},
+use cli::commands::{
+    sign::{threshold_sign_message, threshold_sign_precommit, threshold_sign_round1, threshold_sign_round2},
     generate::{generate_threshold_public_key_round1, generate_threshold_public_key_round2},
     submit::submit_threshold_extrinsic,
+    encrypt::encrypt_secrets,
+    session::{session_close, session_list, session_new, session_show},
+    coordinator::run_coordinator,
+    verify::verify_threshold_signature,
+    request::propose_signing_request,
+    whoami::whoami,
+    dkg::{dkg_certify, dkg_status},
+    refresh::{refresh_signing_shares_round1, refresh_signing_shares_round2},
+    reshare::{reshare_round1, reshare_round2},
 };
 use clap::Parser;
-use cli::{commands::aggregate::aggregate_threshold_signature, CliError, Cli, Commands};
+use olaf_cli::storage::Backend;
+use cli::{commands::aggregate::aggregate_threshold_signature, CliError, Cli, Commands, DkgCommands, SessionCommands};
 
 #[tokio::main]
-async fn main() -> Result<(), CliError> {
+async fn main() {
     let cli = Cli::parse();
 
-    match cli.command {
-        Commands::GenerateThresholdPublicKeyRound1 { threshold, participant, files } => generate_threshold_public_key_round1(threshold, participant, files).await?,
-        Commands::GenerateThresholdPublicKeyRound2 { participant, files } => generate_threshold_public_key_round2(participant, files).await?,
-        Commands::ThresholdSignRound1 { participant, files } => threshold_sign_round1(participant, files).await?,
+    let result = match Backend::parse(&cli.storage) {
+        Ok(backend) => run(cli.command, &backend).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        if cli.json_errors {
+            eprintln!("{}", e.to_json());
+        } else {
+            eprintln!("{}", e);
+        }
+
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run(command: Commands, backend: &Backend) -> Result<(), CliError> {
+    match command {
+        Commands::GenerateThresholdPublicKeyRound1 { threshold, participant, files, session, coordinator, expansion_mode, password_file, force } => generate_threshold_public_key_round1(threshold, participant, files, backend, session, coordinator, expansion_mode.into(), password_file, force).await?,
+        Commands::GenerateThresholdPublicKeyRound2 { participant, files, session, coordinator, expansion_mode, password_file, allow_legacy } => {
+            generate_threshold_public_key_round2(participant, files, backend, session, coordinator, expansion_mode.into(), password_file, allow_legacy).await?
+        }
+        Commands::RefreshSigningSharesRound1 { participant, files, session, coordinator, expansion_mode, password_file, force } => refresh_signing_shares_round1(participant, files, backend, session, coordinator, expansion_mode.into(), password_file, force).await?,
+        Commands::RefreshSigningSharesRound2 { participant, files, session, coordinator, expansion_mode, password_file } => refresh_signing_shares_round2(participant, files, backend, session, coordinator, expansion_mode.into(), password_file).await?,
+        Commands::ReshareRound1 { participant, files, session, new_session, coordinator, expansion_mode, password_file, dealers, force } => {
+            reshare_round1(participant, files, backend, session, new_session, coordinator, expansion_mode.into(), password_file, dealers, force).await?
+        }
+        Commands::ReshareRound2 { participant, files, session, coordinator, expansion_mode, password_file, threshold_public_key, dealers } => {
+            reshare_round2(participant, files, backend, session, coordinator, expansion_mode.into(), password_file, threshold_public_key, dealers).await?
+        }
+        Commands::ThresholdSignRound1 { participant, files, session, coordinator, password_file, force } => threshold_sign_round1(participant, files, backend, session, coordinator, password_file, force).await?,
+        Commands::ThresholdSignPrecommit { participant, files, session, coordinator, count, password_file, force } => {
+            threshold_sign_precommit(participant, files, backend, session, coordinator, count, password_file, force).await?
+        }
         Commands::ThresholdSignRound2 {
             participant,
             files,
+            session,
+            coordinator,
+            signing_request,
             url,
             pallet,
             call_name,
             call_data,
-            context
+            context,
+            transaction,
+            password_file,
+            nonce_index,
+        } => {
+            threshold_sign_round2(participant, files, backend, session, coordinator, signing_request, url, pallet, call_name, call_data, context, transaction, password_file, nonce_index).await?
+        }
+        Commands::ProposeSigningRequest { files, session, coordinator, url, pallet, call_name, call_data, context, transaction, nonce_index, precommitted, signers } => {
+            propose_signing_request(files, backend, session, coordinator, url, pallet, call_name, call_data, context, transaction, nonce_index, precommitted, signers).await?
+        }
+        Commands::ThresholdSignMessage {
+            participant,
+            files,
+            session,
+            coordinator,
+            message,
+            message_file,
+            message_hex,
+            context,
+            password_file,
+            nonce_index,
         } => {
-            threshold_sign_round2(participant, files, url, pallet, call_name, call_data, context).await?
+            threshold_sign_message(participant, files, backend, session, coordinator, message, message_file, message_hex, context, password_file, nonce_index).await?
+        }
+        Commands::AggregateThresholdSignature { files, session, coordinator } => aggregate_threshold_signature(files, backend, session, coordinator).await?,
+        Commands::SubmitThresholdExtrinsic { files, session, url } => submit_threshold_extrinsic(files, backend, session, url).await?,
+        Commands::Verify { files, session, coordinator, message, message_file, message_hex, extrinsic, chain, context, signature, signing_packages } => {
+            verify_threshold_signature(files, backend, session, coordinator, message, message_file, message_hex, extrinsic, chain, context, signature, signing_packages).await?
         }
-        Commands::AggregateThresholdSignature { files } => aggregate_threshold_signature(files).await?,
-        Commands::SubmitThresholdExtrinsic { files } => submit_threshold_extrinsic(files).await?,
+        Commands::EncryptSecrets { participant, files, session, password_file } => encrypt_secrets(participant, files, backend, session, password_file).await?,
+        Commands::Whoami { secret_key, files, session, expansion_mode, password_file } => whoami(secret_key, files, backend, session, expansion_mode.into(), password_file).await?,
+        Commands::Dkg { command } => match command {
+            DkgCommands::Status { files, session, coordinator } => dkg_status(files, backend, session, coordinator).await?,
+            DkgCommands::Certify { files, session, coordinator } => dkg_certify(files, backend, session, coordinator).await?,
+        },
+        Commands::Session { command } => match command {
+            SessionCommands::New { purpose, files, id, threshold, recipients, dkg_session } => session_new(files, backend, purpose.into(), id, threshold, recipients, dkg_session).await?,
+            SessionCommands::List { files } => session_list(files, backend).await?,
+            SessionCommands::Show { id, files } => session_show(files, backend, id).await?,
+            SessionCommands::Close { id, files } => session_close(files, backend, id).await?,
+        },
+        Commands::Coordinator { listen } => run_coordinator(listen).await?,
     }
 
     Ok(())
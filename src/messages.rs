@@ -0,0 +1,224 @@
+use std::{fmt, sync::Arc};
+use serde::{Deserialize, Serialize};
+use crate::{
+    coordinator::client::CoordinatorClient,
+    error::CliError,
+    protocol::PrecommitmentBundle,
+    storage::{Artifact, Storage},
+};
+
+/// The collections of protocol messages every participant contributes to and reads from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Collection {
+    AllMessages,
+    SigningCommitments,
+    SigningPackages,
+    GenerationOutputs,
+    RefreshMessages,
+    ReshareMessages,
+    Precommitments,
+}
+
+impl fmt::Display for Collection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Collection::AllMessages => write!(f, "all_messages"),
+            Collection::SigningCommitments => write!(f, "signing_commitments"),
+            Collection::SigningPackages => write!(f, "signing_packages"),
+            Collection::GenerationOutputs => write!(f, "generation_outputs"),
+            Collection::RefreshMessages => write!(f, "refresh_messages"),
+            Collection::ReshareMessages => write!(f, "reshare_messages"),
+            Collection::Precommitments => write!(f, "precommitments"),
+        }
+    }
+}
+
+/// One participant's message in a collection, keyed by the public key of its sender
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Hex encoding of the public key of the sender, or `None` for entries written before entries
+    /// were keyed, which can never be replaced
+    pub sender: Option<String>,
+    pub message: Vec<u8>,
+}
+
+impl Entry {
+    pub fn new(sender: &[u8], message: Vec<u8>) -> Self {
+        Self {
+            sender: Some(hex::encode(sender)),
+            message,
+        }
+    }
+
+    /// The sender, for messages to the user
+    pub fn sender_name(&self) -> &str {
+        self.sender.as_deref().unwrap_or("an unknown sender")
+    }
+}
+
+/// An entry as encoded in a collection: keyed, or a bare message as written by older versions
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EncodedEntry {
+    Keyed(Entry),
+    Legacy(Vec<u8>),
+}
+
+impl From<EncodedEntry> for Entry {
+    fn from(entry: EncodedEntry) -> Self {
+        match entry {
+            EncodedEntry::Keyed(entry) => entry,
+            EncodedEntry::Legacy(message) => Entry { sender: None, message },
+        }
+    }
+}
+
+/// What publishing an entry did to its collection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Published {
+    /// The sender had no entry yet
+    Added,
+    /// The sender already published exactly this entry
+    Unchanged,
+    /// An earlier, different entry of the sender was replaced
+    Replaced,
+}
+
+/// Decodes the entries of a collection, accepting the bare messages of older versions
+pub fn decode_entries(collection: Collection, content: &[u8]) -> Result<Vec<Entry>, CliError> {
+    let entries: Vec<EncodedEntry> = serde_json::from_slice(content)
+        .map_err(|e| CliError::serialization(format!("Failed to deserialize {} data: {}", collection, e)).with_source(e))?;
+
+    Ok(entries.into_iter().map(Entry::from).collect())
+}
+
+/// Decodes one entry, accepting the bare message of older versions
+pub fn decode_entry(content: &[u8]) -> Result<Entry, serde_json::Error> {
+    serde_json::from_slice::<EncodedEntry>(content).map(Entry::from)
+}
+
+/// Puts `entry` into `entries`, in place of the earlier entry of the same sender if there is one
+pub fn upsert(entries: &mut Vec<Entry>, entry: Entry) -> Published {
+    let earlier = entries
+        .iter_mut()
+        .find(|earlier| earlier.sender.is_some() && earlier.sender == entry.sender);
+
+    match earlier {
+        Some(earlier) if *earlier == entry => Published::Unchanged,
+        Some(earlier) => {
+            *earlier = entry;
+            Published::Replaced
+        }
+        None => {
+            entries.push(entry);
+            Published::Added
+        }
+    }
+}
+
+/// Whether `entry` only adds to `earlier`, an entry of the same sender: a bundle of precommitted
+/// signing commitments keeping every commitment of the earlier bundle. Taking its place cannot
+/// change a message another participant may already use.
+pub fn extends(collection: Collection, earlier: &Entry, entry: &Entry) -> bool {
+    if collection != Collection::Precommitments || earlier.sender.is_none() || earlier.sender != entry.sender {
+        return false;
+    }
+
+    match (PrecommitmentBundle::from_bytes(&earlier.message), PrecommitmentBundle::from_bytes(&entry.message)) {
+        (Ok(earlier), Ok(bundle)) => bundle.extends(&earlier),
+        _ => false,
+    }
+}
+
+/// Where the shared collections are exchanged: the local storage, or a coordinator relay
+pub enum MessageBoard {
+    Storage(Arc<dyn Storage>),
+    Coordinator(CoordinatorClient),
+}
+
+impl MessageBoard {
+    pub fn new(storage: Arc<dyn Storage>, coordinator: Option<&str>, session: &str) -> Self {
+        match coordinator {
+            Some(url) => MessageBoard::Coordinator(CoordinatorClient::new(url, session)),
+            None => MessageBoard::Storage(storage),
+        }
+    }
+
+    /// Where a collection is read from and written to, for messages to the user
+    pub fn location(&self, collection: Collection) -> String {
+        match self {
+            MessageBoard::Storage(storage) => storage.location(Artifact::Collection(collection)),
+            MessageBoard::Coordinator(client) => client.collection_url(collection),
+        }
+    }
+
+    /// Returns the message of every entry of the collection, in the order they were first published
+    pub async fn fetch(&self, collection: Collection) -> Result<Vec<Vec<u8>>, CliError> {
+        let entries = match self {
+            MessageBoard::Storage(storage) => storage.list(collection)?,
+            MessageBoard::Coordinator(client) => client.fetch(collection).await?,
+        };
+
+        Ok(entries.into_iter().map(|entry| entry.message).collect())
+    }
+
+    /// Returns every entry of the collection, or none if nothing was published to it yet
+    pub async fn entries(&self, collection: Collection) -> Result<Vec<Entry>, CliError> {
+        match self {
+            MessageBoard::Storage(storage) => storage.entries(collection),
+            MessageBoard::Coordinator(client) => client.fetch(collection).await,
+        }
+    }
+
+    /// Checks that `entry` can be published. A coordinator never replaces an earlier entry of the
+    /// sender with a different one, unless the new one only [`extends`] it, whatever `force` says.
+    /// In local storage, if a later round may already depend on the collection as it is
+    /// (`in_use`), replacing an earlier entry of the sender with a different one is refused unless
+    /// `force` is set, since the other participants would go on with messages that no longer match.
+    pub async fn check_replaceable(&self, collection: Collection, entry: &Entry, in_use: bool, force: bool) -> Result<(), CliError> {
+        if entry.sender.is_none() {
+            return Ok(());
+        }
+
+        match self {
+            MessageBoard::Coordinator(_) => {
+                let entries = self.entries(collection).await?;
+
+                if entries.iter().any(|earlier| earlier.sender == entry.sender && earlier != entry && !extends(collection, earlier, entry)) {
+                    return Err(CliError::input(format!(
+                        "{} already holds a different entry from {}. A coordinator never replaces entries, even with --force: \
+                         run the round again in a new session",
+                        self.location(collection),
+                        entry.sender_name(),
+                    )));
+                }
+            }
+            MessageBoard::Storage(_) if in_use && !force => {
+                let entries = self.entries(collection).await?;
+
+                if entries.iter().any(|earlier| earlier.sender == entry.sender && earlier != entry) {
+                    return Err(CliError::input(format!(
+                        "{} already holds a different entry from {}, which a later round may already use. Pass --force to replace it",
+                        self.location(collection),
+                        entry.sender_name(),
+                    )));
+                }
+            }
+            MessageBoard::Storage(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Publishes an entry to the collection. In local storage it replaces the earlier entry of the
+    /// same sender, while a coordinator refuses a different entry unless it [`extends`] the earlier one.
+    /// A replaced entry is reported as [`Published::Replaced`], for the caller to warn about.
+    pub async fn publish(&self, collection: Collection, entry: Entry) -> Result<Published, CliError> {
+        match self {
+            MessageBoard::Storage(storage) => storage.publish(collection, entry),
+            MessageBoard::Coordinator(client) => client.publish(collection, &entry).await,
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use schnorrkel::olaf::multisig::{SigningCommitments, SigningNonces};
+use serde::{Deserialize, Serialize};
+use sp_core::hashing::blake2_256;
+use zeroize::Zeroizing;
+use crate::{
+    error::CliError,
+    keystore::{EncryptedSecret, Unlock},
+    participant::Participant,
+    storage::{Artifact, Storage},
+};
+
+/// A signing nonce together with what it was created for and whether it has been used
+#[derive(Serialize, Deserialize)]
+pub struct NonceRecord {
+    /// The signing session the nonce was created for
+    pub session: String,
+    /// Hex encoding of the public signing commitment matching the nonce
+    pub commitment: String,
+    pub state: NonceState,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NonceState {
+    /// The encrypted nonce, still available for exactly one signature
+    Unused { secret: EncryptedSecret },
+    /// Tombstone left once the nonce was used, recording what it signed
+    Consumed { commitments_hash: String, payload_hash: String },
+}
+
+/// Hashes a set of signing commitments in the order they are given
+pub fn commitments_hash(signing_commitments: &[SigningCommitments]) -> [u8; 32] {
+    let bytes: Vec<u8> = signing_commitments
+        .iter()
+        .flat_map(|commitment| commitment.to_bytes())
+        .collect();
+
+    blake2_256(&bytes)
+}
+
+/// Encrypts a freshly generated signing nonce and stores it, bound to `session` and its commitment.
+///
+/// It replaces any earlier nonce of `participant` through [`Storage::update`], which wipes the
+/// earlier one, so a nonce that was never used does not survive being replaced.
+pub fn write_nonce(
+    storage: &dyn Storage,
+    participant: Participant,
+    session: &str,
+    signing_nonces: &SigningNonces,
+    signing_commitments: &SigningCommitments,
+    unlock: &Unlock,
+) -> Result<(), CliError> {
+    let signing_nonces_bytes = Zeroizing::new(signing_nonces.to_bytes());
+
+    let record = NonceRecord {
+        session: session.to_string(),
+        commitment: hex::encode(signing_commitments.to_bytes()),
+        state: NonceState::Unused {
+            secret: EncryptedSecret::seal(&signing_nonces_bytes[..], unlock.new_password()?)?,
+        },
+    };
+
+    let record_json = serde_json::to_string_pretty(&record)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize signing nonce: {}", e)).with_source(e))?;
+
+    let record_bytes = record_json.into_bytes();
+
+    storage.update(Artifact::SigningNonce(participant), &mut |_| Ok(Some(record_bytes.clone())))
+}
+
+/// Takes the signing nonce of `participant` out of storage for one signature.
+///
+/// The nonce must have been created for `session` and its commitment must be part of
+/// `signing_commitments`. The stored nonce is replaced by a tombstone before the nonce is
+/// returned, reading and replacing it under one [`Storage::update`], so a nonce can never be
+/// handed out twice, even to concurrent signers or if signing fails afterwards.
+pub fn consume_nonce(
+    storage: &dyn Storage,
+    participant: Participant,
+    session: &str,
+    signing_commitments: &[SigningCommitments],
+    payload: &[u8],
+    unlock: &Unlock,
+) -> Result<SigningNonces, CliError> {
+    let artifact = Artifact::SigningNonce(participant);
+    let path = storage.location(artifact);
+
+    // Ask for the password before the nonce is locked, so a prompt never holds the lock
+    unlock.password()?;
+
+    let mut signing_nonces = None;
+
+    storage.update(artifact, &mut |record_bytes| {
+        let record_bytes = record_bytes
+            .ok_or_else(|| CliError::io(format!("No signing nonce found at {}, run round 1 of threshold signing first", path)))?;
+
+        let record: NonceRecord = serde_json::from_slice(record_bytes).map_err(|e| {
+            CliError::serialization(format!(
+                "Failed to deserialize signing nonce at {}, run round 1 of threshold signing again: {}",
+                path, e
+            ))
+            .with_source(e)
+        })?;
+
+        if record.session != session {
+            return Err(CliError::protocol(format!(
+                "The signing nonce in {} was created for session '{}', not for session '{}'",
+                path, record.session, session
+            )));
+        }
+
+        signing_nonces = Some(open_unused(
+            &record.state,
+            &record.commitment,
+            signing_commitments,
+            unlock,
+            &format!("the signing nonce in {}", path),
+            "run round 1 of threshold signing again",
+        )?);
+
+        let tombstone = NonceRecord {
+            session: record.session,
+            commitment: record.commitment,
+            state: consumed(signing_commitments, payload),
+        };
+
+        let tombstone_json = serde_json::to_string_pretty(&tombstone)
+            .map_err(|e| CliError::serialization(format!("Failed to serialize signing nonce tombstone: {}", e)).with_source(e))?;
+
+        Ok(Some(tombstone_json.into_bytes()))
+    })?;
+
+    Ok(signing_nonces.expect("the nonce is opened before its tombstone is written"))
+}
+
+/// A signing nonce generated ahead of signing, which any signing session can pick by its index
+#[derive(Serialize, Deserialize)]
+pub struct PrecommittedNonce {
+    pub index: u32,
+    /// Hex encoding of the public signing commitment matching the nonce
+    pub commitment: String,
+    pub state: NonceState,
+}
+
+/// The precommitted signing nonces of `participant`, used or not, or none if it never precommitted
+pub fn read_precommitted_nonces(storage: &dyn Storage, participant: Participant) -> Result<Vec<PrecommittedNonce>, CliError> {
+    let artifact = Artifact::PrecommittedNonces(participant);
+
+    decode_precommitted_nonces(storage.get(artifact)?.as_deref(), &storage.location(artifact))
+}
+
+fn decode_precommitted_nonces(content: Option<&[u8]>, path: &str) -> Result<Vec<PrecommittedNonce>, CliError> {
+    match content {
+        Some(content) => serde_json::from_slice(content).map_err(|e| {
+            CliError::serialization(format!("Failed to deserialize precommitted signing nonces at {}: {}", path, e)).with_source(e)
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn encode_precommitted_nonces(nonces: &[PrecommittedNonce]) -> Result<Vec<u8>, CliError> {
+    serde_json::to_vec_pretty(nonces)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize precommitted signing nonces: {}", e)).with_source(e))
+}
+
+/// Encrypts freshly generated signing nonces and adds them to the precommitted nonces of
+/// `participant`, at the indices following the ones it already has. Returns every precommitted
+/// nonce, including the new ones.
+///
+/// The nonces are added under one [`Storage::update`], so concurrent additions and consumptions
+/// are never lost.
+pub fn add_precommitted_nonces(
+    storage: &dyn Storage,
+    participant: Participant,
+    generated: &[(SigningNonces, SigningCommitments)],
+    unlock: &Unlock,
+) -> Result<Vec<PrecommittedNonce>, CliError> {
+    let artifact = Artifact::PrecommittedNonces(participant);
+    let path = storage.location(artifact);
+
+    // Encrypt before taking the lock, scrypt is slow and the password may have to be prompted for
+    let mut sealed = Vec::with_capacity(generated.len());
+
+    for (signing_nonces, signing_commitments) in generated {
+        let signing_nonces_bytes = Zeroizing::new(signing_nonces.to_bytes());
+
+        sealed.push((
+            hex::encode(signing_commitments.to_bytes()),
+            EncryptedSecret::seal(&signing_nonces_bytes[..], unlock.new_password()?)?,
+        ));
+    }
+
+    let mut nonces = Vec::new();
+
+    storage.update(artifact, &mut |content| {
+        nonces = decode_precommitted_nonces(content, &path)?;
+        let first_index = nonces.iter().map(|nonce| nonce.index + 1).max().unwrap_or(0);
+
+        for (index, (commitment, secret)) in (first_index..).zip(std::mem::take(&mut sealed)) {
+            nonces.push(PrecommittedNonce {
+                index,
+                commitment,
+                state: NonceState::Unused { secret },
+            });
+        }
+
+        encode_precommitted_nonces(&nonces).map(Some)
+    })?;
+
+    Ok(nonces)
+}
+
+/// Takes the precommitted signing nonce at `index` of `participant` out of storage for one
+/// signature.
+///
+/// Its commitment must be part of `signing_commitments`. As with [`consume_nonce`], the stored
+/// nonce is replaced by a tombstone under one [`Storage::update`] before it is returned.
+pub fn consume_precommitted_nonce(
+    storage: &dyn Storage,
+    participant: Participant,
+    index: u32,
+    signing_commitments: &[SigningCommitments],
+    payload: &[u8],
+    unlock: &Unlock,
+) -> Result<SigningNonces, CliError> {
+    let artifact = Artifact::PrecommittedNonces(participant);
+    let path = storage.location(artifact);
+
+    // Ask for the password before the nonces are locked, so a prompt never holds the lock
+    unlock.password()?;
+
+    let mut signing_nonces = None;
+
+    storage.update(artifact, &mut |content| {
+        let mut nonces = decode_precommitted_nonces(content, &path)?;
+
+        let nonce = nonces
+            .iter_mut()
+            .find(|nonce| nonce.index == index)
+            .ok_or_else(|| CliError::input(format!("No precommitted signing nonce with index {} found in {}", index, path)))?;
+
+        signing_nonces = Some(open_unused(
+            &nonce.state,
+            &nonce.commitment,
+            signing_commitments,
+            unlock,
+            &format!("the precommitted signing nonce {} in {}", index, path),
+            "pick another index",
+        )?);
+
+        nonce.state = consumed(signing_commitments, payload);
+
+        encode_precommitted_nonces(&nonces).map(Some)
+    })?;
+
+    Ok(signing_nonces.expect("the nonce is opened before its tombstone is written"))
+}
+
+/// Decrypts a nonce that was not used yet and whose commitment is part of `signing_commitments`.
+/// `nonce` names it and `retry` says what to do instead if it was used, for messages to the user.
+fn open_unused(
+    state: &NonceState,
+    commitment: &str,
+    signing_commitments: &[SigningCommitments],
+    unlock: &Unlock,
+    nonce: &str,
+    retry: &str,
+) -> Result<SigningNonces, CliError> {
+    let secret = match state {
+        NonceState::Unused { secret } => secret,
+        NonceState::Consumed { commitments_hash, payload_hash } => {
+            return Err(CliError::protocol(format!(
+                "Refusing to sign with {}, which was already used to sign payload {} with commitments {}: {}",
+                nonce, payload_hash, commitments_hash, retry
+            )));
+        }
+    };
+
+    let own_commitment_included = signing_commitments
+        .iter()
+        .any(|signing_commitment| hex::encode(signing_commitment.to_bytes()) == commitment);
+
+    if !own_commitment_included {
+        return Err(CliError::protocol(format!(
+            "The signing commitments do not include the commitment of {}",
+            nonce
+        )));
+    }
+
+    let signing_nonces_bytes = secret.open(unlock.password()?)?;
+
+    SigningNonces::from_bytes(&signing_nonces_bytes)
+        .map_err(|e| CliError::protocol(format!("Failed to parse signing nonces: {:?}", e)))
+}
+
+/// The tombstone of a nonce, recording what it signed
+fn consumed(signing_commitments: &[SigningCommitments], payload: &[u8]) -> NonceState {
+    NonceState::Consumed {
+        commitments_hash: hex::encode(commitments_hash(signing_commitments)),
+        payload_hash: hex::encode(blake2_256(payload)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use tempfile::TempDir;
+    use super::*;
+    use crate::{
+        files::FilePaths,
+        protocol::{sign_round1, SignRound1Input},
+        storage::{FileStorage, SqliteStorage},
+    };
+
+    const PARTICIPANT: Participant = Participant { index: 1, account: [1u8; 32] };
+    const SESSION: &str = "signing";
+
+    type Open<'a> = &'a (dyn Fn() -> Box<dyn Storage> + Sync);
+
+    fn unlock(dir: &TempDir) -> Unlock {
+        let password_file = dir.path().join("password");
+        std::fs::write(&password_file, "password").unwrap();
+
+        Unlock::new(Some(password_file.to_string_lossy().into_owned()))
+    }
+
+    fn generate_nonce() -> (SigningNonces, SigningCommitments) {
+        let signing_share = schnorrkel::Keypair::generate_with(rand::rngs::OsRng).to_bytes();
+        let output = sign_round1(SignRound1Input { signing_share: &signing_share }).unwrap();
+
+        (output.signing_nonces, output.signing_commitments)
+    }
+
+    /// Consumes the same signing nonce from two threads at once, each with its own handle on the
+    /// storage, as two processes would
+    fn consume_concurrently(open: Open, unlock: &Unlock) {
+        let (signing_nonces, signing_commitments) = generate_nonce();
+        write_nonce(&*open(), PARTICIPANT, SESSION, &signing_nonces, &signing_commitments, unlock).unwrap();
+
+        let barrier = Barrier::new(2);
+
+        let results: Vec<Result<SigningNonces, CliError>> = std::thread::scope(|scope| {
+            let signers: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let storage = open();
+                        barrier.wait();
+                        consume_nonce(&*storage, PARTICIPANT, SESSION, std::slice::from_ref(&signing_commitments), b"payload", unlock)
+                    })
+                })
+                .collect();
+
+            signers.into_iter().map(|signer| signer.join().unwrap()).collect()
+        });
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results.iter().any(|result| matches!(result, Err(CliError::Protocol { .. }))));
+    }
+
+    #[test]
+    fn only_one_of_two_concurrent_signers_gets_the_nonce() {
+        let dir = TempDir::new().unwrap();
+        let unlock = unlock(&dir);
+        let file_paths = FilePaths::new(dir.path());
+        let database = dir.path().join("artifacts.db");
+
+        for _ in 0..8 {
+            consume_concurrently(&|| Box::new(FileStorage::new(file_paths.clone())) as Box<dyn Storage>, &unlock);
+            consume_concurrently(&|| Box::new(SqliteStorage::open(&database, file_paths.clone()).unwrap()) as Box<dyn Storage>, &unlock);
+        }
+    }
+
+    #[test]
+    fn a_consumed_nonce_is_refused() {
+        let dir = TempDir::new().unwrap();
+        let unlock = unlock(&dir);
+        let storage = FileStorage::new(FilePaths::new(dir.path()));
+
+        let (signing_nonces, signing_commitments) = generate_nonce();
+        write_nonce(&storage, PARTICIPANT, SESSION, &signing_nonces, &signing_commitments, &unlock).unwrap();
+
+        let commitments = std::slice::from_ref(&signing_commitments);
+        assert!(matches!(consume_nonce(&storage, PARTICIPANT, "other", commitments, b"payload", &unlock), Err(CliError::Protocol { .. })));
+        assert!(consume_nonce(&storage, PARTICIPANT, SESSION, commitments, b"payload", &unlock).is_ok());
+        assert!(matches!(consume_nonce(&storage, PARTICIPANT, SESSION, commitments, b"payload", &unlock), Err(CliError::Protocol { .. })));
+    }
+
+    /// Consumes two precommitted nonces while more are added, each from its own thread and with
+    /// its own handle on the storage
+    fn precommit_concurrently(open: Open, unlock: &Unlock) {
+        let storage = open();
+        let precommitted = [generate_nonce(), generate_nonce()];
+        let more = [generate_nonce()];
+
+        add_precommitted_nonces(&*storage, PARTICIPANT, &precommitted, unlock).unwrap();
+
+        let barrier = Barrier::new(3);
+
+        std::thread::scope(|scope| {
+            for (index, (_, signing_commitments)) in precommitted.iter().enumerate() {
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    let storage = open();
+                    barrier.wait();
+                    consume_precommitted_nonce(&*storage, PARTICIPANT, index as u32, std::slice::from_ref(signing_commitments), b"payload", unlock)
+                        .unwrap();
+                });
+            }
+
+            scope.spawn(|| {
+                let storage = open();
+                barrier.wait();
+                add_precommitted_nonces(&*storage, PARTICIPANT, &more, unlock).unwrap();
+            });
+        });
+
+        let nonces = read_precommitted_nonces(&*storage, PARTICIPANT).unwrap();
+        let states: Vec<(u32, bool)> = nonces
+            .iter()
+            .map(|nonce| (nonce.index, matches!(nonce.state, NonceState::Consumed { .. })))
+            .collect();
+
+        assert_eq!(states, [(0, true), (1, true), (2, false)]);
+
+        storage.delete(Artifact::PrecommittedNonces(PARTICIPANT)).unwrap();
+    }
+
+    #[test]
+    fn concurrent_precommitted_nonce_updates_are_never_lost() {
+        let dir = TempDir::new().unwrap();
+        let unlock = unlock(&dir);
+        let file_paths = FilePaths::new(dir.path());
+        let database = dir.path().join("artifacts.db");
+
+        for _ in 0..4 {
+            precommit_concurrently(&|| Box::new(FileStorage::new(file_paths.clone())) as Box<dyn Storage>, &unlock);
+            precommit_concurrently(&|| Box::new(SqliteStorage::open(&database, file_paths.clone()).unwrap()) as Box<dyn Storage>, &unlock);
+        }
+    }
+
+    #[test]
+    fn a_replaced_nonce_is_wiped() {
+        let dir = TempDir::new().unwrap();
+        let unlock = unlock(&dir);
+        let storage = FileStorage::new(FilePaths::new(dir.path()));
+
+        let (signing_nonces, signing_commitments) = generate_nonce();
+        write_nonce(&storage, PARTICIPANT, SESSION, &signing_nonces, &signing_commitments, &unlock).unwrap();
+
+        // Another name for the file of the first nonce, which sees what happens to its content
+        let earlier = dir.path().join("earlier_nonce");
+        std::fs::hard_link(storage.path(Artifact::SigningNonce(PARTICIPANT)), &earlier).unwrap();
+        assert!(std::fs::read(&earlier).unwrap().iter().any(|byte| *byte != 0));
+
+        let (other_nonces, other_commitments) = generate_nonce();
+        write_nonce(&storage, PARTICIPANT, SESSION, &other_nonces, &other_commitments, &unlock).unwrap();
+
+        assert!(std::fs::read(&earlier).unwrap().iter().all(|byte| *byte == 0));
+
+        // Only the new nonce can sign
+        assert!(consume_nonce(&storage, PARTICIPANT, SESSION, &[signing_commitments], b"payload", &unlock).is_err());
+        consume_nonce(&storage, PARTICIPANT, SESSION, &[other_commitments], b"payload", &unlock).unwrap();
+    }
+}
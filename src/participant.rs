@@ -0,0 +1,122 @@
+use std::{fmt, str::FromStr};
+use sp_core::crypto::{AccountId32, Ss58Codec};
+use crate::error::CliError;
+
+/// A participant as named on the command line: by its SS58 account, or by its position among the
+/// recipients, starting from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParticipantArg {
+    Index(u16),
+    Account(String),
+}
+
+impl FromStr for ParticipantArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u16>() {
+            Ok(0) => Err("participants are numbered from 1".to_string()),
+            Ok(index) => Ok(ParticipantArg::Index(index)),
+            Err(_) => AccountId32::from_ss58check(s)
+                .map(|_| ParticipantArg::Account(s.to_string()))
+                .map_err(|e| format!("expected a position among the recipients or an SS58 account: {:?}", e)),
+        }
+    }
+}
+
+impl fmt::Display for ParticipantArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParticipantArg::Index(index) => write!(f, "{}", index),
+            ParticipantArg::Account(account) => write!(f, "{}", account),
+        }
+    }
+}
+
+/// A participant of a ceremony, identified by its account, at its position among the recipients
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Participant {
+    /// The position among the recipients, starting from 1
+    pub index: u16,
+    /// The public key of the account
+    pub account: [u8; 32],
+}
+
+impl Participant {
+    /// Finds the participant named by `arg` among the SS58 accounts of the recipients
+    pub fn resolve(arg: &ParticipantArg, recipients: &[String]) -> Result<Self, CliError> {
+        let accounts = recipients
+            .iter()
+            .map(|recipient| {
+                AccountId32::from_ss58check(recipient)
+                    .map(|account| <[u8; 32]>::from(account))
+                    .map_err(|e| CliError::key_parsing(format!("Invalid SS58 recipient {}: {:?}", recipient, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let position = match arg {
+            ParticipantArg::Index(index) => (*index as usize)
+                .checked_sub(1)
+                .filter(|position| *position < accounts.len())
+                .ok_or_else(|| {
+                    CliError::input(format!(
+                        "There is no participant {}: positions start from 1 and there are {} recipients",
+                        index,
+                        accounts.len()
+                    ))
+                })?,
+            ParticipantArg::Account(account) => {
+                let public_key: [u8; 32] = AccountId32::from_ss58check(account)
+                    .map_err(|e| CliError::key_parsing(format!("Invalid SS58 account {}: {:?}", account, e)))?
+                    .into();
+
+                accounts
+                    .iter()
+                    .position(|recipient| *recipient == public_key)
+                    .ok_or_else(|| CliError::input(format!("The account {} is not among the recipients", account)))?
+            }
+        };
+
+        Ok(Self {
+            index: position as u16 + 1,
+            account: accounts[position],
+        })
+    }
+
+    /// The SS58 encoding of the account, with the generic Substrate prefix
+    pub fn ss58(&self) -> String {
+        AccountId32::from(self.account).to_ss58check()
+    }
+}
+
+impl fmt::Display for Participant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "participant {} ({})", self.index, self.ss58())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipients() -> Vec<String> {
+        (1..=3).map(|index| AccountId32::from([index; 32]).to_ss58check()).collect()
+    }
+
+    #[test]
+    fn a_participant_is_resolved_by_position_or_account() {
+        let by_position = Participant::resolve(&ParticipantArg::Index(2), &recipients()).unwrap();
+        let by_account = Participant::resolve(&ParticipantArg::Account(recipients()[1].clone()), &recipients()).unwrap();
+
+        assert_eq!(by_position, by_account);
+        assert_eq!(by_position, Participant { index: 2, account: [2; 32] });
+    }
+
+    #[test]
+    fn a_position_outside_the_recipients_is_refused() {
+        for index in [0, 4, u16::MAX] {
+            let refused = Participant::resolve(&ParticipantArg::Index(index), &recipients());
+            assert!(matches!(refused, Err(CliError::Input { .. })), "position {}", index);
+        }
+    }
+}
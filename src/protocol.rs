@@ -0,0 +1,2281 @@
+//! The rounds of threshold public key generation and threshold signing, free of any IO.
+//!
+//! Inputs and outputs are plain values: reading secrets, storing outputs and exchanging messages
+//! between participants is left to the caller, such as the commands of the CLI.
+
+use std::fmt;
+use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, ristretto::RistrettoPoint, scalar::Scalar, traits::Identity};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use schnorrkel::{
+    olaf::{
+        multisig::{aggregate as aggregate_packages, MultiSigError, SigningCommitments, SigningNonces, SigningPackage},
+        simplpedpop::{AllMessage, SPPOutputMessage},
+        SigningKeypair,
+    },
+    ExpansionMode, Keypair, MiniSecretKey, PublicKey, Signature, KEYPAIR_LENGTH,
+};
+use sp_core::{crypto::Ss58Codec, hashing::blake2_256};
+use subxt::utils::AccountId32;
+use zeroize::Zeroizing;
+use crate::{
+    dealing::{decompress, secret_scalar, Dealing, DealingRecipient},
+    error::CliError,
+    extrinsic::payload_hash,
+    inspect::{Reader, SPPOutputMessageView, SPPOutputView, SigningPackageView},
+};
+
+/// How a contributor mini secret key is expanded into a keypair. It must match how the account of
+/// the participant was created: `subkey` and polkadot-js use ed25519 expansion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyExpansion {
+    #[default]
+    Ed25519,
+    Uniform,
+}
+
+impl KeyExpansion {
+    fn mode(self) -> ExpansionMode {
+        match self {
+            KeyExpansion::Ed25519 => ExpansionMode::Ed25519,
+            KeyExpansion::Uniform => ExpansionMode::Uniform,
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            KeyExpansion::Ed25519 => KeyExpansion::Uniform,
+            KeyExpansion::Uniform => KeyExpansion::Ed25519,
+        }
+    }
+}
+
+impl fmt::Display for KeyExpansion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyExpansion::Ed25519 => write!(f, "ed25519"),
+            KeyExpansion::Uniform => write!(f, "uniform"),
+        }
+    }
+}
+
+/// Input of round 1 of threshold public key generation
+pub struct DkgRound1Input<'a> {
+    /// The contributor secret key of the participant
+    pub secret_key: &'a [u8],
+    pub expansion: KeyExpansion,
+    pub threshold: u16,
+    /// The SS58 accounts of all participants, including this one
+    pub recipients: &'a [String],
+}
+
+/// Output of round 1 of threshold public key generation
+pub struct DkgRound1Output {
+    /// The account of the participant
+    pub account_id: AccountId32,
+    /// The encoded [`AllMessageBundle`] to send to all participants
+    pub all_message: Vec<u8>,
+}
+
+/// Input of round 2 of threshold public key generation
+pub struct DkgRound2Input<'a> {
+    /// The contributor secret key of the participant
+    pub secret_key: &'a [u8],
+    pub expansion: KeyExpansion,
+    /// The SS58 accounts of all participants, to check the keypair of the participant and the round 1
+    /// messages against
+    pub recipients: &'a [String],
+    /// The encoded [`AllMessageBundle`]s of all participants, including this one
+    pub all_messages: &'a [Vec<u8>],
+    /// Whether to accept `AllMessage`s sent on their own by older versions, which are not bound to
+    /// the recipients they were created for
+    pub allow_legacy: bool,
+}
+
+/// Output of round 2 of threshold public key generation
+pub struct DkgRound2Output {
+    /// The encoded `SPPOutputMessage`, public
+    pub generation_output: Vec<u8>,
+    /// The encoded [`GenerationOutputBundle`] to send to all participants, for the DKG certificate
+    pub generation_output_bundle: Vec<u8>,
+    /// The encoded signing share, secret
+    pub signing_share: Zeroizing<Vec<u8>>,
+    pub threshold_public_key: AccountId32,
+}
+
+/// Input of round 1 of threshold signing
+pub struct SignRound1Input<'a> {
+    /// The encoded signing share of the participant
+    pub signing_share: &'a [u8],
+}
+
+/// Output of round 1 of threshold signing
+pub struct SignRound1Output {
+    /// The secret signing nonces, to be used for exactly one signature
+    pub signing_nonces: SigningNonces,
+    /// The public signing commitments to send to all participants
+    pub signing_commitments: SigningCommitments,
+    /// The public key of the signing share, which identifies the participant's messages
+    pub verifying_share: PublicKey,
+}
+
+/// Input of round 2 of threshold signing
+pub struct SignRound2Input<'a> {
+    /// The encoded signing share of the participant
+    pub signing_share: &'a [u8],
+    /// The encoded `SPPOutputMessage` of the participant
+    pub generation_output: &'a [u8],
+    /// The encoded signing commitments of all signers, including this one
+    pub signing_commitments: &'a [Vec<u8>],
+    pub signing_nonces: &'a SigningNonces,
+    pub context: &'a [u8],
+    pub payload: Vec<u8>,
+}
+
+/// Output of round 2 of threshold signing
+pub struct SignRound2Output {
+    /// The encoded signing package to send to the aggregator
+    pub signing_package: Vec<u8>,
+    /// The public key of the signing share, which identifies the participant's messages
+    pub verifying_share: PublicKey,
+}
+
+/// Input of the aggregation of a threshold signature
+pub struct AggregateInput<'a> {
+    /// The encoded signing packages of all signers
+    pub signing_packages: &'a [Vec<u8>],
+    /// The hex encoded payload hash and the context every package must have signed. Without it,
+    /// the ones signed by most packages are expected.
+    pub expected: Option<(&'a str, &'a str)>,
+    /// The SS58 accounts of all participants, used to name misbehaving signers
+    pub recipients: &'a [String],
+}
+
+/// Output of the aggregation of a threshold signature
+pub struct AggregateOutput {
+    pub signature: Signature,
+}
+
+/// Input of the check of individual signing packages
+pub struct CheckSigningPackagesInput<'a> {
+    /// The encoded signing packages, each with the verifying share of its signer if it is known
+    pub signing_packages: &'a [(Option<[u8; 32]>, Vec<u8>)],
+    /// The hex encoded payload hash and the context every package must have signed. Without it,
+    /// the ones signed by most packages are expected.
+    pub expected: Option<(&'a str, &'a str)>,
+}
+
+/// Output of the check of individual signing packages
+pub struct CheckSigningPackagesOutput {
+    /// What is wrong with each signing package, in order: nothing for a valid package
+    pub problems: Vec<Vec<String>>,
+    /// Whether the signature shares were checked, which needs the signing packages of all signers
+    pub shares_checked: bool,
+}
+
+/// Input of round 1 of the refresh of the signing shares
+pub struct RefreshRound1Input<'a> {
+    /// The contributor secret key of the participant
+    pub secret_key: &'a [u8],
+    pub expansion: KeyExpansion,
+    /// The encoded `SPPOutputMessage` of the participant, whose signing shares are refreshed
+    pub generation_output: &'a [u8],
+    /// The SS58 accounts of all participants, including this one
+    pub recipients: &'a [String],
+}
+
+/// Output of round 1 of the refresh of the signing shares
+pub struct RefreshRound1Output {
+    /// The account of the participant
+    pub account_id: AccountId32,
+    /// The encoded [`Dealing`] of zero to send to all participants
+    pub dealing: Vec<u8>,
+}
+
+/// Input of round 2 of the refresh of the signing shares
+pub struct RefreshRound2Input<'a> {
+    /// The contributor secret key of the participant
+    pub secret_key: &'a [u8],
+    pub expansion: KeyExpansion,
+    /// The encoded signing share of the participant, to refresh
+    pub signing_share: &'a [u8],
+    /// The encoded `SPPOutputMessage` of the participant
+    pub generation_output: &'a [u8],
+    /// The SS58 accounts of all participants, including this one
+    pub recipients: &'a [String],
+    /// The encoded [`Dealing`]s of all participants, including this one
+    pub dealings: &'a [Vec<u8>],
+}
+
+/// Output of round 2 of the refresh of the signing shares
+pub struct RefreshRound2Output {
+    /// The encoded `SPPOutputMessage` with the refreshed verifying shares, public
+    pub generation_output: Vec<u8>,
+    /// The encoded [`GenerationOutputBundle`] of the refreshed output, for a new DKG certificate
+    pub generation_output_bundle: Vec<u8>,
+    /// The refreshed signing share, secret
+    pub signing_share: Zeroizing<Vec<u8>>,
+    /// The threshold public key, the same as before the refresh
+    pub threshold_public_key: AccountId32,
+}
+
+/// Input of round 1 of resharing, run by the current holders of signing shares
+pub struct ReshareRound1Input<'a> {
+    /// The contributor secret key of the participant
+    pub secret_key: &'a [u8],
+    pub expansion: KeyExpansion,
+    /// The encoded signing share of the participant, to reshare
+    pub signing_share: &'a [u8],
+    /// The encoded `SPPOutputMessage` of the participant
+    pub generation_output: &'a [u8],
+    /// The SS58 accounts of the current participants, including this one
+    pub recipients: &'a [String],
+    /// The SS58 accounts of the participants to reshare to
+    pub new_recipients: &'a [String],
+    pub new_threshold: u16,
+    /// The positions among the current participants of the holders that deal, starting from 1, or
+    /// `None` for the first ones, as many as the current threshold
+    pub dealers: Option<&'a [u16]>,
+}
+
+/// Output of round 1 of resharing
+pub struct ReshareRound1Output {
+    /// The account of the participant
+    pub account_id: AccountId32,
+    /// The encoded [`ReshareMessage`] to send to all new participants
+    pub reshare_message: Vec<u8>,
+}
+
+/// Input of round 2 of resharing, run by the new participants
+pub struct ReshareRound2Input<'a> {
+    /// The contributor secret key of the new participant
+    pub secret_key: &'a [u8],
+    pub expansion: KeyExpansion,
+    /// The SS58 accounts of the new participants, including this one
+    pub new_recipients: &'a [String],
+    pub new_threshold: u16,
+    /// The encoded [`ReshareMessage`]s of the current holders that reshared
+    pub reshare_messages: &'a [Vec<u8>],
+    /// The threshold public key whose signing shares are reshared. Messages for any other key, or
+    /// for other signing shares of it, are refused.
+    pub threshold_public_key: &'a AccountId32,
+    /// The positions among the current participants of the holders that deal, starting from 1, or
+    /// `None` for the first ones, as many as the current threshold. Must be the same as in round 1.
+    pub dealers: Option<&'a [u16]>,
+}
+
+/// Output of round 2 of resharing
+pub struct ReshareRound2Output {
+    /// The encoded `SPPOutputMessage` of the new participant, public
+    pub generation_output: Vec<u8>,
+    /// The encoded [`GenerationOutputBundle`] of the new output, for the DKG certificate
+    pub generation_output_bundle: Vec<u8>,
+    /// The new signing share, secret
+    pub signing_share: Zeroizing<Vec<u8>>,
+    /// The threshold public key, the same as before resharing
+    pub threshold_public_key: AccountId32,
+    /// The positions among the current participants of the holders whose messages were used, starting from 1
+    pub dealers: Vec<u16>,
+}
+
+/// Turns a contributor secret key into the keypair of a participant: a 32 byte mini secret key is
+/// expanded with `expansion`, and a 96 byte encoded keypair, as derived from a secret URI or a
+/// polkadot-js export, is taken as is
+pub fn contributor_keypair(secret_key: &[u8], expansion: KeyExpansion) -> Result<Keypair, CliError> {
+    if secret_key.len() == KEYPAIR_LENGTH {
+        return Keypair::from_bytes(secret_key)
+            .map_err(|e| CliError::key_parsing(format!("Failed to parse keypair from secret key bytes: {}", e)).with_source(e));
+    }
+
+    Ok(MiniSecretKey::from_bytes(secret_key)
+        .map_err(|e| CliError::key_parsing(format!("Failed to generate keypair from secret key bytes: {}", e)).with_source(e))?
+        .expand_to_keypair(expansion.mode()))
+}
+
+/// Checks that the account of the participant is among the recipients, which fails if the secret
+/// key was expanded differently from how the account was created
+pub fn check_recipient(secret_key: &[u8], expansion: KeyExpansion, recipients: &[String]) -> Result<(), CliError> {
+    let recipients = parse_recipients(recipients)?;
+    let public_key = contributor_keypair(secret_key, expansion)?.public;
+
+    if recipients.contains(&public_key) {
+        return Ok(());
+    }
+
+    let account_id = AccountId32(public_key.to_bytes());
+
+    // Only mini secret keys are expanded, so only they can be off by the expansion mode
+    if secret_key.len() != KEYPAIR_LENGTH {
+        let other_public_key = contributor_keypair(secret_key, expansion.other())?.public;
+        let other_account_id = AccountId32(other_public_key.to_bytes());
+
+        if recipients.contains(&other_public_key) {
+            return Err(CliError::input(format!(
+                "The contributor secret key expands to account {} with {} expansion, which is not among the recipients, \
+                 but to recipient {} with {} expansion: pass --expansion-mode {}",
+                account_id, expansion, other_account_id, expansion.other(), expansion.other(),
+            )));
+        }
+
+        return Err(CliError::input(format!(
+            "The contributor secret key expands to account {} with {} expansion and to account {} with {} expansion, \
+             neither of which is among the recipients",
+            account_id, expansion, other_account_id, expansion.other(),
+        )));
+    }
+
+    Err(CliError::input(format!(
+        "The account {} of the contributor secret key is not among the recipients",
+        account_id,
+    )))
+}
+
+/// Parses the SS58 accounts of the recipients into their public keys
+pub fn parse_recipients(recipients: &[String]) -> Result<Vec<PublicKey>, CliError> {
+    recipients
+        .iter()
+        .map(|recipient| {
+            let account_id = sp_core::crypto::AccountId32::from_ss58check(recipient)
+                .map_err(|e| CliError::key_parsing(format!("Invalid SS58 recipient {}: {}", recipient, e)))?;
+            let public_key_bytes = account_id.as_ref();
+            PublicKey::from_bytes(public_key_bytes)
+                .map_err(|e| CliError::key_parsing(format!("Failed to parse public key for recipient {}: {}", recipient, e)).with_source(e))
+        })
+        .collect()
+}
+
+/// Checks that the recipients are distinct and that `threshold` of them can sign, returning their
+/// public keys
+pub fn validate_recipients(recipients: &[String], threshold: u16) -> Result<Vec<PublicKey>, CliError> {
+    let public_keys = parse_recipients(recipients)?;
+
+    for (index, public_key) in public_keys.iter().enumerate() {
+        if let Some(duplicate) = public_keys[index + 1..].iter().position(|other| other == public_key) {
+            return Err(CliError::input(format!(
+                "Recipients {} and {} are the same account {}: every participant must appear exactly once",
+                index + 1,
+                index + duplicate + 2,
+                AccountId32(public_key.to_bytes()),
+            )));
+        }
+    }
+
+    if threshold < 2 {
+        return Err(CliError::input(format!(
+            "The threshold is {}, but must be at least 2 for the key to be shared",
+            threshold,
+        )));
+    }
+
+    if threshold as usize > public_keys.len() {
+        return Err(CliError::input(format!(
+            "The threshold is {}, but there are only {} recipients: lower the threshold or add recipients",
+            threshold,
+            public_keys.len(),
+        )));
+    }
+
+    Ok(public_keys)
+}
+
+/// Hashes the public keys of the recipients, in order, so participants can check they all used the same ones
+pub fn recipients_hash(recipients: &[PublicKey]) -> [u8; 32] {
+    let bytes: Vec<u8> = recipients
+        .iter()
+        .flat_map(|recipient| recipient.to_bytes())
+        .collect();
+
+    blake2_256(&bytes)
+}
+
+const ALL_MESSAGE_BUNDLE_MAGIC: &[u8; 8] = b"olafdkg1";
+
+/// The `AllMessage` of a participant, with its account and the hash of the recipients it was
+/// created for. `AllMessage`s sent on their own, as by older versions, are decoded as bundles
+/// without an account and hash, which round 2 only accepts when explicitly allowed.
+pub struct AllMessageBundle {
+    pub sender: Option<[u8; 32]>,
+    pub recipients_hash: Option<[u8; 32]>,
+    pub all_message: Vec<u8>,
+}
+
+impl AllMessageBundle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (Some(sender), Some(recipients_hash)) = (self.sender, self.recipients_hash) else {
+            return self.all_message.clone();
+        };
+
+        let mut bytes = Vec::with_capacity(ALL_MESSAGE_BUNDLE_MAGIC.len() + 64 + self.all_message.len());
+        bytes.extend_from_slice(ALL_MESSAGE_BUNDLE_MAGIC);
+        bytes.extend_from_slice(&sender);
+        bytes.extend_from_slice(&recipients_hash);
+        bytes.extend_from_slice(&self.all_message);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match bytes.strip_prefix(ALL_MESSAGE_BUNDLE_MAGIC.as_slice()) {
+            Some(rest) if rest.len() >= 64 => Self {
+                sender: Some(rest[..32].try_into().expect("32 bytes")),
+                recipients_hash: Some(rest[32..64].try_into().expect("32 bytes")),
+                all_message: rest[64..].to_vec(),
+            },
+            _ => Self {
+                sender: None,
+                recipients_hash: None,
+                all_message: bytes.to_vec(),
+            },
+        }
+    }
+}
+
+/// The signing context of the statement a participant signs in round 2: that the DKG completed
+/// with the `SPPOutput` it signs
+const DKG_COMPLETE_CONTEXT: &[u8] = b"olaf-cli dkg complete";
+
+/// The `SPPOutputMessage` of a participant with its account, and its signature with the
+/// contributor keypair over the `SPPOutput`, which vouches for the outcome of the DKG
+pub struct GenerationOutputBundle {
+    pub sender: [u8; 32],
+    pub signature: [u8; 64],
+    pub generation_output: Vec<u8>,
+}
+
+impl GenerationOutputBundle {
+    /// Bundles a generation output with the signature of the contributor keypair over its `SPPOutput`
+    pub fn sign(keypair: &Keypair, generation_output: Vec<u8>, spp_output: &[u8]) -> Self {
+        Self {
+            sender: keypair.public.to_bytes(),
+            signature: keypair.sign_simple(DKG_COMPLETE_CONTEXT, spp_output).to_bytes(),
+            generation_output,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(96 + self.generation_output.len());
+        bytes.extend_from_slice(&self.sender);
+        bytes.extend_from_slice(&self.signature);
+        bytes.extend_from_slice(&self.generation_output);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CliError> {
+        if bytes.len() < 96 {
+            return Err(CliError::protocol(format!("Truncated generation output bundle of {} bytes", bytes.len())));
+        }
+
+        Ok(Self {
+            sender: bytes[..32].try_into().expect("32 bytes"),
+            signature: bytes[32..96].try_into().expect("64 bytes"),
+            generation_output: bytes[96..].to_vec(),
+        })
+    }
+}
+
+/// The signature of one participant in a [`DkgCertificate`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CertificateSignature {
+    /// The SS58 account of the participant
+    pub account: String,
+    /// Hex encoding of its signature over the `SPPOutput`
+    pub signature: String,
+}
+
+/// Proof that every recipient completed the DKG with the same outcome: the `SPPOutput` they
+/// agree on, holding the threshold, the threshold public key and the verifying shares, signed by
+/// the contributor keypair of each of them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DkgCertificate {
+    pub threshold_public_key: String,
+    pub threshold: u16,
+    /// The SS58 accounts of the recipients, in order
+    pub recipients: Vec<String>,
+    /// Hex encoding of the `SPPOutput`
+    pub spp_output: String,
+    pub signatures: Vec<CertificateSignature>,
+}
+
+impl DkgCertificate {
+    /// Checks that the certificate holds a valid signature over its `SPPOutput` by every
+    /// recipient, and that its threshold and threshold public key are the ones of the `SPPOutput`
+    pub fn verify(&self) -> Result<(), CliError> {
+        let spp_output = hex::decode(&self.spp_output)
+            .map_err(|e| CliError::serialization(format!("Failed to decode SPPOutput of DKG certificate: {}", e)).with_source(e))?;
+        let view = SPPOutputView::parse(&spp_output)?;
+
+        if view.threshold != self.threshold || AccountId32(view.threshold_public_key).to_string() != self.threshold_public_key {
+            return Err(CliError::protocol(
+                "The threshold or threshold public key of the DKG certificate is not the one of its SPPOutput".to_string(),
+            ));
+        }
+
+        for (recipient, public_key) in self.recipients.iter().zip(parse_recipients(&self.recipients)?) {
+            let signature = self.signatures
+                .iter()
+                .find(|signature| signature.account == *recipient)
+                .ok_or_else(|| CliError::protocol(format!("The DKG certificate has no signature of {}", recipient)))?;
+
+            let signature = hex::decode(&signature.signature)
+                .ok()
+                .and_then(|bytes| Signature::from_bytes(&bytes).ok())
+                .ok_or_else(|| CliError::serialization(format!("Invalid signature of {} in DKG certificate", recipient)))?;
+
+            public_key
+                .verify_simple(DKG_COMPLETE_CONTEXT, &spp_output, &signature)
+                .map_err(|_| CliError::protocol(format!("The signature of {} in the DKG certificate is invalid", recipient)))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds the certificate of a DKG from the [`GenerationOutputBundle`]s of its participants.
+///
+/// Every recipient must have sent its bundle, with a valid signature by its account, and every
+/// `SPPOutput` must be the same: the same threshold, threshold public key and verifying shares.
+/// Otherwise all problems found are reported together, naming the participants concerned.
+pub fn dkg_certificate(bundles: &[Vec<u8>], recipients: &[String]) -> Result<DkgCertificate, CliError> {
+    let public_keys = parse_recipients(recipients)?;
+
+    let mut problems = Vec::new();
+    let mut outputs: Vec<(usize, Vec<u8>, [u8; 64])> = Vec::new();
+
+    for (position, bytes) in bundles.iter().enumerate() {
+        let bundle = match GenerationOutputBundle::from_bytes(bytes) {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                problems.push(format!("generation output {}: {}", position + 1, e.message()));
+                continue;
+            }
+        };
+
+        let Some(index) = public_keys.iter().position(|public_key| public_key.to_bytes() == bundle.sender) else {
+            problems.push(format!("{} is not a recipient, but sent a generation output", AccountId32(bundle.sender)));
+            continue;
+        };
+        let name = format!("participant {} ({})", index + 1, recipients[index]);
+
+        let spp_output = match SPPOutputMessage::from_bytes(&bundle.generation_output) {
+            Ok(generation_output) => generation_output.spp_output().to_bytes(),
+            Err(e) => {
+                problems.push(format!("the generation output of {} cannot be parsed: {:?}", name, e));
+                continue;
+            }
+        };
+
+        let valid = Signature::from_bytes(&bundle.signature)
+            .is_ok_and(|signature| public_keys[index].verify_simple(DKG_COMPLETE_CONTEXT, &spp_output, &signature).is_ok());
+
+        if !valid {
+            problems.push(format!("the generation output of {} is not signed by its account", name));
+            continue;
+        }
+
+        outputs.push((index, spp_output, bundle.signature));
+    }
+
+    for (index, recipient) in recipients.iter().enumerate() {
+        if !outputs.iter().any(|(output_index, _, _)| *output_index == index) {
+            problems.push(format!("participant {} ({}) has not sent its generation output", index + 1, recipient));
+        }
+    }
+
+    let expected = outputs
+        .iter()
+        .max_by_key(|(_, spp_output, _)| outputs.iter().filter(|(_, other, _)| other == spp_output).count())
+        .map(|(_, spp_output, _)| spp_output.clone());
+
+    if let Some(expected) = &expected {
+        let expected_view = SPPOutputView::parse(expected)?;
+
+        for (index, spp_output, _) in &outputs {
+            if spp_output == expected {
+                continue;
+            }
+
+            let view = SPPOutputView::parse(spp_output)?;
+            let mut differences = Vec::new();
+            if view.threshold_public_key != expected_view.threshold_public_key {
+                differences.push(format!("threshold public key {}", AccountId32(view.threshold_public_key)));
+            }
+            if view.threshold != expected_view.threshold {
+                differences.push(format!("threshold {}", view.threshold));
+            }
+            if view.verifying_shares != expected_view.verifying_shares {
+                differences.push("different verifying shares".to_string());
+            }
+            if differences.is_empty() {
+                differences.push("a different SPPOutput".to_string());
+            }
+
+            problems.push(format!(
+                "participant {} ({}) derived {}",
+                index + 1,
+                recipients[*index],
+                differences.join(", "),
+            ));
+        }
+    }
+
+    let Some(spp_output) = expected.filter(|_| problems.is_empty()) else {
+        return Err(CliError::protocol(format!("The DKG is not complete: {}", problems.join("; "))));
+    };
+
+    let view = SPPOutputView::parse(&spp_output)?;
+
+    let mut signatures: Vec<(usize, [u8; 64])> = outputs.iter().map(|(index, _, signature)| (*index, *signature)).collect();
+    signatures.sort_by_key(|(index, _)| *index);
+
+    Ok(DkgCertificate {
+        threshold_public_key: AccountId32(view.threshold_public_key).to_string(),
+        threshold: view.threshold,
+        recipients: recipients.to_vec(),
+        spp_output: hex::encode(&spp_output),
+        signatures: signatures
+            .into_iter()
+            .map(|(index, signature)| CertificateSignature {
+                account: recipients[index].clone(),
+                signature: hex::encode(signature),
+            })
+            .collect(),
+    })
+}
+
+/// Generates the message of round 1 of a participant to send to all participants (including itself)
+pub fn dkg_round1(input: DkgRound1Input) -> Result<DkgRound1Output, CliError> {
+    let recipients = validate_recipients(input.recipients, input.threshold)?;
+    check_recipient(input.secret_key, input.expansion, input.recipients)?;
+
+    let keypair = contributor_keypair(input.secret_key, input.expansion)?;
+    let recipients_hash = recipients_hash(&recipients);
+
+    let all_message: AllMessage = keypair.simplpedpop_contribute_all(input.threshold, recipients)
+        .map_err(|e| CliError::protocol(format!("Failed to generate AllMessage: {:?}", e)))?;
+
+    let bundle = AllMessageBundle {
+        sender: Some(keypair.public.to_bytes()),
+        recipients_hash: Some(recipients_hash),
+        all_message: all_message.to_bytes(),
+    };
+
+    Ok(DkgRound1Output {
+        account_id: AccountId32(keypair.public.to_bytes()),
+        all_message: bundle.to_bytes(),
+    })
+}
+
+/// Checks that every bundle was created for `recipients`. Bundles of older versions, which carry no
+/// hash, are refused unless `allow_legacy` is set, and are then left unchecked.
+fn check_recipients_hashes(bundles: &[AllMessageBundle], recipients: &[String], allow_legacy: bool) -> Result<(), CliError> {
+    let unbound: Vec<String> = bundles
+        .iter()
+        .enumerate()
+        .filter(|(_, bundle)| bundle.recipients_hash.is_none())
+        .map(|(index, _)| (index + 1).to_string())
+        .collect();
+
+    if !unbound.is_empty() && !allow_legacy {
+        return Err(CliError::protocol(format!(
+            "The round 1 messages at positions {} are not bound to a list of recipients, as sent by older versions: \
+             all participants should run round 1 again, or pass --allow-legacy to accept them without the check",
+            unbound.join(", "),
+        )));
+    }
+
+    let expected = recipients_hash(&parse_recipients(recipients)?);
+
+    let mismatched: Vec<String> = bundles
+        .iter()
+        .enumerate()
+        .filter(|(_, bundle)| bundle.recipients_hash.is_some_and(|hash| hash != expected))
+        .map(|(index, bundle)| match bundle.sender {
+            Some(sender) => AccountId32(sender).to_string(),
+            None => format!("the sender of message {}", index + 1),
+        })
+        .collect();
+
+    if !mismatched.is_empty() {
+        return Err(CliError::protocol(format!(
+            "The round 1 messages of {} were created for a different list of recipients than the local recipients: \
+             all participants must run round 1 again with the same recipients",
+            mismatched.join(", "),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Generates the threshold public key and the signing share of a participant, from the messages
+/// of round 1 of all participants (including itself)
+pub fn dkg_round2(input: DkgRound2Input) -> Result<DkgRound2Output, CliError> {
+    check_recipient(input.secret_key, input.expansion, input.recipients)?;
+
+    let keypair = contributor_keypair(input.secret_key, input.expansion)?;
+
+    let bundles: Vec<AllMessageBundle> = input.all_messages
+        .iter()
+        .map(|bytes| AllMessageBundle::from_bytes(bytes))
+        .collect();
+
+    check_recipients_hashes(&bundles, input.recipients, input.allow_legacy)?;
+
+    let all_messages: Vec<AllMessage> = bundles
+        .iter()
+        .map(|bundle| AllMessage::from_bytes(&bundle.all_message)
+            .map_err(|e| CliError::protocol(format!("Failed to parse AllMessage: {:?}", e))))
+        .collect::<Result<_, _>>()?;
+
+    let (generation_output, signing_share) = keypair.simplpedpop_recipient_all(&all_messages)
+        .map_err(|e| CliError::protocol(format!("Failed to process AllMessages: {:?}", e)))?;
+
+    let bundle = GenerationOutputBundle::sign(&keypair, generation_output.to_bytes(), &generation_output.spp_output().to_bytes());
+
+    Ok(DkgRound2Output {
+        threshold_public_key: AccountId32(generation_output.spp_output().threshold_public_key().0.to_bytes()),
+        generation_output: generation_output.to_bytes(),
+        generation_output_bundle: bundle.to_bytes(),
+        signing_share: Zeroizing::new(signing_share.to_bytes()),
+    })
+}
+
+/// Generates the secret signing nonces and the public signing commitments of a participant
+pub fn sign_round1(input: SignRound1Input) -> Result<SignRound1Output, CliError> {
+    let signing_share = parse_signing_share(input.signing_share)?;
+
+    let (signing_nonces, signing_commitments) = signing_share.commit();
+
+    Ok(SignRound1Output {
+        signing_nonces,
+        signing_commitments,
+        verifying_share: verifying_share(input.signing_share)?,
+    })
+}
+
+const PRECOMMITMENT_BUNDLE_MAGIC: &[u8; 8] = b"olafpre1";
+
+/// The signing commitments a participant generated ahead of signing, by index, sent to all
+/// participants at once so that signing sessions using one of them need no round 1
+pub struct PrecommitmentBundle {
+    pub commitments: Vec<(u32, Vec<u8>)>,
+}
+
+impl PrecommitmentBundle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(PRECOMMITMENT_BUNDLE_MAGIC);
+        bytes.extend_from_slice(&(self.commitments.len() as u32).to_le_bytes());
+        for (index, commitment) in &self.commitments {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&(commitment.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(commitment);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CliError> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.take(PRECOMMITMENT_BUNDLE_MAGIC.len())? != PRECOMMITMENT_BUNDLE_MAGIC {
+            return Err(CliError::protocol("Not a bundle of precommitted signing commitments".to_string()));
+        }
+
+        let commitments = (0..reader.length()?)
+            .map(|_| {
+                let index = u32::from_le_bytes(reader.array()?);
+                let length = reader.length()?;
+                Ok((index, reader.take(length)?.to_vec()))
+            })
+            .collect::<Result<_, CliError>>()?;
+
+        if !reader.is_empty() {
+            return Err(CliError::protocol("Trailing bytes after precommitted signing commitments".to_string()));
+        }
+
+        Ok(Self { commitments })
+    }
+
+    /// The signing commitments precommitted at `index`, if any
+    pub fn commitment(&self, index: u32) -> Option<&[u8]> {
+        self.commitments
+            .iter()
+            .find(|(precommitted, _)| *precommitted == index)
+            .map(|(_, commitment)| commitment.as_slice())
+    }
+
+    /// Whether this bundle keeps every commitment of `earlier` at its index, so that signing
+    /// sessions already using one of them are unaffected when it replaces `earlier`
+    pub fn extends(&self, earlier: &Self) -> bool {
+        earlier
+            .commitments
+            .iter()
+            .all(|(index, commitment)| self.commitment(*index) == Some(commitment.as_slice()))
+    }
+}
+
+/// The signing commitments precommitted at `index`, from every bundle that has one there, in the
+/// order of the bundles
+pub fn precommitted_signing_commitments(bundles: &[Vec<u8>], index: u32) -> Result<Vec<Vec<u8>>, CliError> {
+    let mut signing_commitments = Vec::new();
+
+    for bundle in bundles {
+        if let Some(commitment) = PrecommitmentBundle::from_bytes(bundle)?.commitment(index) {
+            signing_commitments.push(commitment.to_vec());
+        }
+    }
+
+    if signing_commitments.is_empty() {
+        return Err(CliError::protocol(format!("No participant precommitted signing commitments at index {}", index)));
+    }
+
+    Ok(signing_commitments)
+}
+
+/// Generates the signing package of a participant over `payload`
+pub fn sign_round2(input: SignRound2Input) -> Result<SignRound2Output, CliError> {
+    let signing_share = parse_signing_share(input.signing_share)?;
+
+    let generation_output = SPPOutputMessage::from_bytes(input.generation_output)
+        .map_err(|e| CliError::protocol(format!("Failed to parse generation output: {:?}", e)))?;
+
+    let signing_commitments = parse_signing_commitments(input.signing_commitments)?;
+
+    let signing_package = signing_share.sign(
+        input.context.to_vec(),
+        input.payload,
+        generation_output.spp_output(),
+        signing_commitments,
+        input.signing_nonces,
+    )
+    .map_err(|e| CliError::protocol(format!("Failed to create signing package: {:?}", e)))?;
+
+    Ok(SignRound2Output {
+        signing_package: signing_package.to_bytes(),
+        verifying_share: verifying_share(input.signing_share)?,
+    })
+}
+
+/// Aggregates the signing packages of at least t signers into one threshold signature, after
+/// checking that they all signed the same payload
+pub fn aggregate(input: AggregateInput) -> Result<AggregateOutput, CliError> {
+    let signing_packages: Vec<SigningPackage> = input.signing_packages
+        .iter()
+        .map(|sp| SigningPackage::from_bytes(sp)
+            .map_err(|e| CliError::protocol(format!("Failed to parse SigningPackage: {:?}", e))))
+        .collect::<Result<_, _>>()?;
+
+    let views: Vec<SigningPackageView> = input.signing_packages
+        .iter()
+        .map(|bytes| SigningPackageView::parse(bytes))
+        .collect::<Result<_, _>>()?;
+
+    check_agreement(&views, input.expected)?;
+
+    match aggregate_packages(&signing_packages) {
+        Ok(signature) => Ok(AggregateOutput { signature }),
+        Err(MultiSigError::InvalidSignatureShare { culprit }) => Err(identify_culprits(&views, &culprit, input.recipients)?),
+        Err(e) => Err(CliError::protocol(format!("Failed to aggregate threshold signature: {:?}", e))),
+    }
+}
+
+/// Checks every signing package on its own, without aggregating them: that it can be parsed,
+/// that its signer is a participant of the key, that it signed the expected payload and context
+/// with the same signing commitments as the others and, once the packages of all signers of those
+/// commitments are present, that its signature share is valid
+pub fn check_signing_packages(input: CheckSigningPackagesInput) -> Result<CheckSigningPackagesOutput, CliError> {
+    let mut problems = vec![Vec::new(); input.signing_packages.len()];
+    let mut parsed = Vec::new();
+
+    for (index, (_, bytes)) in input.signing_packages.iter().enumerate() {
+        match (SigningPackage::from_bytes(bytes), SigningPackageView::parse(bytes)) {
+            (Ok(package), Ok(view)) => parsed.push((index, package, view)),
+            (Err(e), _) => problems[index].push(format!("cannot be parsed: {:?}", e)),
+            (_, Err(e)) => problems[index].push(format!("cannot be parsed: {}", e.message())),
+        }
+    }
+
+    let views: Vec<&SigningPackageView> = parsed.iter().map(|(_, _, view)| view).collect();
+    let differences = package_differences(&views, input.expected);
+
+    for ((index, _, _), differences) in parsed.iter().zip(differences) {
+        if !differences.is_empty() {
+            problems[*index].push(format!("signed {}", differences.join(", ")));
+        }
+    }
+
+    for (index, _, view) in &parsed {
+        let Some(signer) = input.signing_packages[*index].0 else {
+            continue;
+        };
+
+        match SPPOutputView::parse(&view.spp_output) {
+            Ok(spp_output) if spp_output.position(&signer).is_none() => {
+                problems[*index].push("its signer is not a participant of the threshold public key".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => problems[*index].push(format!("its SPPOutput cannot be parsed: {}", e.message())),
+        }
+    }
+
+    // Signature shares can only be checked by aggregating them, which needs the package of every
+    // signer whose commitments they were signed with
+    let complete = problems.iter().all(Vec::is_empty)
+        && views.first().is_some_and(|view| view.signing_commitments.len() == parsed.len());
+
+    if !complete {
+        return Ok(CheckSigningPackagesOutput { problems, shares_checked: false });
+    }
+
+    let signing_packages: Vec<SigningPackage> = parsed.into_iter().map(|(_, package, _)| package).collect();
+
+    match aggregate_packages(&signing_packages) {
+        Ok(_) => {}
+        Err(MultiSigError::InvalidSignatureShare { culprit }) => {
+            for verifying_share in culprit.iter().map(|culprit| culprit.0.to_bytes()) {
+                let index = input
+                    .signing_packages
+                    .iter()
+                    .position(|(signer, _)| *signer == Some(verifying_share))
+                    .ok_or_else(|| {
+                        CliError::protocol(format!(
+                            "Invalid signature share from an unknown signer with verifying share 0x{}",
+                            hex::encode(verifying_share)
+                        ))
+                    })?;
+
+                problems[index].push("its signature share is not valid for the verifying share of its signer".to_string());
+            }
+        }
+        Err(e) => return Err(CliError::protocol(format!("Failed to check the signature shares: {:?}", e))),
+    }
+
+    Ok(CheckSigningPackagesOutput { problems, shares_checked: true })
+}
+
+const REFRESH_BINDING_CONTEXT: &[u8] = b"olaf-cli refresh";
+
+/// The `SPPOutput` of a generation output, the recipients of a refresh of its signing shares with
+/// their identifiers, and what the dealings of the refresh are bound to
+fn refresh_context(generation_output: &[u8], recipients: &[String]) -> Result<(SPPOutputView, Vec<DealingRecipient>, [u8; 32]), CliError> {
+    let message = SPPOutputMessageView::parse(generation_output)?;
+    let spp_output = SPPOutputView::parse(message.spp_output)?;
+    let public_keys = parse_recipients(recipients)?;
+
+    if spp_output.verifying_shares.len() != public_keys.len() {
+        return Err(CliError::input(format!(
+            "The generation output has {} verifying shares, but there are {} recipients",
+            spp_output.verifying_shares.len(),
+            public_keys.len(),
+        )));
+    }
+
+    let dealing_recipients = public_keys
+        .into_iter()
+        .zip(&spp_output.verifying_shares)
+        .map(|(public_key, (identifier, _))| DealingRecipient {
+            public_key,
+            identifier: Scalar::from_bytes_mod_order(*identifier),
+        })
+        .collect();
+
+    let binding = blake2_256(&[REFRESH_BINDING_CONTEXT, message.spp_output].concat());
+
+    Ok((spp_output, dealing_recipients, binding))
+}
+
+/// Deals zero to all participants, to be added to their signing shares
+pub fn refresh_round1(input: RefreshRound1Input) -> Result<RefreshRound1Output, CliError> {
+    check_recipient(input.secret_key, input.expansion, input.recipients)?;
+
+    let keypair = contributor_keypair(input.secret_key, input.expansion)?;
+    let (spp_output, recipients, binding) = refresh_context(input.generation_output, input.recipients)?;
+
+    let dealing = Dealing::deal(&keypair, binding, Scalar::ZERO, spp_output.threshold, &recipients)?;
+
+    Ok(RefreshRound1Output {
+        account_id: AccountId32(keypair.public.to_bytes()),
+        dealing: dealing.to_bytes(),
+    })
+}
+
+/// Refreshes the signing share of a participant with the dealings of zero of all participants
+/// (including itself), and the verifying shares of all participants with their commitments.
+///
+/// Every participant must deal, for the same `SPPOutput`, so that all participants add the same
+/// polynomials to their shares. The shares still interpolate to the same secret, so the threshold
+/// public key stays the same, but shares from before and after the refresh no longer combine.
+pub fn refresh_round2(input: RefreshRound2Input) -> Result<RefreshRound2Output, CliError> {
+    check_recipient(input.secret_key, input.expansion, input.recipients)?;
+
+    let keypair = contributor_keypair(input.secret_key, input.expansion)?;
+    let (spp_output, recipients, binding) = refresh_context(input.generation_output, input.recipients)?;
+
+    let position = recipients
+        .iter()
+        .position(|recipient| recipient.public_key == keypair.public)
+        .expect("checked to be among the recipients");
+
+    if verifying_share(input.signing_share)?.to_bytes() != spp_output.verifying_shares[position].1 {
+        return Err(CliError::key_parsing(
+            "The signing share does not match the generation output: one of them was already refreshed".to_string(),
+        ));
+    }
+
+    let name = |index: usize| format!("participant {} ({})", index + 1, input.recipients[index]);
+
+    let mut problems = Vec::new();
+    let mut senders = Vec::new();
+    let mut dealings = Vec::new();
+
+    for bytes in input.dealings {
+        let dealing = match Dealing::from_bytes(bytes) {
+            Ok(dealing) => dealing,
+            Err(e) => {
+                problems.push(format!("a refresh message cannot be parsed: {}", e.message()));
+                continue;
+            }
+        };
+
+        let Some(index) = recipients.iter().position(|recipient| recipient.public_key.to_bytes() == dealing.sender) else {
+            problems.push(format!("{} is not a recipient, but sent a refresh message", AccountId32(dealing.sender)));
+            continue;
+        };
+        senders.push(index);
+
+        let problem = if dealing.verify_signature().is_err() {
+            Some("is not signed by its account")
+        } else if dealing.binding != binding {
+            Some("is for other signing shares, from before or after another refresh")
+        } else if dealing.commitments.len() != spp_output.threshold as usize || dealing.encrypted_shares.len() != recipients.len() {
+            Some("is for another threshold or number of participants")
+        } else if dealing.secret_commitment()? != RistrettoPoint::identity() {
+            Some("would change the threshold public key")
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => problems.push(format!("the refresh message of {} {}", name(index), problem)),
+            None => dealings.push((index, dealing)),
+        }
+    }
+
+    for index in 0..recipients.len() {
+        if !senders.contains(&index) {
+            problems.push(format!("{} has not sent a refresh message", name(index)));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(CliError::protocol(format!("Cannot refresh the signing share: {}", problems.join("; "))));
+    }
+
+    let mut secret = secret_scalar(input.signing_share)?;
+    for (index, dealing) in &dealings {
+        let share = dealing
+            .open(position, &keypair, &recipients[position].identifier)
+            .map_err(|e| e.context(format!("The refresh message of {} is invalid", name(*index))))?;
+
+        *secret += *share;
+    }
+
+    let verifying_shares = spp_output.verifying_shares
+        .iter()
+        .zip(&recipients)
+        .map(|((identifier, verifying_share), recipient)| {
+            let mut point = decompress(verifying_share)?;
+            for (_, dealing) in &dealings {
+                point += dealing.commitment_at(&recipient.identifier)?;
+            }
+
+            Ok((*identifier, point.compress().to_bytes()))
+        })
+        .collect::<Result<Vec<_>, CliError>>()?;
+
+    let public_key = (*secret * RISTRETTO_BASEPOINT_POINT).compress().to_bytes();
+    if public_key != verifying_shares[position].1 {
+        return Err(CliError::protocol("The refreshed signing share does not match its refreshed verifying share".to_string()));
+    }
+
+    let mut nonce = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(&mut nonce[..]);
+
+    let mut signing_share = Zeroizing::new(Vec::with_capacity(KEYPAIR_LENGTH));
+    signing_share.extend_from_slice(secret.as_bytes());
+    signing_share.extend_from_slice(&nonce[..]);
+    signing_share.extend_from_slice(&public_key);
+    parse_signing_share(&signing_share)?;
+
+    let refreshed = SPPOutputView {
+        verifying_shares,
+        ..spp_output
+    };
+    let spp_output = refreshed.to_bytes();
+
+    // The signature over the old `SPPOutput` does not cover the refreshed one, so sign it anew
+    let signature = keypair.sign_simple(REFRESH_BINDING_CONTEXT, &spp_output).to_bytes();
+    let generation_output = SPPOutputMessageView {
+        signer: &keypair.public.to_bytes(),
+        spp_output: &spp_output,
+        signature: &signature,
+    }
+    .to_bytes();
+
+    SPPOutputMessage::from_bytes(&generation_output)
+        .map_err(|e| CliError::protocol(format!("Failed to encode refreshed generation output: {:?}", e)))?;
+
+    let bundle = GenerationOutputBundle::sign(&keypair, generation_output.clone(), &spp_output);
+
+    Ok(RefreshRound2Output {
+        generation_output,
+        generation_output_bundle: bundle.to_bytes(),
+        signing_share,
+        threshold_public_key: AccountId32(refreshed.threshold_public_key),
+    })
+}
+
+const RESHARE_CONTEXT: &[u8] = b"olaf-cli reshare";
+
+/// The message of a current holder to the new participants of a resharing: the dealing of its
+/// signing share, signed with that signing share, with the `SPPOutput` the share belongs to and
+/// its position in it, so the new participants can check the dealing against its verifying share
+pub struct ReshareMessage {
+    pub spp_output: Vec<u8>,
+    /// The position of the verifying share of the holder in the `SPPOutput`, starting from 0
+    pub position: u16,
+    pub dealing: Dealing,
+}
+
+impl ReshareMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.spp_output.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.spp_output);
+        bytes.extend_from_slice(&self.position.to_le_bytes());
+        bytes.extend_from_slice(&self.dealing.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CliError> {
+        let mut reader = Reader::new(bytes);
+
+        let spp_output_length = reader.length()?;
+        let spp_output = reader.take(spp_output_length)?.to_vec();
+        let position = u16::from_le_bytes(reader.array()?);
+        let dealing = Dealing::from_bytes(reader.rest())?;
+
+        Ok(Self {
+            spp_output,
+            position,
+            dealing,
+        })
+    }
+}
+
+/// What the dealings of a resharing are bound to: the `SPPOutput` of the shares being reshared,
+/// the holders that deal, and the new participants and threshold
+fn reshare_binding(spp_output: &[u8], dealers: &[u16], new_recipients: &[PublicKey], new_threshold: u16) -> [u8; 32] {
+    let dealers: Vec<u8> = dealers.iter().flat_map(|dealer| dealer.to_le_bytes()).collect();
+
+    blake2_256(&[RESHARE_CONTEXT, spp_output, &dealers, &recipients_hash(new_recipients), &new_threshold.to_le_bytes()].concat())
+}
+
+/// The positions of the holders that deal, in order: `dealers`, or the first ones, as many as the
+/// threshold of `view`. Every new participant combines the dealings of exactly these holders, so
+/// they all end up with shares of the same polynomial.
+fn reshare_dealers(dealers: Option<&[u16]>, view: &SPPOutputView) -> Result<Vec<u16>, CliError> {
+    let Some(dealers) = dealers else {
+        return Ok((1..=view.threshold).collect());
+    };
+
+    let mut sorted = dealers.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    if sorted.len() != dealers.len() {
+        return Err(CliError::input("A holder is given more than once among the dealers".to_string()));
+    }
+
+    if let Some(dealer) = sorted.iter().find(|dealer| **dealer == 0 || **dealer > view.participants) {
+        return Err(CliError::input(format!("There is no holder {} among the {} current participants", dealer, view.participants)));
+    }
+
+    if sorted.len() < view.threshold as usize {
+        return Err(CliError::input(format!(
+            "{} holders are chosen to deal, but the current threshold is {}",
+            sorted.len(),
+            view.threshold,
+        )));
+    }
+
+    Ok(sorted)
+}
+
+/// The identifiers of the new participants of a resharing, derived from their accounts
+fn reshare_recipients(new_recipients: &[PublicKey]) -> Vec<DealingRecipient> {
+    let hash = recipients_hash(new_recipients);
+
+    new_recipients
+        .iter()
+        .enumerate()
+        .map(|(position, public_key)| DealingRecipient {
+            public_key: *public_key,
+            identifier: Scalar::from_bytes_mod_order(blake2_256(
+                &[RESHARE_CONTEXT, &hash, &(position as u16).to_le_bytes()].concat(),
+            )),
+        })
+        .collect()
+}
+
+/// The Lagrange coefficient at zero of `identifier` among `identifiers`
+fn lagrange_coefficient(identifier: &Scalar, identifiers: &[Scalar]) -> Scalar {
+    identifiers
+        .iter()
+        .filter(|other| *other != identifier)
+        .fold(Scalar::ONE, |coefficient, other| coefficient * other * (other - identifier).invert())
+}
+
+/// Deals the signing share of a current holder to the new participants, with the new threshold
+pub fn reshare_round1(input: ReshareRound1Input) -> Result<ReshareRound1Output, CliError> {
+    check_recipient(input.secret_key, input.expansion, input.recipients)?;
+
+    let keypair = contributor_keypair(input.secret_key, input.expansion)?;
+    let new_recipients = validate_recipients(input.new_recipients, input.new_threshold)?;
+
+    // Signed with the signing share, so the new participants know the dealing comes from its holder
+    let signing_keypair = Keypair::from_bytes(input.signing_share)
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse signing share: {}", e)).with_source(e))?;
+
+    let spp_output = SPPOutputMessageView::parse(input.generation_output)?.spp_output.to_vec();
+    let view = SPPOutputView::parse(&spp_output)?;
+    let position = view
+        .position(&verifying_share(input.signing_share)?.to_bytes())
+        .ok_or_else(|| CliError::key_parsing("The signing share does not match the generation output".to_string()))?;
+
+    let dealers = reshare_dealers(input.dealers, &view)?;
+    if !dealers.contains(&(position as u16 + 1)) {
+        return Err(CliError::input(format!(
+            "Holder {} is not among the holders that deal, {}",
+            position + 1,
+            dealers.iter().map(u16::to_string).collect::<Vec<_>>().join(", "),
+        )));
+    }
+
+    let dealing = Dealing::deal(
+        &signing_keypair,
+        reshare_binding(&spp_output, &dealers, &new_recipients, input.new_threshold),
+        *secret_scalar(input.signing_share)?,
+        input.new_threshold,
+        &reshare_recipients(&new_recipients),
+    )?;
+
+    let message = ReshareMessage {
+        spp_output,
+        position: position as u16,
+        dealing,
+    };
+
+    Ok(ReshareRound1Output {
+        account_id: AccountId32(keypair.public.to_bytes()),
+        reshare_message: message.to_bytes(),
+    })
+}
+
+/// Combines the dealings of the holders that deal, at least as many as the current threshold, into
+/// the signing share of a new participant, and the verifying shares of all new participants.
+///
+/// The dealings are weighted by the Lagrange coefficients of their holders, so the new shares
+/// interpolate to the same secret and the threshold public key stays the same. The holders that
+/// deal are fixed in advance and bound into every dealing, and round 2 needs the dealing of each
+/// of them, so every new participant combines the same dealings.
+pub fn reshare_round2(input: ReshareRound2Input) -> Result<ReshareRound2Output, CliError> {
+    check_recipient(input.secret_key, input.expansion, input.new_recipients)?;
+
+    let keypair = contributor_keypair(input.secret_key, input.expansion)?;
+    let new_public_keys = validate_recipients(input.new_recipients, input.new_threshold)?;
+    let recipients = reshare_recipients(&new_public_keys);
+
+    let position = new_public_keys
+        .iter()
+        .position(|public_key| *public_key == keypair.public)
+        .expect("checked to be among the recipients");
+
+    let messages: Vec<ReshareMessage> = input.reshare_messages
+        .iter()
+        .map(|bytes| ReshareMessage::from_bytes(bytes))
+        .collect::<Result<_, _>>()?;
+
+    if messages.is_empty() {
+        return Err(CliError::input("No current holder has sent a reshare message yet".to_string()));
+    }
+
+    // The `SPPOutput` being reshared is one of the expected threshold public key, never one the
+    // holders agree on among themselves, and every message must reshare that same one
+    let Some(spp_output) = messages
+        .iter()
+        .find(|message| SPPOutputView::parse(&message.spp_output).is_ok_and(|view| view.threshold_public_key == input.threshold_public_key.0))
+        .map(|message| message.spp_output.clone())
+    else {
+        return Err(CliError::protocol(format!(
+            "None of the {} reshare messages reshares the signing shares of the Threshold Public Key {}",
+            messages.len(),
+            input.threshold_public_key,
+        )));
+    };
+
+    let view = SPPOutputView::parse(&spp_output)?;
+    let dealers = reshare_dealers(input.dealers, &view)?;
+    let binding = reshare_binding(&spp_output, &dealers, &new_public_keys, input.new_threshold);
+
+    let mut problems = Vec::new();
+    let mut dealings: Vec<(usize, &Dealing)> = Vec::new();
+
+    for message in &messages {
+        let holder = message.position as usize;
+        let name = format!("the reshare message of holder {}", holder + 1);
+
+        let problem = if message.spp_output != spp_output {
+            Some(match SPPOutputView::parse(&message.spp_output) {
+                Ok(other) if other.threshold_public_key != view.threshold_public_key => {
+                    format!("is for the Threshold Public Key {}", AccountId32(other.threshold_public_key))
+                }
+                Ok(_) => "reshares other signing shares of the key than the other holders, from before or after a refresh".to_string(),
+                Err(e) => format!("cannot be parsed: {}", e.message()),
+            })
+        } else if holder >= view.verifying_shares.len() {
+            Some(format!("claims position {} among {} holders", holder + 1, view.verifying_shares.len()))
+        } else if !dealers.contains(&(holder as u16 + 1)) {
+            Some("is from a holder that is not among the holders that deal".to_string())
+        } else if message.dealing.sender != view.verifying_shares[holder].1 {
+            Some("is not sent by the holder of the signing share at that position".to_string())
+        } else if message.dealing.verify_signature().is_err() {
+            Some("is not signed by the signing share of its holder".to_string())
+        } else if message.dealing.binding != binding {
+            Some("is for other new participants, another new threshold or other holders that deal".to_string())
+        } else if message.dealing.commitments.len() != input.new_threshold as usize
+            || message.dealing.encrypted_shares.len() != recipients.len()
+        {
+            Some("is for another threshold or number of new participants".to_string())
+        } else if message.dealing.secret_commitment()? != decompress(&view.verifying_shares[holder].1)? {
+            Some("does not deal the signing share of its holder".to_string())
+        } else if dealings.iter().any(|(other, _)| *other == holder) {
+            Some("deals the same signing share as another message".to_string())
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => problems.push(format!("{} {}", name, problem)),
+            None => dealings.push((holder, &message.dealing)),
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(CliError::protocol(format!("Cannot combine the reshare messages: {}", problems.join("; "))));
+    }
+
+    let missing: Vec<String> = dealers
+        .iter()
+        .filter(|dealer| !dealings.iter().any(|(holder, _)| *holder == **dealer as usize - 1))
+        .map(u16::to_string)
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(CliError::input(format!(
+            "Holders {} have not reshared their signing shares yet, and every holder that deals is needed",
+            missing.join(", "),
+        )));
+    }
+
+    dealings.sort_by_key(|(position, _)| *position);
+
+    let identifiers: Vec<Scalar> = dealings
+        .iter()
+        .map(|(position, _)| Scalar::from_bytes_mod_order(view.verifying_shares[*position].0))
+        .collect();
+    let coefficients: Vec<Scalar> = identifiers
+        .iter()
+        .map(|identifier| lagrange_coefficient(identifier, &identifiers))
+        .collect();
+
+    let threshold_public_key = dealings
+        .iter()
+        .zip(&coefficients)
+        .map(|((_, dealing), coefficient)| Ok(dealing.secret_commitment()? * coefficient))
+        .sum::<Result<RistrettoPoint, CliError>>()?;
+
+    if threshold_public_key.compress().to_bytes() != view.threshold_public_key {
+        return Err(CliError::protocol("The reshared signing shares do not combine into the threshold public key".to_string()));
+    }
+
+    let mut secret = Zeroizing::new(Scalar::ZERO);
+    for ((holder, dealing), coefficient) in dealings.iter().zip(&coefficients) {
+        let share = dealing
+            .open(position, &keypair, &recipients[position].identifier)
+            .map_err(|e| e.context(format!("The reshare message of holder {} is invalid", holder + 1)))?;
+
+        *secret += *share * coefficient;
+    }
+
+    let verifying_shares = recipients
+        .iter()
+        .map(|recipient| {
+            let verifying_share = dealings
+                .iter()
+                .zip(&coefficients)
+                .map(|((_, dealing), coefficient)| Ok(dealing.commitment_at(&recipient.identifier)? * coefficient))
+                .sum::<Result<RistrettoPoint, CliError>>()?;
+
+            Ok((recipient.identifier.to_bytes(), verifying_share.compress().to_bytes()))
+        })
+        .collect::<Result<Vec<_>, CliError>>()?;
+
+    let public_key = (*secret * RISTRETTO_BASEPOINT_POINT).compress().to_bytes();
+    if public_key != verifying_shares[position].1 {
+        return Err(CliError::protocol("The new signing share does not match its verifying share".to_string()));
+    }
+
+    let mut nonce = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(&mut nonce[..]);
+
+    let mut signing_share = Zeroizing::new(Vec::with_capacity(KEYPAIR_LENGTH));
+    signing_share.extend_from_slice(secret.as_bytes());
+    signing_share.extend_from_slice(&nonce[..]);
+    signing_share.extend_from_slice(&public_key);
+    parse_signing_share(&signing_share)?;
+
+    let new_spp_output = SPPOutputView {
+        participants: recipients.len() as u16,
+        threshold: input.new_threshold,
+        threshold_public_key: view.threshold_public_key,
+        verifying_shares,
+    }
+    .to_bytes();
+
+    let signature = keypair.sign_simple(RESHARE_CONTEXT, &new_spp_output).to_bytes();
+    let generation_output = SPPOutputMessageView {
+        signer: &keypair.public.to_bytes(),
+        spp_output: &new_spp_output,
+        signature: &signature,
+    }
+    .to_bytes();
+
+    SPPOutputMessage::from_bytes(&generation_output)
+        .map_err(|e| CliError::protocol(format!("Failed to encode new generation output: {:?}", e)))?;
+
+    let bundle = GenerationOutputBundle::sign(&keypair, generation_output.clone(), &new_spp_output);
+
+    Ok(ReshareRound2Output {
+        generation_output,
+        generation_output_bundle: bundle.to_bytes(),
+        signing_share,
+        threshold_public_key: AccountId32(view.threshold_public_key),
+        dealers,
+    })
+}
+
+pub fn parse_signing_share(signing_share: &[u8]) -> Result<SigningKeypair, CliError> {
+    SigningKeypair::from_bytes(signing_share)
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse signing share: {}", e)))
+}
+
+/// The public key of a signing share, the verifying share the other participants know it by
+pub fn verifying_share(signing_share: &[u8]) -> Result<PublicKey, CliError> {
+    Keypair::from_bytes(signing_share)
+        .map(|keypair| keypair.public)
+        .map_err(|e| CliError::key_parsing(format!("Failed to parse signing share: {}", e)).with_source(e))
+}
+
+pub fn parse_signing_commitments(signing_commitments: &[Vec<u8>]) -> Result<Vec<SigningCommitments>, CliError> {
+    signing_commitments
+        .iter()
+        .map(|sc| SigningCommitments::from_bytes(sc)
+            .map_err(|e| CliError::protocol(format!("Failed to parse SigningCommitments: {:?}", e))))
+        .collect()
+}
+
+/// Checks that all signing packages signed the same payload and context with the same signing
+/// commitments. The expected payload is the given one if there is one, and otherwise the one
+/// signed by most packages.
+fn check_agreement(views: &[SigningPackageView], expected_payload: Option<(&str, &str)>) -> Result<(), CliError> {
+    let views: Vec<&SigningPackageView> = views.iter().collect();
+
+    let mismatches: Vec<String> = package_differences(&views, expected_payload)
+        .iter()
+        .enumerate()
+        .filter(|(_, differences)| !differences.is_empty())
+        .map(|(index, differences)| format!("signing package {} signed {}", index + 1, differences.join(", ")))
+        .collect();
+
+    if !mismatches.is_empty() {
+        return Err(CliError::protocol(format!("The signing packages do not agree: {}", mismatches.join("; "))));
+    }
+
+    Ok(())
+}
+
+/// How each signing package differs from what all of them should have signed: the given payload
+/// and context if there are any, and otherwise the ones signed by most packages
+fn package_differences(views: &[&SigningPackageView], expected_payload: Option<(&str, &str)>) -> Vec<Vec<String>> {
+    let signed = |view: &SigningPackageView| {
+        (
+            payload_hash(&view.payload),
+            String::from_utf8_lossy(&view.context).into_owned(),
+            blake2_256(&view.signing_commitments.concat()),
+            blake2_256(&view.spp_output),
+        )
+    };
+
+    let Some(mut expected) = views
+        .iter()
+        .map(|view| signed(view))
+        .max_by_key(|expected| views.iter().filter(|view| signed(view) == *expected).count())
+    else {
+        return Vec::new();
+    };
+
+    if let Some((payload_hash, context)) = expected_payload {
+        expected.0 = payload_hash.trim_start_matches("0x").to_string();
+        expected.1 = context.to_string();
+    }
+
+    views
+        .iter()
+        .map(|view| {
+            let (payload, context, commitments, spp_output) = signed(view);
+
+            let mut differences = Vec::new();
+            if payload != expected.0 {
+                differences.push(format!("payload hash {} instead of {}", payload, expected.0));
+            }
+            if context != expected.1 {
+                differences.push(format!("context {:?} instead of {:?}", context, expected.1));
+            }
+            if commitments != expected.2 {
+                differences.push("different signing commitments".to_string());
+            }
+            if spp_output != expected.3 {
+                differences.push("with signing shares from before or after a refresh of the others".to_string());
+            }
+
+            differences
+        })
+        .collect()
+}
+
+/// Names the participants whose signature shares did not verify against their verifying shares,
+/// by their account among the recipients, and whether the others still meet the threshold.
+///
+/// Each signature share binds the commitments of all signers, so the valid shares cannot be
+/// aggregated without the invalid ones: the remaining signers have to run a new signing round.
+fn identify_culprits(
+    views: &[SigningPackageView],
+    culprits: &[schnorrkel::olaf::VerifyingShare],
+    recipients: &[String],
+) -> Result<CliError, CliError> {
+    let Some(view) = views.first() else {
+        return Ok(CliError::protocol("Failed to aggregate threshold signature: no signing packages".to_string()));
+    };
+
+    let spp_output = SPPOutputView::parse(&view.spp_output)?;
+
+    let names: Vec<String> = culprits
+        .iter()
+        .map(|culprit| {
+            let verifying_share = culprit.0.to_bytes();
+
+            match spp_output.position(&verifying_share) {
+                Some(index) => match recipients.get(index) {
+                    Some(account) => format!("participant {} ({})", index + 1, account),
+                    None => format!("participant {}", index + 1),
+                },
+                None => format!("unknown signer with verifying share 0x{}", hex::encode(verifying_share)),
+            }
+        })
+        .collect();
+
+    let remaining = views.len().saturating_sub(culprits.len());
+
+    let next_step = if remaining >= spp_output.threshold as usize {
+        format!(
+            "the {} remaining signers still meet the threshold of {} and can run a new signing round without them",
+            remaining, spp_output.threshold,
+        )
+    } else {
+        format!(
+            "the {} remaining signers do not meet the threshold of {}",
+            remaining, spp_output.threshold,
+        )
+    };
+
+    Ok(CliError::protocol(format!(
+        "Failed to aggregate threshold signature: invalid signature share from {}; {}",
+        names.join(", "),
+        next_step,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: &[u8] = b"substrate";
+    const PAYLOAD: &[u8] = b"payload";
+
+    /// The contributor secret key, generation output and signing share of one participant
+    struct Holder {
+        secret_key: [u8; 32],
+        generation_output: Vec<u8>,
+        signing_share: Zeroizing<Vec<u8>>,
+    }
+
+    /// The contributor secret keys of `participants` new participants, and their SS58 accounts
+    fn contributors(participants: usize) -> (Vec<[u8; 32]>, Vec<String>) {
+        let secret_keys: Vec<[u8; 32]> = (0..participants)
+            .map(|_| MiniSecretKey::generate_with(OsRng).to_bytes())
+            .collect();
+        let recipients: Vec<String> = secret_keys
+            .iter()
+            .map(|secret_key| AccountId32(contributor_keypair(secret_key, KeyExpansion::Ed25519).unwrap().public.to_bytes()).to_string())
+            .collect();
+
+        (secret_keys, recipients)
+    }
+
+    /// Runs both rounds of key generation among `participants`, returning the recipients, every
+    /// holder and the threshold public key
+    fn generate(participants: usize, threshold: u16) -> (Vec<String>, Vec<Holder>, AccountId32) {
+        let (secret_keys, recipients) = contributors(participants);
+
+        let all_messages: Vec<Vec<u8>> = secret_keys
+            .iter()
+            .map(|secret_key| {
+                dkg_round1(DkgRound1Input {
+                    secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    threshold,
+                    recipients: &recipients,
+                })
+                .unwrap()
+                .all_message
+            })
+            .collect();
+
+        let outputs: Vec<DkgRound2Output> = secret_keys
+            .iter()
+            .map(|secret_key| {
+                dkg_round2(DkgRound2Input {
+                    secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    recipients: &recipients,
+                    all_messages: &all_messages,
+                    allow_legacy: false,
+                })
+                .unwrap()
+            })
+            .collect();
+
+        let threshold_public_key = outputs[0].threshold_public_key.clone();
+        let holders = secret_keys
+            .into_iter()
+            .zip(outputs)
+            .map(|(secret_key, output)| Holder {
+                secret_key,
+                generation_output: output.generation_output,
+                signing_share: output.signing_share,
+            })
+            .collect();
+
+        (recipients, holders, threshold_public_key)
+    }
+
+    /// Runs both rounds of a refresh among all holders, returning the refreshed holders
+    fn refresh(recipients: &[String], holders: &[Holder], threshold_public_key: &AccountId32) -> Vec<Holder> {
+        let dealings: Vec<Vec<u8>> = holders
+            .iter()
+            .map(|holder| {
+                refresh_round1(RefreshRound1Input {
+                    secret_key: &holder.secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    generation_output: &holder.generation_output,
+                    recipients,
+                })
+                .unwrap()
+                .dealing
+            })
+            .collect();
+
+        holders
+            .iter()
+            .map(|holder| {
+                let output = refresh_round2(RefreshRound2Input {
+                    secret_key: &holder.secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    signing_share: &holder.signing_share,
+                    generation_output: &holder.generation_output,
+                    recipients,
+                    dealings: &dealings,
+                })
+                .unwrap();
+
+                assert_eq!(output.threshold_public_key, *threshold_public_key);
+
+                Holder {
+                    secret_key: holder.secret_key,
+                    generation_output: output.generation_output,
+                    signing_share: output.signing_share,
+                }
+            })
+            .collect()
+    }
+
+    /// The reshare messages of the holders at `positions`, starting from 0, to `new_recipients`
+    fn reshare_messages(
+        recipients: &[String],
+        holders: &[Holder],
+        positions: &[usize],
+        new_recipients: &[String],
+        new_threshold: u16,
+        dealers: Option<&[u16]>,
+    ) -> Vec<Vec<u8>> {
+        positions
+            .iter()
+            .map(|position| {
+                let holder = &holders[*position];
+
+                reshare_round1(ReshareRound1Input {
+                    secret_key: &holder.secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    signing_share: &holder.signing_share,
+                    generation_output: &holder.generation_output,
+                    recipients,
+                    new_recipients,
+                    new_threshold,
+                    dealers,
+                })
+                .unwrap()
+                .reshare_message
+            })
+            .collect()
+    }
+
+    /// Runs round 2 of resharing for every new participant
+    fn reshare(
+        secret_keys: &[[u8; 32]],
+        new_recipients: &[String],
+        new_threshold: u16,
+        reshare_messages: &[Vec<u8>],
+        threshold_public_key: &AccountId32,
+        dealers: Option<&[u16]>,
+    ) -> Result<Vec<Holder>, CliError> {
+        secret_keys
+            .iter()
+            .map(|secret_key| {
+                reshare_round2(ReshareRound2Input {
+                    secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    new_recipients,
+                    new_threshold,
+                    reshare_messages,
+                    threshold_public_key,
+                    dealers,
+                })
+                .map(|output| Holder {
+                    secret_key: *secret_key,
+                    generation_output: output.generation_output,
+                    signing_share: output.signing_share,
+                })
+            })
+            .collect()
+    }
+
+    /// Signs with each signing share, against the generation output given with it, and aggregates
+    /// the signing packages
+    fn sign(signers: &[(&[u8], &[u8])], recipients: &[String]) -> Result<Signature, CliError> {
+        let round1: Vec<SignRound1Output> = signers
+            .iter()
+            .map(|(signing_share, _)| sign_round1(SignRound1Input { signing_share }).unwrap())
+            .collect();
+        let signing_commitments: Vec<Vec<u8>> = round1
+            .iter()
+            .map(|output| output.signing_commitments.to_bytes().to_vec())
+            .collect();
+
+        let signing_packages = signers
+            .iter()
+            .zip(&round1)
+            .map(|((signing_share, generation_output), output)| {
+                sign_round2(SignRound2Input {
+                    signing_share,
+                    generation_output,
+                    signing_commitments: &signing_commitments,
+                    signing_nonces: &output.signing_nonces,
+                    context: CONTEXT,
+                    payload: PAYLOAD.to_vec(),
+                })
+                .map(|output| output.signing_package)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        aggregate(AggregateInput {
+            signing_packages: &signing_packages,
+            expected: None,
+            recipients,
+        })
+        .map(|output| output.signature)
+    }
+
+    fn verifies(signature: &Signature, threshold_public_key: &AccountId32) -> bool {
+        PublicKey::from_bytes(&threshold_public_key.0)
+            .unwrap()
+            .verify_simple(CONTEXT, PAYLOAD, signature)
+            .is_ok()
+    }
+
+    #[test]
+    fn a_threshold_signature_verifies_for_its_key_payload_and_context_only() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let (_, _, other_threshold_public_key) = generate(3, 2);
+
+        let signature = sign(
+            &[
+                (&holders[0].signing_share[..], &holders[0].generation_output[..]),
+                (&holders[1].signing_share[..], &holders[1].generation_output[..]),
+            ],
+            &recipients,
+        )
+        .unwrap();
+
+        assert!(verifies(&signature, &threshold_public_key));
+        assert!(!verifies(&signature, &other_threshold_public_key));
+
+        let public_key = PublicKey::from_bytes(&threshold_public_key.0).unwrap();
+        assert!(public_key.verify_simple(CONTEXT, b"another payload", &signature).is_err());
+        assert!(public_key.verify_simple(b"another context", PAYLOAD, &signature).is_err());
+    }
+
+    #[test]
+    fn refreshed_shares_sign_for_the_same_key() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let refreshed = refresh(&recipients, &holders, &threshold_public_key);
+
+        let signature = sign(
+            &[
+                (&refreshed[0].signing_share[..], &refreshed[0].generation_output[..]),
+                (&refreshed[2].signing_share[..], &refreshed[2].generation_output[..]),
+            ],
+            &recipients,
+        )
+        .unwrap();
+
+        assert!(verifies(&signature, &threshold_public_key));
+    }
+
+    #[test]
+    fn shares_from_before_and_after_a_refresh_do_not_sign_together() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let refreshed = refresh(&recipients, &holders, &threshold_public_key);
+
+        // The stale share, against its own generation output or the refreshed one
+        for stale_generation_output in [&holders[0].generation_output, &refreshed[0].generation_output] {
+            let mixed = sign(
+                &[
+                    (&holders[0].signing_share[..], &stale_generation_output[..]),
+                    (&refreshed[1].signing_share[..], &refreshed[1].generation_output[..]),
+                ],
+                &recipients,
+            );
+
+            assert!(!mixed.is_ok_and(|signature| verifies(&signature, &threshold_public_key)));
+        }
+    }
+
+    #[test]
+    fn refreshed_generation_output_is_signed_by_its_participant() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let refreshed = refresh(&recipients, &holders, &threshold_public_key);
+
+        for holder in &refreshed {
+            let message = SPPOutputMessageView::parse(&holder.generation_output).unwrap();
+            let keypair = contributor_keypair(&holder.secret_key, KeyExpansion::Ed25519).unwrap();
+
+            assert_eq!(message.signer, keypair.public.to_bytes());
+            assert!(keypair
+                .public
+                .verify_simple(REFRESH_BINDING_CONTEXT, message.spp_output, &Signature::from_bytes(message.signature).unwrap())
+                .is_ok());
+        }
+    }
+
+    /// The signed generation output a holder sends for the DKG certificate
+    fn output_bundle(holder: &Holder) -> Vec<u8> {
+        let keypair = contributor_keypair(&holder.secret_key, KeyExpansion::Ed25519).unwrap();
+        let spp_output = SPPOutputMessage::from_bytes(&holder.generation_output).unwrap().spp_output().to_bytes();
+
+        GenerationOutputBundle::sign(&keypair, holder.generation_output.clone(), &spp_output).to_bytes()
+    }
+
+    #[test]
+    fn outputs_with_different_verifying_shares_are_not_certified() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let refreshed = refresh(&recipients, &holders, &threshold_public_key);
+
+        let bundles: Vec<Vec<u8>> = holders.iter().map(output_bundle).collect();
+        dkg_certificate(&bundles, &recipients).unwrap().verify().unwrap();
+
+        // The refreshed output keeps the threshold public key, but not the verifying shares
+        let mixed = vec![output_bundle(&holders[0]), output_bundle(&holders[1]), output_bundle(&refreshed[2])];
+        let refused = dkg_certificate(&mixed, &recipients).unwrap_err();
+
+        assert!(matches!(refused, CliError::Protocol { .. }));
+        assert!(refused.message().contains(&format!("participant 3 ({}) derived different verifying shares", recipients[2])));
+    }
+
+    #[test]
+    fn reshared_shares_sign_for_the_same_key() {
+        // To more participants with a larger threshold, and to fewer with a smaller one
+        for (participants, threshold, new_participants, new_threshold) in [(3, 2, 5, 4), (4, 3, 3, 2)] {
+            let (recipients, holders, threshold_public_key) = generate(participants, threshold);
+            let (secret_keys, new_recipients) = contributors(new_participants);
+
+            let dealers: Vec<usize> = (0..threshold as usize).collect();
+            let messages = reshare_messages(&recipients, &holders, &dealers, &new_recipients, new_threshold, None);
+            let reshared = reshare(&secret_keys, &new_recipients, new_threshold, &messages, &threshold_public_key, None).unwrap();
+
+            let signers: Vec<(&[u8], &[u8])> = reshared[new_participants - new_threshold as usize..]
+                .iter()
+                .map(|holder| (&holder.signing_share[..], &holder.generation_output[..]))
+                .collect();
+            let signature = sign(&signers, &new_recipients).unwrap();
+
+            assert!(verifies(&signature, &threshold_public_key));
+        }
+    }
+
+    #[test]
+    fn chosen_dealers_reshare_for_the_same_key() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let (secret_keys, new_recipients) = contributors(3);
+        let dealers: &[u16] = &[2, 3];
+
+        let messages = reshare_messages(&recipients, &holders, &[1, 2], &new_recipients, 2, Some(dealers));
+
+        // Every new participant must combine the dealings of the same holders
+        assert!(reshare(&secret_keys, &new_recipients, 2, &messages, &threshold_public_key, None).is_err());
+
+        let reshared = reshare(&secret_keys, &new_recipients, 2, &messages, &threshold_public_key, Some(dealers)).unwrap();
+        let signature = sign(
+            &[
+                (&reshared[0].signing_share[..], &reshared[0].generation_output[..]),
+                (&reshared[1].signing_share[..], &reshared[1].generation_output[..]),
+            ],
+            &new_recipients,
+        )
+        .unwrap();
+
+        assert!(verifies(&signature, &threshold_public_key));
+    }
+
+    #[test]
+    fn fewer_dealings_than_the_threshold_are_refused() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let (secret_keys, new_recipients) = contributors(3);
+
+        let messages = reshare_messages(&recipients, &holders, &[0], &new_recipients, 2, None);
+        let refused = reshare(&secret_keys, &new_recipients, 2, &messages, &threshold_public_key, None);
+        assert!(matches!(refused, Err(CliError::Input { .. })));
+
+        // Fewer dealers than the threshold cannot even be chosen
+        let refused = reshare_round1(ReshareRound1Input {
+            secret_key: &holders[0].secret_key,
+            expansion: KeyExpansion::Ed25519,
+            signing_share: &holders[0].signing_share,
+            generation_output: &holders[0].generation_output,
+            recipients: &recipients,
+            new_recipients: &new_recipients,
+            new_threshold: 2,
+            dealers: Some(&[1][..]),
+        });
+        assert!(matches!(refused, Err(CliError::Input { .. })));
+    }
+
+    #[test]
+    fn a_dealing_of_another_secret_is_refused() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let (secret_keys, new_recipients) = contributors(3);
+        let mut messages = reshare_messages(&recipients, &holders, &[0, 1], &new_recipients, 2, None);
+
+        // Signed by the signing share of holder 2 and bound to this resharing, but dealing another secret
+        let mut message = ReshareMessage::from_bytes(&messages[1]).unwrap();
+        let new_public_keys = validate_recipients(&new_recipients, 2).unwrap();
+        message.dealing = Dealing::deal(
+            &Keypair::from_bytes(&holders[1].signing_share).unwrap(),
+            reshare_binding(&message.spp_output, &[1, 2], &new_public_keys, 2),
+            Scalar::from_bytes_mod_order(rand::random()),
+            2,
+            &reshare_recipients(&new_public_keys),
+        )
+        .unwrap();
+        messages[1] = message.to_bytes();
+
+        let refused = reshare(&secret_keys, &new_recipients, 2, &messages, &threshold_public_key, None);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+        assert!(refused.err().unwrap().message().contains("does not deal the signing share of its holder"));
+    }
+
+    #[test]
+    fn a_foreign_or_tampered_spp_output_is_refused() {
+        let (recipients, holders, threshold_public_key) = generate(3, 2);
+        let (other_recipients, other_holders, _) = generate(3, 2);
+        let (secret_keys, new_recipients) = contributors(3);
+
+        let messages = reshare_messages(&recipients, &holders, &[0, 1], &new_recipients, 2, None);
+        let foreign = reshare_messages(&other_recipients, &other_holders, &[0, 1], &new_recipients, 2, None);
+
+        // Only messages for another key
+        let refused = reshare(&secret_keys, &new_recipients, 2, &foreign, &threshold_public_key, None);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+
+        // One message for another key among the expected ones
+        let mixed = [messages[0].clone(), foreign[1].clone()];
+        let refused = reshare(&secret_keys, &new_recipients, 2, &mixed, &threshold_public_key, None);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+
+        // One message whose SPPOutput has the verifying share of another holder changed
+        let mut message = ReshareMessage::from_bytes(&messages[1]).unwrap();
+        let last = message.spp_output.len() - 1;
+        message.spp_output[last] ^= 1;
+        let tampered = [messages[0].clone(), message.to_bytes()];
+        let refused = reshare(&secret_keys, &new_recipients, 2, &tampered, &threshold_public_key, None);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+    }
+
+    /// The round 1 message of each participant, created for `recipients`
+    fn round1(secret_keys: &[[u8; 32]], recipients: &[String]) -> Vec<Vec<u8>> {
+        secret_keys
+            .iter()
+            .map(|secret_key| {
+                dkg_round1(DkgRound1Input {
+                    secret_key,
+                    expansion: KeyExpansion::Ed25519,
+                    threshold: 2,
+                    recipients,
+                })
+                .unwrap()
+                .all_message
+            })
+            .collect()
+    }
+
+    fn round2(secret_key: &[u8; 32], recipients: &[String], all_messages: &[Vec<u8>], allow_legacy: bool) -> Result<DkgRound2Output, CliError> {
+        dkg_round2(DkgRound2Input {
+            secret_key,
+            expansion: KeyExpansion::Ed25519,
+            recipients,
+            all_messages,
+            allow_legacy,
+        })
+    }
+
+    #[test]
+    fn a_threshold_out_of_range_is_refused() {
+        let (_, recipients) = contributors(3);
+
+        for threshold in [0, 1, 4] {
+            assert!(matches!(validate_recipients(&recipients, threshold), Err(CliError::Input { .. })));
+        }
+        assert!(validate_recipients(&recipients, 2).is_ok());
+        assert!(validate_recipients(&recipients, 3).is_ok());
+    }
+
+    #[test]
+    fn duplicate_recipients_are_refused() {
+        let (_, recipients) = contributors(3);
+        let duplicated = [recipients[0].clone(), recipients[1].clone(), recipients[0].clone()];
+
+        assert!(matches!(validate_recipients(&duplicated, 2), Err(CliError::Input { .. })));
+    }
+
+    #[test]
+    fn a_contributor_outside_the_recipients_is_refused() {
+        let (_, recipients) = contributors(3);
+        let (outsiders, _) = contributors(1);
+
+        assert!(matches!(check_recipient(&outsiders[0], KeyExpansion::Ed25519, &recipients), Err(CliError::Input { .. })));
+
+        let refused = dkg_round1(DkgRound1Input {
+            secret_key: &outsiders[0],
+            expansion: KeyExpansion::Ed25519,
+            threshold: 2,
+            recipients: &recipients,
+        });
+        assert!(matches!(refused, Err(CliError::Input { .. })));
+    }
+
+    #[test]
+    fn a_round1_message_for_other_recipients_is_refused() {
+        let (secret_keys, recipients) = contributors(3);
+        let mut all_messages = round1(&secret_keys, &recipients);
+
+        // The last participant created its message for the same accounts in another order
+        let reordered = [recipients[1].clone(), recipients[0].clone(), recipients[2].clone()];
+        all_messages[2] = round1(&secret_keys[2..], &reordered).remove(0);
+
+        let refused = round2(&secret_keys[0], &recipients, &all_messages, false);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+
+        // Accepting legacy messages does not skip the check of bound ones
+        let refused = round2(&secret_keys[0], &recipients, &all_messages, true);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+    }
+
+    #[test]
+    fn a_legacy_round1_message_needs_allow_legacy() {
+        let (secret_keys, recipients) = contributors(3);
+        let mut all_messages = round1(&secret_keys, &recipients);
+
+        // As sent by older versions: the bare AllMessage, without sender or recipients hash
+        all_messages[1] = AllMessageBundle::from_bytes(&all_messages[1]).all_message;
+
+        let refused = round2(&secret_keys[0], &recipients, &all_messages, false);
+        assert!(matches!(refused, Err(CliError::Protocol { .. })));
+
+        let threshold_public_keys: Vec<AccountId32> = secret_keys
+            .iter()
+            .map(|secret_key| round2(secret_key, &recipients, &all_messages, true).unwrap().threshold_public_key)
+            .collect();
+        assert!(threshold_public_keys.iter().all(|key| *key == threshold_public_keys[0]));
+    }
+
+    /// The signing packages of `signers`, each signing the context and payload given with it. The
+    /// signer at `bad_share`, if any, signs with other signing nonces than the ones it committed to.
+    fn signing_packages(signers: &[(&Holder, &[u8], &[u8])], bad_share: Option<usize>) -> Vec<Vec<u8>> {
+        let round1: Vec<SignRound1Output> = signers
+            .iter()
+            .map(|(holder, _, _)| sign_round1(SignRound1Input { signing_share: &holder.signing_share }).unwrap())
+            .collect();
+        let signing_commitments: Vec<Vec<u8>> = round1
+            .iter()
+            .map(|output| output.signing_commitments.to_bytes().to_vec())
+            .collect();
+
+        signers
+            .iter()
+            .zip(&round1)
+            .enumerate()
+            .map(|(index, ((holder, context, payload), output))| {
+                let other_nonces = sign_round1(SignRound1Input { signing_share: &holder.signing_share }).unwrap().signing_nonces;
+                let signing_nonces = if bad_share == Some(index) { &other_nonces } else { &output.signing_nonces };
+
+                sign_round2(SignRound2Input {
+                    signing_share: &holder.signing_share,
+                    generation_output: &holder.generation_output,
+                    signing_commitments: &signing_commitments,
+                    signing_nonces,
+                    context,
+                    payload: payload.to_vec(),
+                })
+                .unwrap()
+                .signing_package
+            })
+            .collect()
+    }
+
+    /// The signing packages with the verifying share of their signers, as checked on their own
+    fn with_signers(holders: &[Holder], signing_packages: &[Vec<u8>]) -> Vec<(Option<[u8; 32]>, Vec<u8>)> {
+        holders
+            .iter()
+            .zip(signing_packages)
+            .map(|(holder, package)| (Some(verifying_share(&holder.signing_share).unwrap().to_bytes()), package.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn an_invalid_signature_share_names_its_signer_only() {
+        let (recipients, holders, _) = generate(3, 2);
+        let signers: Vec<(&Holder, &[u8], &[u8])> = holders.iter().map(|holder| (holder, CONTEXT, PAYLOAD)).collect();
+        let packages = signing_packages(&signers, Some(1));
+
+        let error = aggregate(AggregateInput {
+            signing_packages: &packages,
+            expected: None,
+            recipients: &recipients,
+        })
+        .err()
+        .unwrap();
+
+        assert!(matches!(error, CliError::Protocol { .. }));
+        assert!(error.message().contains(&format!("participant 2 ({})", recipients[1])));
+        assert!(!error.message().contains(&recipients[0]));
+        assert!(!error.message().contains(&recipients[2]));
+        assert!(error.message().contains("still meet the threshold"));
+
+        let checked = check_signing_packages(CheckSigningPackagesInput {
+            signing_packages: &with_signers(&holders, &packages),
+            expected: None,
+        })
+        .unwrap();
+
+        assert!(checked.shares_checked);
+        assert!(checked.problems[0].is_empty());
+        assert!(!checked.problems[1].is_empty());
+        assert!(checked.problems[2].is_empty());
+    }
+
+    #[test]
+    fn a_signer_of_another_payload_or_context_is_named_only() {
+        let (recipients, holders, _) = generate(3, 2);
+
+        for (context, payload) in [(CONTEXT, &b"another payload"[..]), (&b"another context"[..], PAYLOAD)] {
+            let packages = signing_packages(
+                &[(&holders[0], CONTEXT, PAYLOAD), (&holders[1], CONTEXT, PAYLOAD), (&holders[2], context, payload)],
+                None,
+            );
+
+            let views: Vec<SigningPackageView> = packages.iter().map(|package| SigningPackageView::parse(package).unwrap()).collect();
+            let differences = package_differences(&views.iter().collect::<Vec<_>>(), None);
+            assert!(differences[0].is_empty());
+            assert!(differences[1].is_empty());
+            assert!(!differences[2].is_empty());
+
+            let error = aggregate(AggregateInput {
+                signing_packages: &packages,
+                expected: None,
+                recipients: &recipients,
+            })
+            .err()
+            .unwrap();
+
+            assert!(matches!(error, CliError::Protocol { .. }));
+            assert!(error.message().contains("signing package 3 signed"));
+            assert!(!error.message().contains("signing package 1"));
+            assert!(!error.message().contains("signing package 2"));
+
+            let checked = check_signing_packages(CheckSigningPackagesInput {
+                signing_packages: &with_signers(&holders, &packages),
+                expected: None,
+            })
+            .unwrap();
+
+            assert!(checked.problems[0].is_empty());
+            assert!(checked.problems[1].is_empty());
+            assert!(!checked.problems[2].is_empty());
+        }
+    }
+
+    #[test]
+    fn every_signer_of_another_payload_than_the_expected_one_is_named() {
+        let (_, holders, _) = generate(3, 2);
+        let signers: Vec<(&Holder, &[u8], &[u8])> = holders.iter().map(|holder| (holder, CONTEXT, PAYLOAD)).collect();
+        let packages = signing_packages(&signers, None);
+
+        let expected_payload = payload_hash(b"another payload");
+        let checked = check_signing_packages(CheckSigningPackagesInput {
+            signing_packages: &with_signers(&holders, &packages),
+            expected: Some((&expected_payload, "substrate")),
+        })
+        .unwrap();
+
+        assert!(!checked.shares_checked);
+        assert!(checked.problems.iter().all(|problems| !problems.is_empty()));
+    }
+}
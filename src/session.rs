@@ -0,0 +1,199 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
+use crate::{
+    error::CliError,
+    files::FilePaths,
+    storage::{read_json, write_json, Artifact, Backend, Storage},
+};
+
+/// Nonces created outside of any session are bound to this name
+pub const NO_SESSION: &str = "default";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPurpose {
+    /// Generation of a threshold public key and its signing shares
+    Dkg,
+    /// Threshold signing with the key material of a DKG
+    Sign,
+}
+
+impl fmt::Display for SessionPurpose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionPurpose::Dkg => write!(f, "dkg"),
+            SessionPurpose::Sign => write!(f, "sign"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Open,
+    Closed,
+}
+
+impl fmt::Display for SessionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionStatus::Open => write!(f, "open"),
+            SessionStatus::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// Description of a ceremony, stored as `session.json` in the session directory of the storage
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionManifest {
+    pub id: String,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub purpose: SessionPurpose,
+    /// SS58 accounts of the participants
+    pub participants: Vec<String>,
+    pub threshold: u16,
+    /// The DKG session whose key material a signing session uses, if not the base directory
+    pub dkg_session: Option<String>,
+    pub status: SessionStatus,
+    pub closed_at: Option<u64>,
+}
+
+impl SessionManifest {
+    /// The paths a command running in this session reads and writes
+    pub fn file_paths(&self, files: &str) -> FilePaths {
+        let base = FilePaths::new(files);
+        let session_dir = base.session_dir(&self.id);
+
+        let keys_dir = match (&self.purpose, &self.dkg_session) {
+            (SessionPurpose::Dkg, _) => session_dir.clone(),
+            (SessionPurpose::Sign, Some(dkg_session)) => base.session_dir(dkg_session),
+            (SessionPurpose::Sign, None) => files.into(),
+        };
+
+        FilePaths::with_session(files, session_dir, keys_dir)
+    }
+}
+
+/// The session holding the key material a command in `manifest` uses, or [`NO_SESSION`] for the
+/// base directory. Messages that outlive a signing session, such as precommitted signing
+/// commitments, are exchanged under it.
+pub fn keys_session(manifest: Option<&SessionManifest>) -> &str {
+    match manifest {
+        Some(SessionManifest { purpose: SessionPurpose::Dkg, id, .. }) => id,
+        Some(SessionManifest { dkg_session: Some(dkg_session), .. }) => dkg_session,
+        _ => NO_SESSION,
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Session IDs become directory names, so only a conservative set of characters is allowed
+pub fn validate_session_id(session_id: &str) -> Result<(), CliError> {
+    let valid = !session_id.is_empty()
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(CliError::input(format!(
+            "Invalid session ID '{}': only letters, digits, '-' and '_' are allowed",
+            session_id
+        )))
+    }
+}
+
+/// The storage of the artifacts of a session, where its manifest is kept
+fn session_storage(files: &str, backend: &Backend, session_id: &str) -> Result<Arc<dyn Storage>, CliError> {
+    validate_session_id(session_id)?;
+
+    let session_dir = FilePaths::new(files).session_dir(session_id);
+
+    backend.open(&FilePaths::with_session(files, &session_dir, &session_dir))
+}
+
+pub async fn read_manifest(files: &str, backend: &Backend, session_id: &str) -> Result<SessionManifest, CliError> {
+    let storage = session_storage(files, backend, session_id)?;
+
+    if storage.get(Artifact::SessionManifest)?.is_none() {
+        return Err(CliError::input(format!(
+            "Session {} does not exist: no manifest at {}",
+            session_id,
+            storage.location(Artifact::SessionManifest)
+        )));
+    }
+
+    read_json(&*storage, Artifact::SessionManifest)
+        .map_err(|e| e.context(format!("Failed to read manifest of session {}", session_id)))
+}
+
+/// Whether a session with this ID has a manifest in the storage
+pub async fn manifest_exists(files: &str, backend: &Backend, session_id: &str) -> Result<bool, CliError> {
+    Ok(session_storage(files, backend, session_id)?
+        .get(Artifact::SessionManifest)?
+        .is_some())
+}
+
+/// Reads the manifests of all sessions stored under the base directory. The ones that cannot be
+/// read are skipped, with a warning for each.
+pub async fn list_manifests(files: &str, backend: &Backend) -> Result<(Vec<SessionManifest>, Vec<String>), CliError> {
+    let mut manifests = Vec::new();
+    let mut warnings = Vec::new();
+
+    for session_id in backend.session_ids(&FilePaths::new(files))? {
+        match read_manifest(files, backend, &session_id).await {
+            Ok(manifest) => manifests.push(manifest),
+            Err(e) => warnings.push(format!("Skipping session {}: {}", session_id, e.message())),
+        }
+    }
+
+    manifests.sort_by_key(|manifest| manifest.created_at);
+
+    Ok((manifests, warnings))
+}
+
+pub async fn write_manifest(files: &str, backend: &Backend, manifest: &SessionManifest) -> Result<(), CliError> {
+    let storage = session_storage(files, backend, &manifest.id)?;
+
+    write_json(&*storage, Artifact::SessionManifest, manifest)
+        .map_err(|e| e.context(format!("Failed to write manifest of session {}", manifest.id)))
+}
+
+/// Resolves the paths a round command uses: the flat base directory without a session, or the
+/// directories of an open session of the expected purpose
+pub async fn resolve_file_paths(
+    files: &str,
+    backend: &Backend,
+    session: Option<&str>,
+    purpose: SessionPurpose,
+) -> Result<(FilePaths, Option<SessionManifest>), CliError> {
+    let Some(session_id) = session else {
+        return Ok((FilePaths::new(files), None));
+    };
+
+    let manifest = read_manifest(files, backend, session_id).await?;
+
+    if manifest.status == SessionStatus::Closed {
+        return Err(CliError::input(format!("Session {} is closed", session_id)));
+    }
+
+    if manifest.purpose != purpose {
+        return Err(CliError::input(format!(
+            "Session {} is a {} session, but this command needs a {} session",
+            session_id, manifest.purpose, purpose
+        )));
+    }
+
+    Ok((manifest.file_paths(files), Some(manifest)))
+}
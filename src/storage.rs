@@ -0,0 +1,914 @@
+//! Where the artifacts of the protocols are kept between rounds.
+//!
+//! The round functions of [`protocol`](crate::protocol) never touch storage themselves, and the
+//! commands of the CLI only go through [`Storage`]. The backend is chosen with `--storage`:
+//! - [`FileStorage`], the default, keeps each artifact in its own file in the layout of [`FilePaths`]
+//! - [`SqliteStorage`] keeps them in one SQLite database, keyed by the same relative paths
+//! - [`MemoryStorage`] keeps them in memory, for embedding and tests
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use fs2::FileExt;
+use rusqlite::{params, Connection, OptionalExtension};
+use rand::{rngs::OsRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use crate::{
+    error::CliError,
+    files::FilePaths,
+    messages::{decode_entries, upsert, Collection, Entry, Published},
+    participant::Participant,
+};
+
+/// An artifact produced or consumed by the protocols
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Artifact {
+    ContributorSecretKey(Participant),
+    Recipients,
+    GenerationOutput(Participant),
+    SigningShare(Participant),
+    ThresholdPublicKey,
+    DkgCertificate,
+    SigningNonce(Participant),
+    PrecommittedNonces(Participant),
+    SigningRequest,
+    ThresholdSignature,
+    ExtrinsicInfo,
+    SignedMessage,
+    /// The manifest of the session the storage is opened for
+    SessionManifest,
+    /// The messages shared by all participants in a round
+    Collection(Collection),
+}
+
+impl Artifact {
+    /// Whether the artifact holds secret material, which is only ever written readable by its owner
+    pub fn is_secret(&self) -> bool {
+        matches!(
+            self,
+            Artifact::ContributorSecretKey(_)
+                | Artifact::SigningShare(_)
+                | Artifact::SigningNonce(_)
+                | Artifact::PrecommittedNonces(_)
+        )
+    }
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Artifact::ContributorSecretKey(participant) => write!(f, "contributor secret key of {}", participant),
+            Artifact::Recipients => write!(f, "recipients"),
+            Artifact::GenerationOutput(participant) => write!(f, "generation output of {}", participant),
+            Artifact::SigningShare(participant) => write!(f, "signing share of {}", participant),
+            Artifact::ThresholdPublicKey => write!(f, "threshold public key"),
+            Artifact::DkgCertificate => write!(f, "DKG certificate"),
+            Artifact::SigningNonce(participant) => write!(f, "signing nonce of {}", participant),
+            Artifact::PrecommittedNonces(participant) => write!(f, "precommitted signing nonces of {}", participant),
+            Artifact::SigningRequest => write!(f, "signing request"),
+            Artifact::ThresholdSignature => write!(f, "threshold signature"),
+            Artifact::ExtrinsicInfo => write!(f, "extrinsic info"),
+            Artifact::SignedMessage => write!(f, "signed message"),
+            Artifact::SessionManifest => write!(f, "session manifest"),
+            Artifact::Collection(collection) => write!(f, "{}", collection),
+        }
+    }
+}
+
+/// A store of artifacts, keyed by what they are. Contents are opaque bytes, in the encodings of
+/// the files of the CLI; collections are JSON arrays of [`Entry`]s.
+pub trait Storage: Send + Sync {
+    /// The content of an artifact, or `None` if it has not been stored
+    fn get(&self, artifact: Artifact) -> Result<Option<Vec<u8>>, CliError>;
+
+    /// Stores an artifact, replacing any previous content
+    fn put(&self, artifact: Artifact, content: &[u8]) -> Result<(), CliError>;
+
+    /// Removes an artifact. Removing an artifact that was never stored is not an error.
+    fn delete(&self, artifact: Artifact) -> Result<(), CliError>;
+
+    /// Where an artifact is kept, for messages to the user
+    fn location(&self, artifact: Artifact) -> String;
+
+    /// The names of the artifacts stored for the session the storage is opened for, sorted, for
+    /// messages to the user
+    fn stored(&self) -> Result<Vec<String>, CliError>;
+
+    /// Every entry of a collection, in the order they were first published. Fails if the
+    /// collection does not exist yet.
+    fn list(&self, collection: Collection) -> Result<Vec<Entry>, CliError> {
+        match self.get(Artifact::Collection(collection))? {
+            Some(content) => decode_entries(collection, &content),
+            None => Err(CliError::io(format!("No {} found at {}", collection, self.location(Artifact::Collection(collection))))),
+        }
+    }
+
+    /// Every entry of a collection, or none if the collection does not exist yet
+    fn entries(&self, collection: Collection) -> Result<Vec<Entry>, CliError> {
+        match self.get(Artifact::Collection(collection))? {
+            Some(content) => decode_entries(collection, &content),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Replaces an artifact with what `update` makes of its current content, if any, with nothing
+    /// else writing the artifact in between. `update` is called once and returns the new content,
+    /// or `None` to leave the artifact as it is. If it fails, nothing is written.
+    ///
+    /// Artifacts that are read, changed and written back, such as collections and signing nonces,
+    /// must be changed this way: separate [`get`](Storage::get) and [`put`](Storage::put) calls
+    /// lose the changes of anyone writing in between. `update` must not use the storage itself.
+    fn update(&self, artifact: Artifact, update: &mut dyn FnMut(Option<&[u8]>) -> Result<Option<Vec<u8>>, CliError>) -> Result<(), CliError>;
+
+    /// Adds an entry to a collection, creating it if needed, or replaces the earlier entry of the
+    /// same sender
+    fn publish(&self, collection: Collection, entry: Entry) -> Result<Published, CliError> {
+        let mut published = Published::Unchanged;
+
+        self.update(Artifact::Collection(collection), &mut |content| {
+            let (entries_json, outcome) = publish_entry(collection, content, &entry)?;
+            published = outcome;
+            Ok(Some(entries_json))
+        })?;
+
+        Ok(published)
+    }
+
+    /// Overwrites the current content of an artifact with zeros, before it is replaced.
+    ///
+    /// This is best effort: journaling filesystems, flash storage and databases may keep older copies around.
+    fn wipe(&self, artifact: Artifact) -> Result<(), CliError> {
+        match self.get(artifact)? {
+            Some(content) => self.put(artifact, &vec![0u8; content.len()]),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Puts `entry` into the encoded collection `content`, if any, and returns the new encoding
+fn publish_entry(collection: Collection, content: Option<&[u8]>, entry: &Entry) -> Result<(Vec<u8>, Published), CliError> {
+    let mut entries = match content {
+        Some(content) => decode_entries(collection, content)?,
+        None => Vec::new(),
+    };
+    let published = upsert(&mut entries, entry.clone());
+
+    let entries_json = serde_json::to_vec(&entries)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize {} data: {}", collection, e)).with_source(e))?;
+
+    Ok((entries_json, published))
+}
+
+/// Reads and deserializes a JSON artifact
+pub fn read_json<T: DeserializeOwned>(storage: &dyn Storage, artifact: Artifact) -> Result<T, CliError> {
+    let content = storage
+        .get(artifact)?
+        .ok_or_else(|| CliError::io(format!("No {} found at {}", artifact, storage.location(artifact))))?;
+
+    serde_json::from_slice(&content)
+        .map_err(|e| CliError::serialization(format!("Failed to deserialize {}: {}", artifact, e)).with_source(e))
+}
+
+/// Serializes and stores a JSON artifact
+pub fn write_json<T: Serialize>(storage: &dyn Storage, artifact: Artifact, value: &T) -> Result<(), CliError> {
+    let content = serde_json::to_vec(value)
+        .map_err(|e| CliError::serialization(format!("Failed to serialize {}: {}", artifact, e)).with_source(e))?;
+
+    storage.put(artifact, &content)
+}
+
+/// A storage backend, as given with `--storage`. Commands open it for the artifacts of their
+/// session with [`Backend::open`].
+#[derive(Clone, Debug)]
+pub enum Backend {
+    Files,
+    Sqlite(PathBuf),
+}
+
+impl Backend {
+    /// Parses `files` or `sqlite:<path>`
+    pub fn parse(spec: &str) -> Result<Self, CliError> {
+        if spec == "files" {
+            return Ok(Backend::Files);
+        }
+
+        match spec.split_once(':') {
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Backend::Sqlite(PathBuf::from(path))),
+            _ => Err(CliError::input(format!("Invalid storage {}: expected files or sqlite:<path>", spec))),
+        }
+    }
+
+    /// Opens the backend for the artifacts located by `file_paths`
+    pub fn open(&self, file_paths: &FilePaths) -> Result<Arc<dyn Storage>, CliError> {
+        match self {
+            Backend::Files => Ok(Arc::new(FileStorage::new(file_paths.clone()))),
+            Backend::Sqlite(path) => Ok(Arc::new(SqliteStorage::open(path, file_paths.clone())?)),
+        }
+    }
+
+    /// The IDs of the sessions stored under the base directory of `file_paths`, sorted
+    pub fn session_ids(&self, file_paths: &FilePaths) -> Result<Vec<String>, CliError> {
+        match self {
+            Backend::Files => {
+                let sessions_dir = file_paths.sessions();
+
+                let entries = match fs::read_dir(&sessions_dir) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(e) => {
+                        return Err(CliError::io(format!("Failed to read sessions directory {:?}: {}", sessions_dir, e)).with_source(e))
+                    }
+                };
+
+                let mut session_ids = Vec::new();
+
+                for entry in entries {
+                    let entry = entry
+                        .map_err(|e| CliError::io(format!("Failed to read sessions directory {:?}: {}", sessions_dir, e)).with_source(e))?;
+
+                    if entry.path().is_dir() {
+                        session_ids.extend(entry.file_name().to_str().map(str::to_string));
+                    }
+                }
+
+                session_ids.sort();
+
+                Ok(session_ids)
+            }
+            Backend::Sqlite(path) => SqliteStorage::open(path, file_paths.clone())?.session_ids(),
+        }
+    }
+}
+
+fn artifact_path(file_paths: &FilePaths, artifact: Artifact) -> PathBuf {
+    match artifact {
+        Artifact::ContributorSecretKey(participant) => file_paths.contributor_secret_key(&participant),
+        Artifact::Recipients => file_paths.recipients(),
+        Artifact::GenerationOutput(participant) => file_paths.generation_output(&participant),
+        Artifact::SigningShare(participant) => file_paths.signing_share(&participant),
+        Artifact::ThresholdPublicKey => file_paths.threshold_public_key(),
+        Artifact::DkgCertificate => file_paths.dkg_certificate(),
+        Artifact::SigningNonce(participant) => file_paths.signing_nonce(&participant),
+        Artifact::PrecommittedNonces(participant) => file_paths.precommitted_nonces(&participant),
+        Artifact::SigningRequest => file_paths.signing_request(),
+        Artifact::ThresholdSignature => file_paths.threshold_signature(),
+        Artifact::ExtrinsicInfo => file_paths.extrinsic_info(),
+        Artifact::SignedMessage => file_paths.signed_message(),
+        Artifact::SessionManifest => file_paths.session_manifest(),
+        Artifact::Collection(Collection::AllMessages) => file_paths.all_messages(),
+        Artifact::Collection(Collection::SigningCommitments) => file_paths.signing_commitments(),
+        Artifact::Collection(Collection::SigningPackages) => file_paths.signing_packages(),
+        Artifact::Collection(Collection::GenerationOutputs) => file_paths.generation_outputs(),
+        Artifact::Collection(Collection::RefreshMessages) => file_paths.refresh_messages(),
+        Artifact::Collection(Collection::ReshareMessages) => file_paths.reshare_messages(),
+        Artifact::Collection(Collection::Precommitments) => file_paths.precommitments(),
+    }
+}
+
+/// Where older versions kept an artifact of a participant, by its position among the recipients
+fn legacy_artifact_path(file_paths: &FilePaths, artifact: Artifact) -> Option<PathBuf> {
+    match artifact {
+        Artifact::ContributorSecretKey(participant) => Some(file_paths.legacy_contributor_secret_key(&participant)),
+        Artifact::GenerationOutput(participant) => Some(file_paths.legacy_generation_output(&participant)),
+        Artifact::SigningShare(participant) => Some(file_paths.legacy_signing_share(&participant)),
+        Artifact::SigningNonce(participant) => Some(file_paths.legacy_signing_nonce(&participant)),
+        _ => None,
+    }
+}
+
+/// Artifacts kept as files, in the layout given by [`FilePaths`]
+pub struct FileStorage {
+    file_paths: FilePaths,
+}
+
+impl FileStorage {
+    pub fn new(file_paths: FilePaths) -> Self {
+        Self { file_paths }
+    }
+
+    /// The file of an artifact: the one named by account, unless only a legacy file named by
+    /// position exists, which then keeps being used
+    pub fn path(&self, artifact: Artifact) -> PathBuf {
+        let path = artifact_path(&self.file_paths, artifact);
+
+        match legacy_artifact_path(&self.file_paths, artifact) {
+            Some(legacy_path) if !path.exists() && legacy_path.exists() => legacy_path,
+            _ => path,
+        }
+    }
+
+    /// Runs `f` on the path of an artifact while holding an exclusive lock on it.
+    ///
+    /// The lock is taken on a separate `.lock` file next to the artifact, since writes replace the
+    /// artifact file itself. It is shared by every process using the same files, and released
+    /// when the lock file is closed, even if the process dies.
+    fn locked<T>(&self, artifact: Artifact, f: impl FnOnce(&Path) -> Result<T, CliError>) -> Result<T, CliError> {
+        let path = self.path(artifact);
+        let lock_path = path.with_extension("lock");
+
+        let lock_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(|e| CliError::io(format!("Failed to open lock file {:?}: {}", lock_path, e)).with_source(e))?;
+
+        lock_file
+            .lock_exclusive()
+            .map_err(|e| CliError::io(format!("Failed to lock {} file {:?}: {}", artifact, path, e)).with_source(e))?;
+
+        f(&path)
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, artifact: Artifact) -> Result<Option<Vec<u8>>, CliError> {
+        read_file(artifact, &self.path(artifact))
+    }
+
+    fn put(&self, artifact: Artifact, content: &[u8]) -> Result<(), CliError> {
+        self.locked(artifact, |path| write_file(path, content, artifact.is_secret()))
+    }
+
+    fn delete(&self, artifact: Artifact) -> Result<(), CliError> {
+        self.locked(artifact, |path| match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(CliError::io(format!("Failed to delete {} file {:?}: {}", artifact, path, e)).with_source(e))
+            }
+            _ => Ok(()),
+        })
+    }
+
+    fn location(&self, artifact: Artifact) -> String {
+        format!("{:?}", self.path(artifact))
+    }
+
+    fn stored(&self) -> Result<Vec<String>, CliError> {
+        let session_dir = self.file_paths.session();
+
+        let entries = match fs::read_dir(session_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(CliError::io(format!("Failed to read directory {:?}: {}", session_dir, e)).with_source(e)),
+        };
+
+        let mut names = Vec::new();
+
+        for entry in entries {
+            let entry = entry.map_err(|e| CliError::io(format!("Failed to read directory {:?}: {}", session_dir, e)).with_source(e))?;
+            let path = entry.path();
+
+            // Lock files and temporary files are not artifacts
+            let is_artifact = path.is_file() && !path.extension().is_some_and(|extension| extension == "lock" || extension == "tmp");
+
+            if is_artifact {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    fn update(&self, artifact: Artifact, update: &mut dyn FnMut(Option<&[u8]>) -> Result<Option<Vec<u8>>, CliError>) -> Result<(), CliError> {
+        // Hold the lock across the read and the write, so concurrent updates never lose changes
+        self.locked(artifact, |path| {
+            let content = read_file(artifact, path)?;
+
+            let Some(new_content) = update(content.as_deref())? else {
+                return Ok(());
+            };
+
+            // A replaced secret is wiped through a handle on the old file, once the new content is
+            // in place, so readers never find the artifact missing or zeroed
+            let old_file = match content {
+                Some(content) if artifact.is_secret() => Some((open_for_wiping(artifact, path)?, content.len())),
+                _ => None,
+            };
+
+            write_file(path, &new_content, artifact.is_secret())?;
+
+            match old_file {
+                Some((mut old_file, length)) => zero_file(artifact, path, &mut old_file, length),
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn wipe(&self, artifact: Artifact) -> Result<(), CliError> {
+        self.locked(artifact, |path| wipe_file(artifact, path))
+    }
+}
+
+fn read_file(artifact: Artifact, path: &Path) -> Result<Option<Vec<u8>>, CliError> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(CliError::io(format!("Failed to read {} file {:?}: {}", artifact, path, e)).with_source(e)),
+    }
+}
+
+/// Overwrites a file in place with zeros
+fn wipe_file(artifact: Artifact, path: &Path) -> Result<(), CliError> {
+    let length = match fs::metadata(path) {
+        Ok(metadata) => metadata.len() as usize,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(CliError::io(format!("Failed to read {} file {:?}: {}", artifact, path, e)).with_source(e)),
+    };
+
+    // Overwrite the file in place, rather than replacing it, so the old content does not survive in the old inode
+    zero_file(artifact, path, &mut open_for_wiping(artifact, path)?, length)
+}
+
+fn open_for_wiping(artifact: Artifact, path: &Path) -> Result<File, CliError> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| CliError::io(format!("Failed to open {} file {:?} for wiping: {}", artifact, path, e)).with_source(e))
+}
+
+/// Overwrites the first `length` bytes of an open file with zeros
+fn zero_file(artifact: Artifact, path: &Path, file: &mut File, length: usize) -> Result<(), CliError> {
+    file.write_all(&vec![0u8; length])
+        .map_err(|e| CliError::io(format!("Failed to wipe {} file {:?}: {}", artifact, path, e)).with_source(e))?;
+
+    file.sync_all()
+        .map_err(|e| CliError::io(format!("Failed to flush wiped {} file {:?}: {}", artifact, path, e)).with_source(e))
+}
+
+/// Replaces the content of a file atomically: readers see either the old or the new content,
+/// and a crash never leaves a half-written file.
+pub fn write_file(path: &Path, content: &[u8], private: bool) -> Result<(), CliError> {
+    // Write next to the target and rename over it. The temporary name is unique, so writers that
+    // do not share a lock never write into each other's temporary file.
+    let mut suffix = [0u8; 8];
+    OsRng.fill_bytes(&mut suffix);
+    let temp_path = path.with_extension(format!("{}.tmp", hex::encode(suffix)));
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(if private { 0o600 } else { 0o666 });
+
+    let mut temp_file = options
+        .open(&temp_path)
+        .map_err(|e| CliError::io(format!("Failed to create temporary file {:?}: {}", temp_path, e)).with_source(e))?;
+
+    let written = temp_file
+        .write_all(content)
+        .and_then(|_| temp_file.sync_all())
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    written.map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        CliError::io(format!("Failed to write {:?}: {}", path, e)).with_source(e)
+    })
+}
+
+/// Artifacts kept in memory, lost when dropped
+#[derive(Default)]
+pub struct MemoryStorage {
+    artifacts: Mutex<HashMap<Artifact, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, artifact: Artifact) -> Result<Option<Vec<u8>>, CliError> {
+        Ok(self.artifacts.lock().expect("memory storage lock poisoned").get(&artifact).cloned())
+    }
+
+    fn put(&self, artifact: Artifact, content: &[u8]) -> Result<(), CliError> {
+        self.artifacts.lock().expect("memory storage lock poisoned").insert(artifact, content.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, artifact: Artifact) -> Result<(), CliError> {
+        self.artifacts.lock().expect("memory storage lock poisoned").remove(&artifact);
+        Ok(())
+    }
+
+    fn location(&self, artifact: Artifact) -> String {
+        format!("memory ({})", artifact)
+    }
+
+    fn stored(&self) -> Result<Vec<String>, CliError> {
+        let mut names: Vec<String> = self
+            .artifacts
+            .lock()
+            .expect("memory storage lock poisoned")
+            .keys()
+            .map(Artifact::to_string)
+            .collect();
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    fn update(&self, artifact: Artifact, update: &mut dyn FnMut(Option<&[u8]>) -> Result<Option<Vec<u8>>, CliError>) -> Result<(), CliError> {
+        // Hold the lock across the read and the write, so concurrent updates never lose changes
+        let mut artifacts = self.artifacts.lock().expect("memory storage lock poisoned");
+
+        let new_content = update(artifacts.get(&artifact).map(Vec::as_slice))?;
+
+        if let Some(new_content) = new_content {
+            artifacts.insert(artifact, new_content);
+        }
+
+        Ok(())
+    }
+}
+
+/// Artifacts kept in one SQLite database, keyed by the path [`FileStorage`] would use, relative
+/// to the base directory. One database can hold the artifacts of every session.
+pub struct SqliteStorage {
+    path: PathBuf,
+    file_paths: FilePaths,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path, file_paths: FilePaths) -> Result<Self, CliError> {
+        let connection = Connection::open(path)
+            .map_err(|e| CliError::io(format!("Failed to open SQLite database {:?}: {}", path, e)).with_source(e))?;
+
+        // Deleted and overwritten content is zeroed in the database file, not just unlinked
+        connection
+            .execute_batch(
+                "PRAGMA secure_delete = ON;
+                 PRAGMA busy_timeout = 5000;
+                 CREATE TABLE IF NOT EXISTS artifacts (key TEXT PRIMARY KEY, content BLOB NOT NULL);",
+            )
+            .map_err(|e| CliError::io(format!("Failed to initialize SQLite database {:?}: {}", path, e)).with_source(e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file_paths,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// The key of an artifact: the one named by account, unless only a legacy key named by
+    /// position exists, which then keeps being used
+    fn key(&self, artifact: Artifact) -> String {
+        let key = self.relative_key(&artifact_path(&self.file_paths, artifact));
+
+        let Some(legacy_path) = legacy_artifact_path(&self.file_paths, artifact) else {
+            return key;
+        };
+        let legacy_key = self.relative_key(&legacy_path);
+
+        if !self.contains(&key) && self.contains(&legacy_key) {
+            legacy_key
+        } else {
+            key
+        }
+    }
+
+    fn relative_key(&self, path: &Path) -> String {
+        path.strip_prefix(self.file_paths.base())
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.connection()
+            .query_row("SELECT 1 FROM artifacts WHERE key = ?1", params![key], |_| Ok(()))
+            .optional()
+            .is_ok_and(|row| row.is_some())
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().expect("SQLite storage lock poisoned")
+    }
+
+    /// Every key in the database, sorted
+    fn keys(&self) -> Result<Vec<String>, CliError> {
+        let io_error = |e: rusqlite::Error| CliError::io(format!("Failed to list artifacts in {:?}: {}", self.path, e)).with_source(e);
+
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT key FROM artifacts ORDER BY key").map_err(io_error)?;
+        let keys = statement
+            .query_map([], |row| row.get(0))
+            .map_err(io_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(io_error)?;
+
+        Ok(keys)
+    }
+
+    /// The IDs of the sessions with a manifest in the database, sorted
+    fn session_ids(&self) -> Result<Vec<String>, CliError> {
+        let sessions_key = self.relative_key(&self.file_paths.sessions());
+
+        Ok(self
+            .keys()?
+            .iter()
+            .filter_map(|key| {
+                let manifest_path = Path::new(key);
+                let session_dir = manifest_path.parent()?;
+
+                (manifest_path.file_name()? == "session.json" && session_dir.parent()? == Path::new(&sessions_key))
+                    .then(|| session_dir.file_name()?.to_str().map(str::to_string))
+                    .flatten()
+            })
+            .collect())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, artifact: Artifact) -> Result<Option<Vec<u8>>, CliError> {
+        // The key is looked up before taking the connection, which looking it up needs too
+        let key = self.key(artifact);
+
+        self.connection()
+            .query_row("SELECT content FROM artifacts WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(|e| CliError::io(format!("Failed to read {} from {:?}: {}", artifact, self.path, e)).with_source(e))
+    }
+
+    fn put(&self, artifact: Artifact, content: &[u8]) -> Result<(), CliError> {
+        let key = self.key(artifact);
+
+        self.connection()
+            .execute(
+                "INSERT INTO artifacts (key, content) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET content = excluded.content",
+                params![key, content],
+            )
+            .map(|_| ())
+            .map_err(|e| CliError::io(format!("Failed to write {} to {:?}: {}", artifact, self.path, e)).with_source(e))
+    }
+
+    fn delete(&self, artifact: Artifact) -> Result<(), CliError> {
+        let key = self.key(artifact);
+
+        self.connection()
+            .execute("DELETE FROM artifacts WHERE key = ?1", params![key])
+            .map(|_| ())
+            .map_err(|e| CliError::io(format!("Failed to delete {} from {:?}: {}", artifact, self.path, e)).with_source(e))
+    }
+
+    fn location(&self, artifact: Artifact) -> String {
+        format!("{:?} ({})", self.path, self.key(artifact))
+    }
+
+    fn stored(&self) -> Result<Vec<String>, CliError> {
+        let session_key = self.relative_key(self.file_paths.session());
+
+        Ok(self
+            .keys()?
+            .iter()
+            .filter(|key| Path::new(key).parent() == Some(Path::new(&session_key)))
+            .filter_map(|key| Path::new(key).file_name()?.to_str().map(str::to_string))
+            .collect())
+    }
+
+    fn update(&self, artifact: Artifact, update: &mut dyn FnMut(Option<&[u8]>) -> Result<Option<Vec<u8>>, CliError>) -> Result<(), CliError> {
+        let key = self.key(artifact);
+        let io_error = |e: rusqlite::Error| CliError::io(format!("Failed to update {} in {:?}: {}", artifact, self.path, e)).with_source(e);
+
+        let mut connection = self.connection();
+
+        // An immediate transaction takes the write lock up front, so writers from other processes queue up
+        let transaction = connection
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(io_error)?;
+
+        let content: Option<Vec<u8>> = transaction
+            .query_row("SELECT content FROM artifacts WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(io_error)?;
+
+        // Dropping the transaction without committing rolls it back
+        let Some(new_content) = update(content.as_deref())? else {
+            return Ok(());
+        };
+
+        transaction
+            .execute(
+                "INSERT INTO artifacts (key, content) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET content = excluded.content",
+                params![key, new_content],
+            )
+            .map_err(io_error)?;
+
+        transaction.commit().map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const PARTICIPANT: Participant = Participant { index: 1, account: [1u8; 32] };
+
+    /// A backend under test, over its own empty directory, which is removed when it is dropped
+    struct TestBackend {
+        name: &'static str,
+        /// Opens the storage again, as another process would. Memory storage is shared instead.
+        open: Box<dyn Fn() -> Arc<dyn Storage> + Send + Sync>,
+        _dir: TempDir,
+    }
+
+    fn backends() -> Vec<TestBackend> {
+        let memory = Arc::new(MemoryStorage::new());
+
+        let files_dir = TempDir::new().unwrap();
+        let files_paths = FilePaths::new(files_dir.path());
+
+        let sqlite_dir = TempDir::new().unwrap();
+        let sqlite_paths = FilePaths::new(sqlite_dir.path());
+        let sqlite_path = sqlite_dir.path().join("artifacts.db");
+
+        vec![
+            TestBackend {
+                name: "memory",
+                open: Box::new(move || memory.clone() as Arc<dyn Storage>),
+                _dir: TempDir::new().unwrap(),
+            },
+            TestBackend {
+                name: "files",
+                open: Box::new(move || Arc::new(FileStorage::new(files_paths.clone())) as Arc<dyn Storage>),
+                _dir: files_dir,
+            },
+            TestBackend {
+                name: "sqlite",
+                open: Box::new(move || Arc::new(SqliteStorage::open(&sqlite_path, sqlite_paths.clone()).unwrap()) as Arc<dyn Storage>),
+                _dir: sqlite_dir,
+            },
+        ]
+    }
+
+    fn entry(sender: u8, message: &[u8]) -> Entry {
+        Entry::new(&[sender; 32], message.to_vec())
+    }
+
+    #[test]
+    fn get_returns_what_was_put() {
+        for backend in backends() {
+            let storage = (backend.open)();
+
+            assert_eq!(storage.get(Artifact::Recipients).unwrap(), None);
+
+            storage.put(Artifact::Recipients, b"first").unwrap();
+            assert_eq!(storage.get(Artifact::Recipients).unwrap().as_deref(), Some(&b"first"[..]));
+
+            storage.put(Artifact::Recipients, b"second").unwrap();
+            assert_eq!(storage.get(Artifact::Recipients).unwrap().as_deref(), Some(&b"second"[..]));
+
+            storage.put(Artifact::SigningShare(PARTICIPANT), b"share").unwrap();
+            assert_eq!(storage.get(Artifact::SigningShare(PARTICIPANT)).unwrap().as_deref(), Some(&b"share"[..]));
+            assert_eq!(storage.get(Artifact::Recipients).unwrap().as_deref(), Some(&b"second"[..]));
+        }
+    }
+
+    #[test]
+    fn delete_removes_an_artifact_and_tolerates_missing_ones() {
+        for backend in backends() {
+            let storage = (backend.open)();
+
+            storage.delete(Artifact::ThresholdPublicKey).unwrap();
+
+            storage.put(Artifact::ThresholdPublicKey, b"key").unwrap();
+            storage.delete(Artifact::ThresholdPublicKey).unwrap();
+            assert_eq!(storage.get(Artifact::ThresholdPublicKey).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn list_fails_until_something_is_published() {
+        for backend in backends() {
+            let storage = (backend.open)();
+
+            assert!(matches!(storage.list(Collection::AllMessages), Err(CliError::Io { .. })));
+            assert!(storage.entries(Collection::AllMessages).unwrap().is_empty());
+
+            storage.publish(Collection::AllMessages, entry(1, b"message")).unwrap();
+            assert_eq!(storage.list(Collection::AllMessages).unwrap(), vec![entry(1, b"message")]);
+        }
+    }
+
+    #[test]
+    fn publish_adds_replaces_and_keeps_the_order() {
+        for backend in backends() {
+            let storage = (backend.open)();
+
+            assert_eq!(storage.publish(Collection::SigningCommitments, entry(1, b"a")).unwrap(), Published::Added);
+            assert_eq!(storage.publish(Collection::SigningCommitments, entry(2, b"b")).unwrap(), Published::Added);
+            assert_eq!(storage.publish(Collection::SigningCommitments, entry(1, b"a")).unwrap(), Published::Unchanged);
+            assert_eq!(storage.publish(Collection::SigningCommitments, entry(1, b"c")).unwrap(), Published::Replaced);
+
+            assert_eq!(storage.list(Collection::SigningCommitments).unwrap(), vec![entry(1, b"c"), entry(2, b"b")]);
+            assert!(storage.entries(Collection::SigningPackages).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn wipe_zeroes_the_content() {
+        for backend in backends() {
+            let storage = (backend.open)();
+
+            storage.wipe(Artifact::SigningNonce(PARTICIPANT)).unwrap();
+            assert_eq!(storage.get(Artifact::SigningNonce(PARTICIPANT)).unwrap(), None);
+
+            storage.put(Artifact::SigningNonce(PARTICIPANT), b"nonce").unwrap();
+            storage.wipe(Artifact::SigningNonce(PARTICIPANT)).unwrap();
+            assert_eq!(storage.get(Artifact::SigningNonce(PARTICIPANT)).unwrap(), Some(vec![0u8; 5]));
+        }
+    }
+
+    #[test]
+    fn update_replaces_or_keeps_the_content() {
+        for backend in backends() {
+            let storage = (backend.open)();
+
+            storage.update(Artifact::Recipients, &mut |content| {
+                assert_eq!(content, None);
+                Ok(Some(b"first".to_vec()))
+            }).unwrap();
+
+            storage.update(Artifact::Recipients, &mut |content| {
+                assert_eq!(content, Some(&b"first"[..]));
+                Ok(None)
+            }).unwrap();
+
+            let failed = storage.update(Artifact::Recipients, &mut |_| Err(CliError::input("refused")));
+            assert!(matches!(failed, Err(CliError::Input { .. })));
+
+            assert_eq!(storage.get(Artifact::Recipients).unwrap().as_deref(), Some(&b"first"[..]), "{}", backend.name);
+        }
+    }
+
+    #[test]
+    fn concurrent_publishing_keeps_every_entry() {
+        const TASKS: u8 = 16;
+        const ENTRIES_PER_TASK: u8 = 8;
+
+        for backend in backends() {
+            std::thread::scope(|scope| {
+                for task in 0..TASKS {
+                    let open = &backend.open;
+
+                    scope.spawn(move || {
+                        let storage = open();
+
+                        for index in 0..ENTRIES_PER_TASK {
+                            let sender = task * ENTRIES_PER_TASK + index;
+                            storage.publish(Collection::AllMessages, entry(sender, &[sender])).unwrap();
+                        }
+                    });
+                }
+            });
+
+            let entries = (backend.open)().list(Collection::AllMessages).unwrap();
+
+            assert_eq!(entries.len(), TASKS as usize * ENTRIES_PER_TASK as usize, "{} lost entries", backend.name);
+            for sender in 0..TASKS * ENTRIES_PER_TASK {
+                assert!(entries.contains(&entry(sender, &[sender])), "{} lost the entry of {}", backend.name, sender);
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_updates_are_never_lost() {
+        const TASKS: u32 = 16;
+        const UPDATES_PER_TASK: u32 = 8;
+
+        for backend in backends() {
+            std::thread::scope(|scope| {
+                for _ in 0..TASKS {
+                    let open = &backend.open;
+
+                    scope.spawn(move || {
+                        let storage = open();
+
+                        for _ in 0..UPDATES_PER_TASK {
+                            storage.update(Artifact::PrecommittedNonces(PARTICIPANT), &mut |content| {
+                                let count = content.map_or(0, |content| u32::from_le_bytes(content.try_into().unwrap()));
+                                Ok(Some((count + 1).to_le_bytes().to_vec()))
+                            }).unwrap();
+                        }
+                    });
+                }
+            });
+
+            let content = (backend.open)().get(Artifact::PrecommittedNonces(PARTICIPANT)).unwrap().unwrap();
+
+            assert_eq!(u32::from_le_bytes(content.try_into().unwrap()), TASKS * UPDATES_PER_TASK, "{} lost updates", backend.name);
+        }
+    }
+}